@@ -1,2 +0,0 @@
-INSERT INTO testing.data(some_data)
-VALUES ($1);
\ No newline at end of file
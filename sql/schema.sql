@@ -1,6 +0,0 @@
-DROP SCHEMA IF EXISTS testing CASCADE;
-CREATE SCHEMA testing;
-
-CREATE TABLE testing.data (
-    some_data VARCHAR UNIQUE NOT NULL
-);
\ No newline at end of file
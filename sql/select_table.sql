@@ -1 +0,0 @@
-SELECT (some_data) FROM testing.data;
\ No newline at end of file
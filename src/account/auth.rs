@@ -0,0 +1,347 @@
+//! Authentication and authorization of requests.
+//!
+//! Handlers which need to know the caller take [AuthenticatedUser] as an argument;
+//! requests without valid session are then rejected with HTTP 401 before handler
+//! is run. Whole scopes are restricted to a role by wrapping them in [RoleGuard]:
+//!
+//! ```ignore
+//! web::scope("/users").wrap(RoleGuard::new(Role::Admin))
+//! ```
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{error, web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+use futures::future::{ready, LocalBoxFuture, Ready};
+
+use super::session::{self, SessionToken};
+use super::{Role, UserId};
+use crate::config::Config;
+
+/// User owning session of the request. Session token is taken from the session
+/// cookie or from `Authorization: Bearer <token>` header, for clients without
+/// cookies. Successful authentication renews the session.
+#[derive(Clone, Copy, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: UserId,
+    pub role: Role,
+}
+
+impl AuthenticatedUser {
+    /// Whether the user may see tickets of 'owner_id'.
+    pub fn can_see_tickets_of(&self, owner_id: UserId) -> bool {
+        self.user_id == owner_id || self.role.sees_all_tickets()
+    }
+}
+
+/// Reads session token from the request, cookie takes precedence over header.
+fn session_token(req: &HttpRequest) -> Option<SessionToken> {
+    if let Some(token) = SessionToken::from_request(req) {
+        return Some(token);
+    }
+
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?.trim();
+
+    Some(SessionToken::from(token))
+}
+
+/// Resolves user of the request. User authenticated earlier by [RoleGuard] is
+/// reused, so session is checked once per request.
+async fn authenticate(req: HttpRequest) -> Result<AuthenticatedUser, actix_web::Error> {
+    if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+        return Ok(*user);
+    }
+
+    let token = session_token(&req).ok_or_else(|| error::ErrorUnauthorized("Missing session."))?;
+
+    let (db, config) = match (
+        req.app_data::<web::Data<Pool>>(),
+        req.app_data::<web::Data<Config>>(),
+    ) {
+        (Some(db), Some(config)) => (db.clone(), config.clone()),
+        _ => {
+            error!("Database pool or configuration is not registered as app data.");
+            return Err(error::ErrorInternalServerError(""));
+        }
+    };
+
+    match session::authenticate(&db, &token, &config.session).await {
+        Ok(Some((user_id, role))) => {
+            let user = AuthenticatedUser { user_id, role };
+            req.extensions_mut().insert(user);
+            Ok(user)
+        }
+        Ok(None) => Err(error::ErrorUnauthorized("Session is invalid or expired.")),
+        Err(_) => Err(error::ErrorServiceUnavailable("")),
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        Box::pin(authenticate(req.clone()))
+    }
+}
+
+/// Middleware letting through only requests of users having at least 'min_role'.
+/// Others get HTTP 401 (no valid session) or HTTP 403 (insufficient role).
+#[derive(Clone, Copy)]
+pub struct RoleGuard {
+    min_role: Role,
+}
+
+impl RoleGuard {
+    pub fn new(min_role: Role) -> RoleGuard {
+        RoleGuard { min_role }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RoleGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RoleGuardMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RoleGuardMiddleware {
+            service: Rc::new(service),
+            min_role: self.min_role,
+        }))
+    }
+}
+
+pub struct RoleGuardMiddleware<S> {
+    service: Rc<S>,
+    min_role: Role,
+}
+
+impl<S, B> Service<ServiceRequest> for RoleGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let min_role = self.min_role;
+
+        Box::pin(async move {
+            let user = match authenticate(req.request().clone()).await {
+                Ok(user) => user,
+                Err(error) => return Ok(req.error_response(error).map_into_right_body()),
+            };
+
+            if !user.role.includes(min_role) {
+                let response = HttpResponse::Forbidden().finish();
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::Cookie;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{dev::Service as _, App};
+
+    use super::*;
+    use crate::migrations::test_database;
+
+    const STUDENT: AuthenticatedUser = AuthenticatedUser {
+        user_id: 1,
+        role: Role::Student,
+    };
+
+    async fn extract(req: &HttpRequest) -> Result<AuthenticatedUser, StatusCode> {
+        AuthenticatedUser::extract(req)
+            .await
+            .map_err(|error| error.as_response_error().status_code())
+    }
+
+    #[test]
+    fn token_is_read_from_cookie_before_header() {
+        let req = TestRequest::default()
+            .cookie(Cookie::new(session::SESSION_COOKIE, "cookie"))
+            .insert_header((AUTHORIZATION, "Bearer header"))
+            .to_http_request();
+        let header_only = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer header "))
+            .to_http_request();
+        let basic = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Basic header"))
+            .to_http_request();
+
+        assert_eq!(
+            session_token(&req).unwrap().hash(),
+            SessionToken::from("cookie").hash()
+        );
+        assert_eq!(
+            session_token(&header_only).unwrap().hash(),
+            SessionToken::from("header").hash()
+        );
+        assert!(session_token(&basic).is_none());
+    }
+
+    #[test]
+    fn users_see_only_their_tickets_unless_teachers() {
+        let teacher = AuthenticatedUser {
+            user_id: 2,
+            role: Role::Teacher,
+        };
+
+        assert!(STUDENT.can_see_tickets_of(1));
+        assert!(!STUDENT.can_see_tickets_of(2));
+        assert!(teacher.can_see_tickets_of(1));
+    }
+
+    #[actix_web::test]
+    async fn request_without_session_is_unauthorized() {
+        let req = TestRequest::default().to_http_request();
+
+        assert_eq!(extract(&req).await.unwrap_err(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn missing_app_data_is_internal_error() {
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer abc"))
+            .to_http_request();
+
+        assert_eq!(
+            extract(&req).await.unwrap_err(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[actix_web::test]
+    async fn user_authenticated_earlier_is_reused() {
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(STUDENT);
+
+        let user = extract(&req).await.unwrap();
+
+        assert_eq!((user.user_id, user.role), (1, Role::Student));
+    }
+
+    #[actix_web::test]
+    async fn session_resolves_its_owner() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let config = Config::default();
+        let user_id = rand::random::<u32>() as UserId;
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "INSERT INTO user_data.users (id, username, password_hash, user_salt, email, role)
+                VALUES ($1, $2, '', '', '', 'Teacher')",
+                &[&user_id, &format!("auth-test-{user_id}")],
+            )
+            .await
+            .unwrap();
+        let token = session::create_session(&db, user_id, &config.session)
+            .await
+            .unwrap();
+
+        let request = |token: &str| {
+            TestRequest::default()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(config.clone()))
+                .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+                .to_http_request()
+        };
+        let req = request(token.cookie(&config.session).value());
+
+        let user = extract(&req).await.unwrap();
+
+        assert_eq!((user.user_id, user.role), (user_id, Role::Teacher));
+        assert!(req.extensions().get::<AuthenticatedUser>().is_some());
+        assert_eq!(
+            extract(&request("invalid")).await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    /// Status of request to scope guarded by 'min_role', sent by 'user' or
+    /// without session when there is none.
+    async fn guarded_status(min_role: Role, user: Option<AuthenticatedUser>) -> StatusCode {
+        let app = test::init_service(
+            App::new()
+                .service(
+                    web::scope("/guarded")
+                        .wrap(RoleGuard::new(min_role))
+                        .route("", web::get().to(HttpResponse::Ok)),
+                )
+                // Outermost, so user is known before guard runs.
+                .wrap_fn(move |req, srv| {
+                    if let Some(user) = user {
+                        req.extensions_mut().insert(user);
+                    }
+                    srv.call(req)
+                }),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/guarded").to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    fn user(role: Role) -> Option<AuthenticatedUser> {
+        Some(AuthenticatedUser { user_id: 1, role })
+    }
+
+    #[actix_web::test]
+    async fn guard_rejects_request_without_session() {
+        assert_eq!(
+            guarded_status(Role::Student, None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn guard_forbids_lower_roles() {
+        assert_eq!(
+            guarded_status(Role::Teacher, user(Role::Student)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            guarded_status(Role::Admin, user(Role::Teacher)).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_web::test]
+    async fn guard_lets_through_same_and_higher_roles() {
+        assert_eq!(
+            guarded_status(Role::Teacher, user(Role::Teacher)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            guarded_status(Role::Student, user(Role::Admin)).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,2 @@
+DELETE FROM user_data.sessions
+WHERE expires_at <= now() OR last_seen <= now() - make_interval(secs => $1);
\ No newline at end of file
@@ -0,0 +1,2 @@
+DELETE FROM user_data.sessions
+WHERE token_hash = $1;
\ No newline at end of file
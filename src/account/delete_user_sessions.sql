@@ -0,0 +1,2 @@
+DELETE FROM user_data.sessions
+WHERE user_id = $1;
\ No newline at end of file
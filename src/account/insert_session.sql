@@ -0,0 +1,2 @@
+INSERT INTO user_data.sessions (token_hash, user_id, created_at, last_seen, expires_at)
+VALUES ($1, $2, now(), now(), now() + make_interval(secs => $3));
\ No newline at end of file
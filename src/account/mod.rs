@@ -1,10 +1,20 @@
-use actix_web::{cookie::CookieBuilder, web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 
 use deadpool_postgres::{Object, Pool};
 use serde::{Deserialize, Serialize};
 
+use crate::config::Config;
 use crate::crypto::{Encryptor, Hasher};
 
+mod auth;
+pub mod reencrypt;
+mod role;
+pub mod session;
+
+pub use auth::{AuthenticatedUser, RoleGuard};
+pub use role::Role;
+use session::SessionToken;
+
 pub type UserId = i64;
 
 // FIXME: Security error - username needs to be validated before sql query.
@@ -103,13 +113,7 @@ pub async fn create_account(
     let mut password_hash = [0u8; crate::HASH_LENGTH_BYTES];
     let user_salt = crate::crypto::generate_salt();
 
-    let nonce: Vec<u8> = user_salt
-        .iter()
-        .take(crate::ENCRYPT_NONCE_LEN)
-        .map(|x| x.to_owned())
-        .collect();
-
-    let email_enc = match encryptor.encrypt(user_data.email.as_bytes(), &nonce) {
+    let email_enc = match encryptor.encrypt(user_data.email.as_bytes()) {
         Ok(v) => v,
         Err(e) => {
             error!("Error occured while encrypting email. ERROR = {e:?}");
@@ -189,7 +193,8 @@ async fn find_user_in_database(
     Ok((password_hash, user_salt, user_id))
 }
 
-/// Function tries to authenticate user with data in 'form'. Possible respones:
+/// Function tries to authenticate user with data in 'form'. On success new session
+/// is started and its token is set in cookie. Possible respones:
 ///     HTTP 202 => User authenticated successfully.
 ///     HTTP 401 => Function with given data does not exist in database.
 ///     HTTP 503 => Server problem, try again later.
@@ -198,48 +203,156 @@ async fn login_into_account(
     db: web::Data<Pool>,
     form: web::Json<LoginForm>,
     hasher: web::Data<Hasher<'_>>,
-    encryptor: web::Data<Encryptor>,
+    config: web::Data<Config>,
 ) -> HttpResponse {
     if form.username.len() > crate::MAX_USERNAME_LENGTH {
         return HttpResponse::UnprocessableEntity().body("Username is too long.");
     }
 
-    let (password_hash, user_salt, user_id) = match find_user_in_database(db, &form.username).await
-    {
-        Ok(res) => res,
-        Err(e) => return e,
-    };
+    let (password_hash, user_salt, user_id) =
+        match find_user_in_database(db.clone(), &form.username).await {
+            Ok(res) => res,
+            Err(e) => return e,
+        };
 
     match hasher.password_matches(&form.password, &password_hash, &user_salt) {
         Err(_) => HttpResponse::ServiceUnavailable().finish(),
         Ok(false) => HttpResponse::Unauthorized().finish(),
-        Ok(true) => {
-            use actix_web::cookie::Expiration;
-
-            let token = encryptor
-                .encrypt(form.username.as_bytes(), &user_salt)
-                .unwrap();
-
-            // TODO: Expiration time and its renewal
-            let cookie_at = CookieBuilder::new("auth_token", crate::crypto::encode_hex(&token))
-                .secure(true)
-                .expires(Expiration::Session)
-                .finish();
-
-            let token_id = encryptor
-                .encrypt(&user_id.to_ne_bytes(), &crate::crypto::id_salt())
-                .unwrap();
-
-            let cookie_id =
-                CookieBuilder::new("auth_token_id", crate::crypto::encode_hex(&token_id))
-                    .secure(true)
-                    .expires(Expiration::Session)
-                    .finish();
-
-            HttpResponse::Accepted()
-                .cookie(cookie_at)
-                .cookie(cookie_id)
-                .finish()
+        Ok(true) => match session::create_session(&db, user_id, &config.session).await {
+            Ok(token) => HttpResponse::Accepted()
+                .cookie(token.cookie(&config.session))
+                .finish(),
+            Err(_) => HttpResponse::ServiceUnavailable().finish(),
+        },
+    }
+}
+
+/// Ends session from the request cookie. Possible responses:
+///     HTTP 204 => Session ended (or there was none) and cookie was removed.
+///     HTTP 503 => Server problem, try again later.
+async fn logout(req: HttpRequest, db: web::Data<Pool>) -> HttpResponse {
+    if let Some(token) = SessionToken::from_request(&req) {
+        if session::delete_session(&db, &token).await.is_err() {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    }
+
+    HttpResponse::NoContent()
+        .cookie(session::removal_cookie())
+        .finish()
+}
+
+/// Ends all sessions of the user owning session from the request, logging them
+/// out on all devices. Possible responses:
+///     HTTP 204 => Sessions ended and cookie was removed.
+///     HTTP 401 => Request has no valid session.
+///     HTTP 503 => Server problem, try again later.
+async fn logout_all(user: AuthenticatedUser, db: web::Data<Pool>) -> HttpResponse {
+    match session::delete_user_sessions(&db, user.user_id).await {
+        Ok(_) => HttpResponse::NoContent()
+            .cookie(session::removal_cookie())
+            .finish(),
+        Err(_) => HttpResponse::ServiceUnavailable().finish(),
+    }
+}
+
+#[derive(Serialize)]
+struct UserSummary {
+    id: UserId,
+    username: String,
+    role: String,
+}
+
+/// Lists all users with their roles. Available to admins only.
+async fn list_users(db: web::Data<Pool>) -> HttpResponse {
+    let query_stmt = include_str!("query_users.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match client.query(query_stmt, &[]).await {
+        Ok(rows) => {
+            let users: Vec<UserSummary> = rows
+                .iter()
+                .map(|row| UserSummary {
+                    id: row.get(0),
+                    username: row.get(1),
+                    role: row.get(2),
+                })
+                .collect();
+
+            HttpResponse::Ok().json(users)
+        }
+        Err(error) => {
+            error!("Error occured while querying users. ERROR = {:?}", error);
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleForm {
+    role: Role,
+}
+
+/// Changes role of the user. Available to admins only. Possible responses:
+///     HTTP 204 => Role was changed.
+///     HTTP 404 => User does not exist.
+///     HTTP 503 => Server problem, try again later.
+async fn set_role(
+    db: web::Data<Pool>,
+    user_id: web::Path<UserId>,
+    form: web::Json<RoleForm>,
+) -> HttpResponse {
+    let update_stmt = include_str!("update_role.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match client
+        .execute(update_stmt, &[&*user_id, &form.role.to_string()])
+        .await
+    {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => {
+            info!(
+                "Role of user changed. UserId = {}, Role = {}",
+                user_id, form.role
+            );
+            HttpResponse::NoContent().finish()
+        }
+        Err(error) => {
+            error!("Error occured while changing role. ERROR = {:?}", error);
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+/// Changes role of the user with 'username', returns whether such user exists.
+/// Used by `alsit set-role` command to create the first admin.
+pub async fn set_role_by_username(db: &Pool, username: &str, role: Role) -> Result<bool, ()> {
+    let update_stmt = include_str!("update_role_by_username.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(());
+        }
+    };
+
+    match client
+        .execute(update_stmt, &[&username, &role.to_string()])
+        .await
+    {
+        Ok(updated) => Ok(updated > 0),
+        Err(error) => {
+            error!("Error occured while changing role. ERROR = {:?}", error);
+            Err(())
         }
     }
 }
@@ -248,4 +361,12 @@ async fn login_into_account(
 pub fn account_handler(cfg: &mut web::ServiceConfig) {
     cfg.route("/create", web::post().to(create_account));
     cfg.route("/login", web::post().to(login_into_account));
+    cfg.route("/logout", web::post().to(logout));
+    cfg.route("/logout-all", web::post().to(logout_all));
+    cfg.service(
+        web::scope("/users")
+            .wrap(RoleGuard::new(Role::Admin))
+            .route("", web::get().to(list_users))
+            .route("/{user_id}/role", web::put().to(set_role)),
+    );
 }
@@ -0,0 +1,5 @@
+SELECT id, user_salt, email
+FROM user_data.users
+WHERE $1::BIGINT IS NULL OR id > $1
+ORDER BY id
+LIMIT $2;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT id, username, role
+FROM user_data.users
+ORDER BY username;
\ No newline at end of file
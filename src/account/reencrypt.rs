@@ -0,0 +1,100 @@
+//! Re-encryption of stored data with the current encryption key, run after key
+//! rotation with `alsit reencrypt`. When no data is encrypted with a retired key
+//! anymore, it can be removed from the key file. It also moves data from older
+//! formats of ciphertext, with nonce derived from user salt, to the current one.
+use deadpool_postgres::Pool;
+
+use crate::crypto::Encryptor;
+
+/// Number of users read from the database at once.
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug)]
+pub enum ReencryptError {
+    DatabaseError,
+}
+
+/// Re-encrypts `user_data.users.email` of every user whose email is not encrypted
+/// with the current key and random nonce. Returns number of updated users. Emails
+/// which cannot be decrypted with any known key are logged and skipped.
+pub async fn reencrypt_emails(db: &Pool, encryptor: &Encryptor) -> Result<u64, ReencryptError> {
+    let query_stmt = include_str!("query_emails.sql");
+    let update_stmt = include_str!("update_email.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(ReencryptError::DatabaseError);
+        }
+    };
+
+    let mut last_id: Option<i64> = None;
+    let mut updated = 0;
+
+    loop {
+        let rows = match client.query(query_stmt, &[&last_id, &BATCH_SIZE]).await {
+            Ok(rows) => rows,
+            Err(error) => {
+                error!("Error occured while reading emails. ERROR = {:?}", error);
+                return Err(ReencryptError::DatabaseError);
+            }
+        };
+
+        let last_row = match rows.last() {
+            Some(row) => row,
+            None => break,
+        };
+        last_id = Some(last_row.get(0));
+
+        for row in &rows {
+            let user_id: i64 = row.get(0);
+            let user_salt: Vec<u8> = row.get(1);
+            let email: Vec<u8> = row.get(2);
+
+            let plaintext = match encryptor.decrypt_with_key_id(&email, Some(&user_salt)) {
+                Ok((_, Some(key_id))) if key_id == encryptor.current_key_id() => continue,
+                Ok((plaintext, _)) => plaintext,
+                Err(error) => {
+                    error!(
+                        "Unable to decrypt email. Skipping user. UserId = {}, ERROR = {:?}",
+                        user_id, error
+                    );
+                    continue;
+                }
+            };
+
+            let reencrypted = match encryptor.encrypt(&plaintext) {
+                Ok(reencrypted) => reencrypted,
+                Err(error) => {
+                    error!(
+                        "Unable to encrypt email. Skipping user. UserId = {}, ERROR = {:?}",
+                        user_id, error
+                    );
+                    continue;
+                }
+            };
+
+            // Email is updated only if it did not change in the meantime.
+            match client
+                .execute(update_stmt, &[&user_id, &reencrypted, &email])
+                .await
+            {
+                Ok(count) => updated += count,
+                Err(error) => {
+                    error!(
+                        "Error occured while updating email. UserId = {}, ERROR = {:?}",
+                        user_id, error
+                    );
+                    return Err(ReencryptError::DatabaseError);
+                }
+            }
+        }
+
+        if (rows.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    Ok(updated)
+}
@@ -0,0 +1,8 @@
+UPDATE user_data.sessions AS sessions
+SET last_seen = now()
+FROM user_data.users AS users
+WHERE sessions.token_hash = $1
+    AND users.id = sessions.user_id
+    AND sessions.expires_at > now()
+    AND sessions.last_seen > now() - make_interval(secs => $2)
+RETURNING sessions.user_id, users.role;
\ No newline at end of file
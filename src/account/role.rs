@@ -0,0 +1,87 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Role of the user, stored in `user_data.users.role`. Roles are ordered, every
+/// role has all permissions of the roles before it:
+///
+/// * Student - submits tickets and sees only their own ones.
+/// * Teacher - manages exercises and sees tickets of all users. There are no
+///   courses yet, so teachers are not limited to tickets of their students.
+/// * Admin - manages users and their roles.
+#[derive(Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default)]
+pub enum Role {
+    #[default]
+    Student,
+    Teacher,
+    Admin,
+}
+
+impl Role {
+    /// Whether user with this role has all permissions of 'required' role.
+    pub fn includes(&self, required: Role) -> bool {
+        *self >= required
+    }
+
+    /// Whether tickets of other users are visible to this role.
+    pub fn sees_all_tickets(&self) -> bool {
+        self.includes(Role::Teacher)
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_description = match self {
+            Role::Student => "Student",
+            Role::Teacher => "Teacher",
+            Role::Admin => "Admin",
+        };
+
+        write!(f, "{string_description}")
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Student" => Ok(Role::Student),
+            "Teacher" => Ok(Role::Teacher),
+            "Admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLES: [Role; 3] = [Role::Student, Role::Teacher, Role::Admin];
+
+    #[test]
+    fn roles_include_permissions_of_lower_ones() {
+        for (index, role) in ROLES.iter().enumerate() {
+            for (required_index, required) in ROLES.iter().enumerate() {
+                assert_eq!(role.includes(*required), index >= required_index);
+            }
+        }
+    }
+
+    #[test]
+    fn only_teachers_and_admins_see_all_tickets() {
+        assert!(!Role::Student.sees_all_tickets());
+        assert!(Role::Teacher.sees_all_tickets());
+        assert!(Role::Admin.sees_all_tickets());
+    }
+
+    #[test]
+    fn role_is_parsed_from_its_name() {
+        for role in ROLES {
+            assert_eq!(role.to_string().parse(), Ok(role));
+        }
+        assert_eq!("admin".parse::<Role>(), Err(()));
+        assert_eq!(Role::default(), Role::Student);
+    }
+}
@@ -0,0 +1,392 @@
+//! Server-side sessions stored in `user_data.sessions`.
+//!
+//! After login client gets random opaque token in [SESSION_COOKIE] cookie. Only
+//! SHA-256 of the token is stored, so leaked database does not reveal valid
+//! tokens. Session expires after `session.idle_timeout_secs` without activity or
+//! `session.max_lifetime_secs` after login; every successful authentication
+//! renews the idle timeout.
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::HttpRequest;
+use deadpool_postgres::{Object, Pool};
+use sha2::{Digest, Sha256};
+
+use super::{Role, UserId};
+use crate::config::SessionConfig;
+
+/// Name of the cookie carrying session token.
+pub const SESSION_COOKIE: &str = "session";
+
+/// Number of random bytes in session token.
+const SESSION_TOKEN_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum SessionError {
+    DatabaseError,
+}
+
+/// Token as it is sent to the client.
+pub struct SessionToken(String);
+
+impl SessionToken {
+    fn generate() -> SessionToken {
+        use rand::RngCore;
+
+        let mut token = [0u8; SESSION_TOKEN_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut token);
+
+        SessionToken(crate::crypto::encode_hex(&token))
+    }
+
+    /// Reads token from the session cookie of 'req'.
+    pub fn from_request(req: &HttpRequest) -> Option<SessionToken> {
+        req.cookie(SESSION_COOKIE)
+            .map(|cookie| SessionToken(String::from(cookie.value())))
+    }
+
+    pub(super) fn hash(&self) -> Vec<u8> {
+        Sha256::digest(self.0.as_bytes()).to_vec()
+    }
+
+    /// Cookie carrying the token. It is not available to scripts and is not sent
+    /// with cross-site requests.
+    pub fn cookie(&self, config: &SessionConfig) -> Cookie<'static> {
+        Cookie::build(SESSION_COOKIE, self.0.clone())
+            .path("/")
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .max_age(CookieDuration::seconds(config.max_lifetime_secs as i64))
+            .finish()
+    }
+}
+
+impl From<&str> for SessionToken {
+    fn from(token: &str) -> Self {
+        SessionToken(String::from(token))
+    }
+}
+
+/// Cookie which removes session cookie from the client.
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(SESSION_COOKIE, "")
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    cookie.make_removal();
+
+    cookie
+}
+
+async fn get_client(db: &Pool) -> Result<Object, SessionError> {
+    match db.get().await {
+        Ok(client) => Ok(client),
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            Err(SessionError::DatabaseError)
+        }
+    }
+}
+
+/// Starts new session of 'user_id'. Expired sessions are removed on the way.
+pub async fn create_session(
+    db: &Pool,
+    user_id: UserId,
+    config: &SessionConfig,
+) -> Result<SessionToken, SessionError> {
+    let cleanup_stmt = include_str!("delete_expired_sessions.sql");
+    let insert_stmt = include_str!("insert_session.sql");
+    let client = get_client(db).await?;
+
+    if let Err(error) = client
+        .execute(cleanup_stmt, &[&(config.idle_timeout_secs as f64)])
+        .await
+    {
+        warn!(
+            "Error occured while removing expired sessions. ERROR = {:?}",
+            error
+        );
+    }
+
+    let token = SessionToken::generate();
+
+    match client
+        .execute(
+            insert_stmt,
+            &[&token.hash(), &user_id, &(config.max_lifetime_secs as f64)],
+        )
+        .await
+    {
+        Ok(_) => Ok(token),
+        Err(error) => {
+            error!(
+                "Error occured while creating session. UserId = {}, ERROR = {:?}",
+                user_id, error
+            );
+            Err(SessionError::DatabaseError)
+        }
+    }
+}
+
+/// Checks whether session is valid and renews its idle timeout. Returns owner of
+/// the session with their current role, or `None` if the session does not exist
+/// or has expired.
+pub async fn authenticate(
+    db: &Pool,
+    token: &SessionToken,
+    config: &SessionConfig,
+) -> Result<Option<(UserId, Role)>, SessionError> {
+    let renew_stmt = include_str!("renew_session.sql");
+    let client = get_client(db).await?;
+
+    match client
+        .query_opt(
+            renew_stmt,
+            &[&token.hash(), &(config.idle_timeout_secs as f64)],
+        )
+        .await
+    {
+        Ok(Some(row)) => {
+            let user_id: UserId = row.get(0);
+            let role: String = row.get(1);
+
+            // Unknown role gets the least permissions.
+            let role = role.parse().unwrap_or_else(|_| {
+                error!(
+                    "User has unknown role. UserId = {}, Role = {}",
+                    user_id, role
+                );
+                Role::Student
+            });
+
+            Ok(Some((user_id, role)))
+        }
+        Ok(None) => Ok(None),
+        Err(error) => {
+            error!("Error occured while renewing session. ERROR = {:?}", error);
+            Err(SessionError::DatabaseError)
+        }
+    }
+}
+
+/// Ends single session.
+pub async fn delete_session(db: &Pool, token: &SessionToken) -> Result<(), SessionError> {
+    let delete_stmt = include_str!("delete_session.sql");
+    let client = get_client(db).await?;
+
+    match client.execute(delete_stmt, &[&token.hash()]).await {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!("Error occured while deleting session. ERROR = {:?}", error);
+            Err(SessionError::DatabaseError)
+        }
+    }
+}
+
+/// Ends all sessions of the user, on every device.
+pub async fn delete_user_sessions(db: &Pool, user_id: UserId) -> Result<u64, SessionError> {
+    let delete_stmt = include_str!("delete_user_sessions.sql");
+    let client = get_client(db).await?;
+
+    match client.execute(delete_stmt, &[&user_id]).await {
+        Ok(deleted) => Ok(deleted),
+        Err(error) => {
+            error!(
+                "Error occured while deleting sessions of user. UserId = {}, ERROR = {:?}",
+                user_id, error
+            );
+            Err(SessionError::DatabaseError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::migrations::test_database;
+
+    const CONFIG: SessionConfig = SessionConfig {
+        idle_timeout_secs: 3600,
+        max_lifetime_secs: 7 * 24 * 3600,
+    };
+
+    /// Inserts new user, sessions of different tests do not interfere.
+    async fn create_user(db: &Pool, role: &str) -> UserId {
+        let user_id = rand::random::<u32>() as UserId;
+
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "INSERT INTO user_data.users (id, username, password_hash, user_salt, email, role)
+                VALUES ($1, $2, '', '', '', $3)",
+                &[&user_id, &format!("session-test-{user_id}"), &role],
+            )
+            .await
+            .unwrap();
+
+        user_id
+    }
+
+    /// Moves 'column' of the session 'secs' seconds into the past.
+    async fn age_session(db: &Pool, token: &SessionToken, column: &str, secs: f64) {
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                &format!(
+                    "UPDATE user_data.sessions SET {column} = now() - make_interval(secs => $2)
+                    WHERE token_hash = $1"
+                ),
+                &[&token.hash(), &secs],
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn idle_secs(db: &Pool, token: &SessionToken) -> f64 {
+        db.get()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT EXTRACT(EPOCH FROM now() - last_seen)::FLOAT8
+                FROM user_data.sessions WHERE token_hash = $1",
+                &[&token.hash()],
+            )
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    #[test]
+    fn tokens_are_random_and_stored_hashed() {
+        let token = SessionToken::generate();
+
+        assert_eq!(token.0.len(), 2 * SESSION_TOKEN_LEN);
+        assert_ne!(token.0, SessionToken::generate().0);
+        assert_eq!(token.hash(), Sha256::digest(token.0.as_bytes()).to_vec());
+        assert_ne!(token.hash(), token.0.as_bytes());
+    }
+
+    #[test]
+    fn cookie_is_hidden_from_scripts_and_other_sites() {
+        let token = SessionToken::from("abc");
+        let cookie = token.cookie(&CONFIG);
+
+        assert_eq!(cookie.value(), "abc");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(
+            cookie.max_age(),
+            Some(CookieDuration::seconds(CONFIG.max_lifetime_secs as i64))
+        );
+        assert_eq!(removal_cookie().max_age(), Some(CookieDuration::ZERO));
+    }
+
+    #[test]
+    fn token_is_read_from_cookie() {
+        let req = TestRequest::default()
+            .cookie(Cookie::new(SESSION_COOKIE, "abc"))
+            .to_http_request();
+
+        assert_eq!(SessionToken::from_request(&req).unwrap().0, "abc");
+        assert!(SessionToken::from_request(&TestRequest::default().to_http_request()).is_none());
+    }
+
+    #[actix_web::test]
+    async fn session_authenticates_its_owner() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Teacher").await;
+
+        let token = create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        assert_eq!(
+            authenticate(&db, &token, &CONFIG).await.unwrap(),
+            Some((user_id, Role::Teacher))
+        );
+        assert_eq!(
+            authenticate(&db, &SessionToken::generate(), &CONFIG)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[actix_web::test]
+    async fn authentication_renews_idle_timeout() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Student").await;
+        let token = create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        age_session(&db, &token, "last_seen", 3000.0).await;
+
+        assert!(authenticate(&db, &token, &CONFIG).await.unwrap().is_some());
+        assert!(idle_secs(&db, &token).await < 60.0);
+    }
+
+    #[actix_web::test]
+    async fn idle_session_expires() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Student").await;
+        let token = create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        age_session(&db, &token, "last_seen", 3601.0).await;
+
+        assert_eq!(authenticate(&db, &token, &CONFIG).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn session_expires_after_max_lifetime_despite_activity() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Student").await;
+        let token = create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        age_session(&db, &token, "expires_at", 1.0).await;
+
+        assert_eq!(authenticate(&db, &token, &CONFIG).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn expired_sessions_are_removed_on_login() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Student").await;
+        let expired = create_session(&db, user_id, &CONFIG).await.unwrap();
+        age_session(&db, &expired, "last_seen", 3601.0).await;
+
+        create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        assert_eq!(delete_user_sessions(&db, user_id).await.unwrap(), 1);
+    }
+
+    #[actix_web::test]
+    async fn logout_ends_sessions() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let user_id = create_user(&db, "Admin").await;
+        let first = create_session(&db, user_id, &CONFIG).await.unwrap();
+        let second = create_session(&db, user_id, &CONFIG).await.unwrap();
+        let third = create_session(&db, user_id, &CONFIG).await.unwrap();
+
+        delete_session(&db, &first).await.unwrap();
+
+        assert_eq!(authenticate(&db, &first, &CONFIG).await.unwrap(), None);
+        assert!(authenticate(&db, &second, &CONFIG).await.unwrap().is_some());
+        assert_eq!(delete_user_sessions(&db, user_id).await.unwrap(), 2);
+        assert_eq!(authenticate(&db, &third, &CONFIG).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,3 @@
+UPDATE user_data.users
+SET email = $2
+WHERE id = $1 AND email = $3;
\ No newline at end of file
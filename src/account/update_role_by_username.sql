@@ -0,0 +1,3 @@
+UPDATE user_data.users
+SET role = $2
+WHERE username = $1;
\ No newline at end of file
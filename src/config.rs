@@ -0,0 +1,564 @@
+//! Runtime configuration of the server.
+//!
+//! Configuration is read at startup from JSON file ('config.json' in working
+//! directory, other path can be given in ALSIT_CONFIG env variable). Values which
+//! are not present in the file take defaults, and every value can be overridden
+//! with env variable:
+//!
+//! | Key                         | Env variable            |
+//! |-----------------------------|-------------------------|
+//! | server_address              | ALSIT_ADDRESS           |
+//! | tests_path                  | TESTS_PATH              |
+//! | testing_image_name          | TESTING_IMAGE_NAME      |
+//! | keys_path                   | ALSIT_KEYS_FILE         |
+//! | database.host               | PG__HOST                |
+//! | database.port               | PG__PORT                |
+//! | database.user               | PG__USER                |
+//! | database.password           | PG__PASSWORD            |
+//! | database.dbname             | PG__DBNAME              |
+//! | database.auto_migrate       | ALSIT_AUTO_MIGRATE      |
+//! | judge.number_of_judges      | ALSIT_JUDGES            |
+//! | judge.max_queued_jobs       | ALSIT_MAX_QUEUED_JOBS   |
+//! | judge.sandbox               | ALSIT_SANDBOX           |
+//! | judge.local_tester_path     | ALSIT_LOCAL_TESTER      |
+//! | judge.local_sandbox_dir     | ALSIT_LOCAL_SANDBOX_DIR |
+//! | judge.local_unshare         | ALSIT_LOCAL_UNSHARE     |
+//! | judge.local_cgroup_dir      | ALSIT_LOCAL_CGROUP_DIR  |
+//! | session.idle_timeout_secs   | ALSIT_SESSION_IDLE      |
+//! | session.max_lifetime_secs   | ALSIT_SESSION_LIFETIME  |
+//!
+//! `judge.default_limits` holds limits which are not set for the exercise
+//! (see [crate::judge]). Format of the key file
+//! is described in [crate::crypto::Keys].
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::judge::ExerciseLimits;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Configuration file could not be read.
+    Io { path: String, error: std::io::Error },
+    /// Value of 'key' could not be parsed.
+    Parse { key: String, message: String },
+    /// Value of 'key' was parsed, but is not allowed.
+    Invalid { key: String, message: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(f, "Unable to read configuration file '{path}': {error}")
+            }
+            ConfigError::Parse { key, message } => {
+                write!(f, "Unable to parse value of '{key}': {message}")
+            }
+            ConfigError::Invalid { key, message } => {
+                write!(f, "Invalid value of '{key}': {message}")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxKind {
+    Docker,
+    Local,
+}
+
+impl FromStr for SandboxKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker" => Ok(SandboxKind::Docker),
+            "local" => Ok(SandboxKind::Local),
+            _ => Err(format!("expected 'docker' or 'local', found '{s}'")),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: Option<String>,
+    pub dbname: String,
+    /// Whether missing migrations are applied at startup. When turned off, server
+    /// refuses to start until `alsit migrate` is run.
+    pub auto_migrate: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from("127.0.0.1"),
+            port: 5432,
+            user: String::from("alsit"),
+            password: None,
+            dbname: String::from("alsit_db"),
+            auto_migrate: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct JudgeConfig {
+    /// Number of tickets judged at the same time.
+    pub number_of_judges: usize,
+    /// Number of waiting tickets above which new ones are rejected.
+    pub max_queued_jobs: i64,
+    pub sandbox: SandboxKind,
+    /// Path to testing program, required by local sandbox.
+    pub local_tester_path: Option<PathBuf>,
+    /// Directory for working directories of local sandbox (temporary directory by default).
+    pub local_sandbox_dir: Option<PathBuf>,
+    /// Whether local sandbox puts testing program into separate namespaces.
+    pub local_unshare: bool,
+    /// Cgroup v2 directory, writable by the server, in which local sandbox creates
+    /// cgroups limiting number of processes and, when memory controller is enabled,
+    /// memory. Without it the number of processes is not limited.
+    pub local_cgroup_dir: Option<PathBuf>,
+    pub default_limits: ExerciseLimits,
+}
+
+impl Default for JudgeConfig {
+    fn default() -> Self {
+        Self {
+            number_of_judges: 4,
+            max_queued_jobs: 1000,
+            sandbox: SandboxKind::Docker,
+            local_tester_path: None,
+            local_sandbox_dir: None,
+            local_unshare: false,
+            local_cgroup_dir: None,
+            default_limits: ExerciseLimits::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Time without activity after which session expires.
+    pub idle_timeout_secs: u64,
+    /// Time after which session expires regardless of activity.
+    pub max_lifetime_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 2 * 60 * 60,
+            max_lifetime_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Address at which server listens, for example "127.0.0.1:8080".
+    pub server_address: String,
+    /// Directory with exercises data.
+    pub tests_path: PathBuf,
+    /// Docker image used by testing containers.
+    pub testing_image_name: String,
+    /// File with encryption and hashing keys.
+    pub keys_path: Option<PathBuf>,
+    pub database: DatabaseConfig,
+    pub judge: JudgeConfig,
+    pub session: SessionConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server_address: String::from("127.0.0.1:8080"),
+            tests_path: PathBuf::from("/exercises"),
+            testing_image_name: String::from("alsit_testing_image"),
+            keys_path: None,
+            database: DatabaseConfig::default(),
+            judge: JudgeConfig::default(),
+            session: SessionConfig::default(),
+        }
+    }
+}
+
+/// Replaces 'target' with value of env variable 'var', if it is set.
+fn override_from_env<T>(target: &mut T, var: &str, key: &str) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(var) {
+        *target = value.parse().map_err(|error: T::Err| ConfigError::Parse {
+            key: format!("{key} (from {var})"),
+            message: error.to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn override_optional_from_env<T>(
+    target: &mut Option<T>,
+    var: &str,
+    key: &str,
+) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(var) {
+        let value = value.parse().map_err(|error: T::Err| ConfigError::Parse {
+            key: format!("{key} (from {var})"),
+            message: error.to_string(),
+        })?;
+        *target = Some(value);
+    }
+
+    Ok(())
+}
+
+fn invalid(key: &str, message: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: String::from(key),
+        message: String::from(message),
+    }
+}
+
+impl Config {
+    /// Reads configuration file, applies env variable overrides and validates the result.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = std::env::var("ALSIT_CONFIG").unwrap_or_else(|_| String::from("config.json"));
+
+        let mut config = Config::from_file(&path)?;
+        config.apply_env_overrides()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let content = std::fs::read(path).map_err(|error| ConfigError::Io {
+            path: String::from(path),
+            error,
+        })?;
+
+        let deserializer = &mut serde_json::Deserializer::from_slice(&content);
+
+        serde_path_to_error::deserialize(deserializer).map_err(|error| ConfigError::Parse {
+            key: error.path().to_string(),
+            message: error.into_inner().to_string(),
+        })
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        override_from_env(&mut self.server_address, "ALSIT_ADDRESS", "server_address")?;
+        override_from_env(&mut self.tests_path, "TESTS_PATH", "tests_path")?;
+        override_from_env(
+            &mut self.testing_image_name,
+            "TESTING_IMAGE_NAME",
+            "testing_image_name",
+        )?;
+
+        override_optional_from_env(&mut self.keys_path, "ALSIT_KEYS_FILE", "keys_path")?;
+
+        let database = &mut self.database;
+        override_from_env(&mut database.host, "PG__HOST", "database.host")?;
+        override_from_env(&mut database.port, "PG__PORT", "database.port")?;
+        override_from_env(&mut database.user, "PG__USER", "database.user")?;
+        override_optional_from_env(&mut database.password, "PG__PASSWORD", "database.password")?;
+        override_from_env(&mut database.dbname, "PG__DBNAME", "database.dbname")?;
+        override_from_env(
+            &mut database.auto_migrate,
+            "ALSIT_AUTO_MIGRATE",
+            "database.auto_migrate",
+        )?;
+
+        let judge = &mut self.judge;
+        override_from_env(
+            &mut judge.number_of_judges,
+            "ALSIT_JUDGES",
+            "judge.number_of_judges",
+        )?;
+        override_from_env(
+            &mut judge.max_queued_jobs,
+            "ALSIT_MAX_QUEUED_JOBS",
+            "judge.max_queued_jobs",
+        )?;
+        override_from_env(&mut judge.sandbox, "ALSIT_SANDBOX", "judge.sandbox")?;
+        override_optional_from_env(
+            &mut judge.local_tester_path,
+            "ALSIT_LOCAL_TESTER",
+            "judge.local_tester_path",
+        )?;
+        override_optional_from_env(
+            &mut judge.local_sandbox_dir,
+            "ALSIT_LOCAL_SANDBOX_DIR",
+            "judge.local_sandbox_dir",
+        )?;
+        override_from_env(
+            &mut judge.local_unshare,
+            "ALSIT_LOCAL_UNSHARE",
+            "judge.local_unshare",
+        )?;
+        override_optional_from_env(
+            &mut judge.local_cgroup_dir,
+            "ALSIT_LOCAL_CGROUP_DIR",
+            "judge.local_cgroup_dir",
+        )?;
+
+        let session = &mut self.session;
+        override_from_env(
+            &mut session.idle_timeout_secs,
+            "ALSIT_SESSION_IDLE",
+            "session.idle_timeout_secs",
+        )?;
+        override_from_env(
+            &mut session.max_lifetime_secs,
+            "ALSIT_SESSION_LIFETIME",
+            "session.max_lifetime_secs",
+        )?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.server_address.is_empty() {
+            return Err(invalid("server_address", "must not be empty"));
+        }
+
+        if self.tests_path.as_os_str().is_empty() {
+            return Err(invalid("tests_path", "must not be empty"));
+        }
+
+        if self.database.host.is_empty() {
+            return Err(invalid("database.host", "must not be empty"));
+        }
+
+        if self.database.user.is_empty() {
+            return Err(invalid("database.user", "must not be empty"));
+        }
+
+        if self.database.dbname.is_empty() {
+            return Err(invalid("database.dbname", "must not be empty"));
+        }
+
+        let judge = &self.judge;
+
+        if judge.number_of_judges == 0 {
+            return Err(invalid("judge.number_of_judges", "must be greater than 0"));
+        }
+
+        if judge.max_queued_jobs <= 0 {
+            return Err(invalid("judge.max_queued_jobs", "must be greater than 0"));
+        }
+
+        match judge.sandbox {
+            SandboxKind::Docker if self.testing_image_name.is_empty() => {
+                return Err(invalid(
+                    "testing_image_name",
+                    "must not be empty when Docker sandbox is used",
+                ));
+            }
+            SandboxKind::Local if judge.local_tester_path.is_none() => {
+                return Err(invalid(
+                    "judge.local_tester_path",
+                    "must be set when local sandbox is used",
+                ));
+            }
+            _ => {}
+        }
+
+        if self.session.idle_timeout_secs == 0 {
+            return Err(invalid(
+                "session.idle_timeout_secs",
+                "must be greater than 0",
+            ));
+        }
+
+        if self.session.max_lifetime_secs < self.session.idle_timeout_secs {
+            return Err(invalid(
+                "session.max_lifetime_secs",
+                "must not be smaller than session.idle_timeout_secs",
+            ));
+        }
+
+        let limits = &judge.default_limits;
+
+        if limits.memory_mb <= 0 {
+            return Err(invalid(
+                "judge.default_limits.memory_mb",
+                "must be greater than 0",
+            ));
+        }
+
+        if limits.cpus <= 0.0 {
+            return Err(invalid(
+                "judge.default_limits.cpus",
+                "must be greater than 0",
+            ));
+        }
+
+        if limits.pids <= 0 {
+            return Err(invalid(
+                "judge.default_limits.pids",
+                "must be greater than 0",
+            ));
+        }
+
+        if limits.work_dir_mb <= 0 {
+            return Err(invalid(
+                "judge.default_limits.work_dir_mb",
+                "must be greater than 0",
+            ));
+        }
+
+        if limits.wall_time_secs == 0 {
+            return Err(invalid(
+                "judge.default_limits.wall_time_secs",
+                "must be greater than 0",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Config {
+        serde_json::from_str(json).expect("Configuration should parse.")
+    }
+
+    fn invalid_key(config: &Config) -> String {
+        match config.validate() {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            result => panic!("Expected invalid value, found {result:?}"),
+        }
+    }
+
+    #[test]
+    fn missing_values_take_defaults() {
+        let config = parse(r#"{"database": {"port": 5433}, "judge": {"sandbox": "local"}}"#);
+
+        assert_eq!(config.database.port, 5433);
+        assert_eq!(config.database.host, "127.0.0.1");
+        assert_eq!(config.judge.sandbox, SandboxKind::Local);
+        assert_eq!(config.judge.number_of_judges, 4);
+        assert_eq!(config.server_address, "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn default_configuration_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_zero_judges() {
+        let mut config = Config::default();
+        config.judge.number_of_judges = 0;
+
+        assert_eq!(invalid_key(&config), "judge.number_of_judges");
+    }
+
+    #[test]
+    fn validate_requires_tester_of_local_sandbox() {
+        let mut config = Config::default();
+        config.judge.sandbox = SandboxKind::Local;
+
+        assert_eq!(invalid_key(&config), "judge.local_tester_path");
+
+        config.judge.local_tester_path = Some(PathBuf::from("/usr/bin/tester"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_requires_image_of_docker_sandbox() {
+        let mut config = Config::default();
+        config.testing_image_name.clear();
+
+        assert_eq!(invalid_key(&config), "testing_image_name");
+    }
+
+    #[test]
+    fn validate_rejects_lifetime_shorter_than_idle_timeout() {
+        let mut config = Config::default();
+        config.session.max_lifetime_secs = config.session.idle_timeout_secs - 1;
+
+        assert_eq!(invalid_key(&config), "session.max_lifetime_secs");
+    }
+
+    #[test]
+    fn validate_rejects_non_positive_limits() {
+        let mut config = Config::default();
+        config.judge.default_limits.cpus = 0.0;
+
+        assert_eq!(invalid_key(&config), "judge.default_limits.cpus");
+    }
+
+    // Every test uses its own env variables, as tests run in parallel.
+    #[test]
+    fn env_variable_overrides_value() {
+        let mut port = 5432u16;
+        std::env::set_var("ALSIT_TEST_OVERRIDE_PORT", "6543");
+
+        override_from_env(&mut port, "ALSIT_TEST_OVERRIDE_PORT", "database.port").unwrap();
+
+        assert_eq!(port, 6543);
+    }
+
+    #[test]
+    fn unset_env_variable_keeps_value() {
+        let mut port = 5432u16;
+        let mut password = Some(String::from("secret"));
+
+        override_from_env(&mut port, "ALSIT_TEST_UNSET_PORT", "database.port").unwrap();
+        override_optional_from_env(
+            &mut password,
+            "ALSIT_TEST_UNSET_PASSWORD",
+            "database.password",
+        )
+        .unwrap();
+
+        assert_eq!(port, 5432);
+        assert_eq!(password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn env_variable_sets_optional_value() {
+        let mut keys_path: Option<PathBuf> = None;
+        std::env::set_var("ALSIT_TEST_OVERRIDE_KEYS", "/etc/alsit/keys.json");
+
+        override_optional_from_env(&mut keys_path, "ALSIT_TEST_OVERRIDE_KEYS", "keys_path")
+            .unwrap();
+
+        assert_eq!(keys_path, Some(PathBuf::from("/etc/alsit/keys.json")));
+    }
+
+    #[test]
+    fn unparsable_env_variable_names_key_and_variable() {
+        let mut sandbox = SandboxKind::Docker;
+        std::env::set_var("ALSIT_TEST_BAD_SANDBOX", "vm");
+
+        match override_from_env(&mut sandbox, "ALSIT_TEST_BAD_SANDBOX", "judge.sandbox") {
+            Err(ConfigError::Parse { key, .. }) => {
+                assert_eq!(key, "judge.sandbox (from ALSIT_TEST_BAD_SANDBOX)")
+            }
+            result => panic!("Expected parse error, found {result:?}"),
+        }
+        assert_eq!(sandbox, SandboxKind::Docker);
+    }
+}
@@ -0,0 +1,497 @@
+//! Key material used by [super::Encryptor] and [super::Hasher].
+//!
+//! Keys are read at startup from key file (`keys_path` in configuration,
+//! ALSIT_KEYS_FILE env variable), which is JSON object:
+//!
+//! ```json
+//! {
+//!     "current_encryption_key": 1,
+//!     "encryption_keys": {"0": "<32 bytes>", "1": "<32 bytes>"},
+//!     "hashing_key": "<64 bytes>"
+//! }
+//! ```
+//!
+//! Values are encoded in hex or base64. Every ciphertext starts with id of the key
+//! it was encrypted with, so old keys stay in `encryption_keys` after rotation
+//! (`alsit rotate-key <path>`) until data is re-encrypted with the current one
+//! (`alsit reencrypt`). Key file with single `encryption_key` is read as key 0.
+//!
+//! Current encryption key and hashing key can also be given directly in
+//! ALSIT_ENCRYPTION_KEY (with id in ALSIT_ENCRYPTION_KEY_ID, 0 by default) and
+//! ALSIT_HASHING_KEY env variables, which take precedence over the file. Fresh key
+//! file is generated with `alsit keygen [path]`.
+//!
+//! Release build refuses to start without configured keys; debug build falls back
+//! to fixed development keys.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Length of AES-256-GCM key.
+pub const ENCRYPTION_KEY_LEN: usize = 32;
+/// Length of Argon2 secret (pepper).
+pub const HASHING_KEY_LEN: usize = 64;
+
+/// Identifier of encryption key, stored as the first byte of ciphertext.
+pub type KeyId = u8;
+
+#[derive(Debug)]
+pub enum KeyError {
+    /// Key file could not be read or written.
+    Io { path: String, error: std::io::Error },
+    /// Key file is not valid JSON object with keys.
+    Format { path: String, message: String },
+    /// Key is not valid hex or base64, or has wrong length.
+    InvalidKey { source: String, message: String },
+    /// Key was not found anywhere.
+    Missing { name: &'static str },
+    /// All key ids are already taken.
+    KeyIdsExhausted,
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyError::Io { path, error } => write!(f, "Unable to access key file '{path}': {error}"),
+            KeyError::Format { path, message } => {
+                write!(f, "Key file '{path}' has wrong format: {message}")
+            }
+            KeyError::InvalidKey { source, message } => {
+                write!(f, "Invalid key in {source}: {message}")
+            }
+            KeyError::Missing { name } => write!(
+                f,
+                "No {name} is configured. Set 'keys_path' in configuration or provide keys in env variables."
+            ),
+            KeyError::KeyIdsExhausted => write!(
+                f,
+                "All {} encryption key ids are used. Remove retired keys first.",
+                KeyId::MAX as usize + 1
+            ),
+        }
+    }
+}
+
+/// Content of the key file, keys are encoded.
+#[derive(Serialize, Deserialize, Default)]
+struct KeyFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_encryption_key: Option<KeyId>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    encryption_keys: BTreeMap<KeyId, String>,
+    /// Single key from before key ids were introduced, read as key 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encryption_key: Option<String>,
+    hashing_key: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct EncryptionKey {
+    pub id: KeyId,
+    pub key: [u8; ENCRYPTION_KEY_LEN],
+}
+
+pub struct Keys {
+    /// Current and retired encryption keys.
+    pub encryption_keys: Vec<EncryptionKey>,
+    /// Key used for encryption of new data.
+    pub current_key_id: KeyId,
+    pub hashing_key: [u8; HASHING_KEY_LEN],
+}
+
+impl Keys {
+    /// Reads keys from env variables and key file named in 'config'.
+    pub fn load(config: &Config) -> Result<Keys, KeyError> {
+        let mut keys = match &config.keys_path {
+            Some(path) => Some(Keys::read_key_file(path)?),
+            None => None,
+        };
+
+        if let Ok(encoded) = std::env::var("ALSIT_ENCRYPTION_KEY") {
+            let id = match std::env::var("ALSIT_ENCRYPTION_KEY_ID") {
+                Ok(id) => id.parse().map_err(|_| KeyError::InvalidKey {
+                    source: String::from("ALSIT_ENCRYPTION_KEY_ID"),
+                    message: format!("expected number from 0 to {}", KeyId::MAX),
+                })?,
+                Err(_) => 0,
+            };
+            let key = EncryptionKey {
+                id,
+                key: decode_key(&encoded, "ALSIT_ENCRYPTION_KEY")?,
+            };
+
+            match &mut keys {
+                Some(keys) => keys.set_current(key),
+                None => {
+                    keys = Some(Keys {
+                        encryption_keys: vec![key],
+                        current_key_id: id,
+                        hashing_key: [0u8; HASHING_KEY_LEN],
+                    })
+                }
+            }
+        }
+
+        let hashing_key = match std::env::var("ALSIT_HASHING_KEY") {
+            Ok(encoded) => Some(decode_key(&encoded, "ALSIT_HASHING_KEY")?),
+            Err(_) => None,
+        };
+
+        match (keys, hashing_key) {
+            (Some(mut keys), Some(hashing_key)) => {
+                keys.hashing_key = hashing_key;
+                Ok(keys)
+            }
+            // Hashing key of keys loaded from env without key file is not set yet.
+            (Some(keys), None) if config.keys_path.is_some() => Ok(keys),
+            (None, None) if cfg!(debug_assertions) => {
+                warn!("No keys are configured. Using development keys, which must never be used in production.");
+                Ok(Keys::development())
+            }
+            (None, _) => Err(KeyError::Missing {
+                name: "encryption key",
+            }),
+            (Some(_), None) => Err(KeyError::Missing {
+                name: "hashing key",
+            }),
+        }
+    }
+
+    /// Generates new random keys, with single encryption key of id 0.
+    pub fn generate() -> Keys {
+        use rand::prelude::*;
+
+        let mut keys = Keys {
+            encryption_keys: vec![generate_encryption_key(0)],
+            current_key_id: 0,
+            hashing_key: [0u8; HASHING_KEY_LEN],
+        };
+
+        rand::rngs::OsRng.fill_bytes(&mut keys.hashing_key);
+
+        keys
+    }
+
+    /// Adds new random encryption key and makes it current. Previous keys are kept,
+    /// so existing data can still be decrypted.
+    pub fn rotate(&mut self) -> Result<KeyId, KeyError> {
+        let new_id = match self.encryption_keys.iter().map(|key| key.id).max() {
+            Some(KeyId::MAX) => return Err(KeyError::KeyIdsExhausted),
+            Some(id) => id + 1,
+            None => 0,
+        };
+
+        self.set_current(generate_encryption_key(new_id));
+
+        Ok(new_id)
+    }
+
+    /// Replaces key with the same id (if there is one) and makes 'key' current.
+    fn set_current(&mut self, key: EncryptionKey) {
+        self.current_key_id = key.id;
+        self.encryption_keys.retain(|known| known.id != key.id);
+        self.encryption_keys.push(key);
+    }
+
+    /// Reads keys from key file. Both encryption and hashing keys have to be present.
+    pub fn read_key_file(path: &Path) -> Result<Keys, KeyError> {
+        let key_file = read_key_file(path)?;
+        let source = |name: &str| format!("'{}' of key file '{}'", name, path.display());
+
+        let mut encryption_keys = Vec::new();
+
+        for (id, encoded) in &key_file.encryption_keys {
+            encryption_keys.push(EncryptionKey {
+                id: *id,
+                key: decode_key(encoded, &source(&format!("encryption_keys.{id}")))?,
+            });
+        }
+
+        if let Some(encoded) = &key_file.encryption_key {
+            if !key_file.encryption_keys.contains_key(&0) {
+                encryption_keys.push(EncryptionKey {
+                    id: 0,
+                    key: decode_key(encoded, &source("encryption_key"))?,
+                });
+            }
+        }
+
+        let current_key_id = match key_file.current_encryption_key {
+            Some(id) if encryption_keys.iter().any(|key| key.id == id) => id,
+            Some(id) => {
+                return Err(KeyError::InvalidKey {
+                    source: source("current_encryption_key"),
+                    message: format!("there is no encryption key with id {id}"),
+                })
+            }
+            // Without explicit choice the newest key is current.
+            None => match encryption_keys.iter().map(|key| key.id).max() {
+                Some(id) => id,
+                None => {
+                    return Err(KeyError::Missing {
+                        name: "encryption key",
+                    })
+                }
+            },
+        };
+
+        let hashing_key = match &key_file.hashing_key {
+            Some(encoded) => decode_key(encoded, &source("hashing_key"))?,
+            None => {
+                return Err(KeyError::Missing {
+                    name: "hashing key",
+                })
+            }
+        };
+
+        Ok(Keys {
+            encryption_keys,
+            current_key_id,
+            hashing_key,
+        })
+    }
+
+    /// Writes keys as hex into new file readable only by its owner. Existing file
+    /// is never overwritten.
+    pub fn write_key_file(&self, path: &Path) -> Result<(), KeyError> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let io_error = |error| KeyError::Io {
+            path: path.display().to_string(),
+            error,
+        };
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(io_error)?;
+
+        file.write_all(self.to_json().as_bytes()).map_err(io_error)
+    }
+
+    /// Replaces existing key file with current keys. New content is written to
+    /// temporary file first, so key file is never left half written.
+    pub fn replace_key_file(&self, path: &Path) -> Result<(), KeyError> {
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".new");
+        let temporary_path = Path::new(&temporary_path);
+
+        self.write_key_file(temporary_path)?;
+
+        std::fs::rename(temporary_path, path).map_err(|error| KeyError::Io {
+            path: path.display().to_string(),
+            error,
+        })
+    }
+
+    /// Key file content with keys encoded as hex.
+    pub fn to_json(&self) -> String {
+        let key_file = KeyFile {
+            current_encryption_key: Some(self.current_key_id),
+            encryption_keys: self
+                .encryption_keys
+                .iter()
+                .map(|key| (key.id, super::encode_hex(&key.key)))
+                .collect(),
+            encryption_key: None,
+            hashing_key: Some(super::encode_hex(&self.hashing_key)),
+        };
+
+        let mut json = serde_json::to_string_pretty(&key_file).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+
+    /// Keys compiled into debug builds, so development setup needs no key file.
+    fn development() -> Keys {
+        Keys {
+            encryption_keys: vec![EncryptionKey {
+                id: 0,
+                key: [
+                    35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12,
+                    63, 21, 23, 35, 23, 12, 63, 35, 23, 12, 63, 2, 3,
+                ],
+            }],
+            current_key_id: 0,
+            hashing_key: [
+                35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63,
+                21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63, 21, 23, 92, 2, 1,
+                62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162,
+                36, 232, 15,
+            ],
+        }
+    }
+}
+
+fn generate_encryption_key(id: KeyId) -> EncryptionKey {
+    use rand::prelude::*;
+
+    let mut key = EncryptionKey {
+        id,
+        key: [0u8; ENCRYPTION_KEY_LEN],
+    };
+    rand::rngs::OsRng.fill_bytes(&mut key.key);
+
+    key
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, KeyError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path_string = path.display().to_string();
+
+    let metadata = std::fs::metadata(path).map_err(|error| KeyError::Io {
+        path: path_string.clone(),
+        error,
+    })?;
+
+    if metadata.permissions().mode() & 0o077 != 0 {
+        warn!(
+            "Key file '{}' is accessible by other users. Its permissions should be 600.",
+            path_string
+        );
+    }
+
+    let content = std::fs::read(path).map_err(|error| KeyError::Io {
+        path: path_string.clone(),
+        error,
+    })?;
+
+    serde_json::from_slice(&content).map_err(|error| KeyError::Format {
+        path: path_string,
+        message: error.to_string(),
+    })
+}
+
+/// Decodes key of length 'N' from hex or base64. 'source' names place where key
+/// was found, for error messages.
+fn decode_key<const N: usize>(encoded: &str, source: &str) -> Result<[u8; N], KeyError> {
+    let encoded = encoded.trim();
+
+    let decoded = match super::decode_hex(encoded) {
+        Some(decoded) => decoded,
+        None => base64::decode(encoded).map_err(|_| KeyError::InvalidKey {
+            source: String::from(source),
+            message: String::from("it is neither hex nor base64"),
+        })?,
+    };
+
+    decoded
+        .try_into()
+        .map_err(|decoded: Vec<u8>| KeyError::InvalidKey {
+            source: String::from(source),
+            message: format!("expected {} bytes, found {}", N, decoded.len()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of key file unique to the test, removed before use.
+    fn key_file_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("alsit-{}-{}.json", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn decode_key_accepts_hex_and_base64() {
+        let key = [7u8; ENCRYPTION_KEY_LEN];
+
+        let from_hex: [u8; ENCRYPTION_KEY_LEN] =
+            decode_key(&super::super::encode_hex(&key), "test").unwrap();
+        let from_base64: [u8; ENCRYPTION_KEY_LEN] =
+            decode_key(&format!(" {}\n", base64::encode(key)), "test").unwrap();
+
+        assert_eq!(from_hex, key);
+        assert_eq!(from_base64, key);
+    }
+
+    #[test]
+    fn decode_key_rejects_wrong_length() {
+        let result: Result<[u8; ENCRYPTION_KEY_LEN], _> = decode_key("abcd", "test");
+
+        match result {
+            Err(KeyError::InvalidKey { message, .. }) => {
+                assert_eq!(message, "expected 32 bytes, found 2")
+            }
+            _ => panic!("Expected invalid key"),
+        }
+    }
+
+    #[test]
+    fn decode_key_rejects_garbage() {
+        let result: Result<[u8; ENCRYPTION_KEY_LEN], _> = decode_key("not a key!", "test");
+
+        assert!(matches!(result, Err(KeyError::InvalidKey { .. })));
+    }
+
+    #[test]
+    fn key_file_round_trip() {
+        let path = key_file_path("round-trip");
+        let mut keys = Keys::generate();
+        keys.rotate().unwrap();
+
+        keys.write_key_file(&path).unwrap();
+        let read = Keys::read_key_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(read.current_key_id, 1);
+        assert_eq!(read.hashing_key, keys.hashing_key);
+        assert_eq!(read.encryption_keys.len(), 2);
+        for key in &keys.encryption_keys {
+            let read_key = read.encryption_keys.iter().find(|read| read.id == key.id);
+            assert_eq!(read_key.map(|read| read.key), Some(key.key));
+        }
+    }
+
+    #[test]
+    fn single_key_is_read_as_key_zero() {
+        let path = key_file_path("single-key");
+        let content = format!(
+            r#"{{"encryption_key": "{}", "hashing_key": "{}"}}"#,
+            super::super::encode_hex(&[1u8; ENCRYPTION_KEY_LEN]),
+            super::super::encode_hex(&[2u8; HASHING_KEY_LEN]),
+        );
+        std::fs::write(&path, content).unwrap();
+
+        let keys = Keys::read_key_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(keys.current_key_id, 0);
+        assert_eq!(keys.encryption_keys.len(), 1);
+        assert_eq!(keys.encryption_keys[0].key, [1u8; ENCRYPTION_KEY_LEN]);
+    }
+
+    #[test]
+    fn unknown_current_key_is_rejected() {
+        let path = key_file_path("unknown-current");
+        let content = format!(
+            r#"{{"current_encryption_key": 3, "encryption_keys": {{"0": "{}"}}, "hashing_key": "{}"}}"#,
+            super::super::encode_hex(&[1u8; ENCRYPTION_KEY_LEN]),
+            super::super::encode_hex(&[2u8; HASHING_KEY_LEN]),
+        );
+        std::fs::write(&path, content).unwrap();
+
+        let result = Keys::read_key_file(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(result, Err(KeyError::InvalidKey { .. })));
+    }
+
+    #[test]
+    fn rotation_stops_when_key_ids_are_exhausted() {
+        let mut keys = Keys::generate();
+        keys.encryption_keys[0].id = KeyId::MAX;
+
+        assert!(matches!(keys.rotate(), Err(KeyError::KeyIdsExhausted)));
+    }
+}
@@ -0,0 +1,480 @@
+use aes_gcm::NewAead;
+
+mod keys;
+
+pub use keys::{EncryptionKey, KeyId, Keys};
+
+/// Strucute to wrap entryptor to provide as actix_web state.
+///
+/// Ciphertext consists of id of the key used for encryption (see [keys]), random
+/// nonce of [crate::ENCRYPT_NONCE_LEN] bytes and encrypted data with GCM tag.
+/// Older formats, with nonce derived from user salt, can only be decrypted with
+/// [Encryptor::decrypt_with_key_id] and are migrated by `alsit reencrypt`.
+pub struct Encryptor {
+    /// Aes encryption structures of all known keys.
+    machines: Vec<(KeyId, aes_gcm::Aes256Gcm)>,
+    /// Key used for encryption.
+    current_key_id: KeyId,
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    InternalError,
+    OutPlaceTooSmall,
+    OutPlaceTooBig,
+    UserSaltTooSmall,
+    /// Data was not encrypted with any of known keys, or it was modified.
+    DecryptionFailed,
+}
+
+use aes_gcm::aead::Aead;
+use aes_gcm::Nonce;
+impl Encryptor {
+    /// 'current_key_id' has to be id of one of 'keys'.
+    pub fn new(keys: &[EncryptionKey], current_key_id: KeyId) -> Encryptor {
+        let machines = keys
+            .iter()
+            .map(|key| {
+                let machine = aes_gcm::Aes256Gcm::new(aes_gcm::Key::from_slice(&key.key));
+                (key.id, machine)
+            })
+            .collect();
+
+        Encryptor {
+            machines,
+            current_key_id,
+        }
+    }
+
+    pub fn current_key_id(&self) -> KeyId {
+        self.current_key_id
+    }
+
+    fn machine(&self, key_id: KeyId) -> Option<&aes_gcm::Aes256Gcm> {
+        self.machines
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .map(|(_, machine)| machine)
+    }
+
+    /// Encrypts with the current key and fresh random nonce.
+    pub fn encrypt(&self, to_encrypt: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        use rand::RngCore;
+
+        let mut nonce = [0u8; crate::ENCRYPT_NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let machine = match self.machine(self.current_key_id) {
+            Some(machine) => machine,
+            None => {
+                error!(
+                    "Current encryption key is missing. KeyId = {}",
+                    self.current_key_id
+                );
+                return Err(CryptoError::InternalError);
+            }
+        };
+
+        match machine.encrypt(Nonce::from_slice(&nonce), to_encrypt) {
+            Ok(result) => {
+                let mut output = Vec::with_capacity(1 + nonce.len() + result.len());
+                output.push(self.current_key_id);
+                output.extend_from_slice(&nonce);
+                output.extend_from_slice(&result);
+                Ok(output)
+            }
+            Err(error) => {
+                error!(
+                    "Error occured while condcting encryption process. ERROR = {}",
+                    error
+                );
+                Err(CryptoError::InternalError)
+            }
+        }
+    }
+
+    /// Decrypts data and returns id of the key it was encrypted with. When
+    /// 'legacy_salt' is given, data in older formats with nonce taken from first
+    /// 12 bytes of the salt is accepted as well; id is `None` for such data.
+    pub fn decrypt_with_key_id(
+        &self,
+        to_decrypt: &[u8],
+        legacy_salt: Option<&[u8]>,
+    ) -> Result<(Vec<u8>, Option<KeyId>), CryptoError> {
+        if to_decrypt.len() > crate::ENCRYPT_NONCE_LEN {
+            let (key_id, rest) = (to_decrypt[0], &to_decrypt[1..]);
+            let (nonce, ciphertext) = rest.split_at(crate::ENCRYPT_NONCE_LEN);
+
+            if let Some(machine) = self.machine(key_id) {
+                if let Ok(result) = machine.decrypt(Nonce::from_slice(nonce), ciphertext) {
+                    return Ok((result, Some(key_id)));
+                }
+            }
+        }
+
+        match legacy_salt {
+            Some(salt) => self.decrypt_legacy(to_decrypt, salt),
+            None => Err(CryptoError::DecryptionFailed),
+        }
+    }
+
+    /// Decrypts data encrypted with nonce derived from user salt, either prefixed
+    /// with key id or without it.
+    fn decrypt_legacy(
+        &self,
+        to_decrypt: &[u8],
+        user_salt: &[u8],
+    ) -> Result<(Vec<u8>, Option<KeyId>), CryptoError> {
+        if user_salt.len() < crate::ENCRYPT_NONCE_LEN {
+            return Err(CryptoError::UserSaltTooSmall);
+        }
+
+        let nonce = Nonce::from_slice(&user_salt[..crate::ENCRYPT_NONCE_LEN]);
+
+        if let Some((key_id, ciphertext)) = to_decrypt.split_first() {
+            if let Some(machine) = self.machine(*key_id) {
+                if let Ok(result) = machine.decrypt(nonce, ciphertext) {
+                    return Ok((result, None));
+                }
+            }
+        }
+
+        // GCM authentication makes sure that wrong key is never accepted.
+        for (_, machine) in &self.machines {
+            if let Ok(result) = machine.decrypt(nonce, to_decrypt) {
+                return Ok((result, None));
+            }
+        }
+
+        Err(CryptoError::DecryptionFailed)
+    }
+}
+
+impl Clone for Encryptor {
+    fn clone(&self) -> Self {
+        Self {
+            machines: self.machines.clone(),
+            current_key_id: self.current_key_id,
+        }
+    }
+}
+
+use argon2::Argon2;
+pub struct Hasher<'a> {
+    machine: Argon2<'a>,
+}
+
+impl<'a> Hasher<'a> {
+    /// Generates new hasher with supplied secret (Pepper).
+    pub fn new(secret: &'a [u8]) -> Hasher<'a> {
+        use argon2::Params;
+
+        let params = Params::new(
+            Params::DEFAULT_M_COST,
+            Params::DEFAULT_T_COST,
+            Params::DEFAULT_P_COST,
+            Some(crate::HASH_LENGTH_BYTES),
+        )
+        .expect("Wrong hasher configuration.");
+
+        let machine = Argon2::new_with_secret(
+            secret,
+            argon2::Algorithm::Argon2id,
+            argon2::Version::default(),
+            params,
+        )
+        .expect("Unable to construct Argon2 structure. Probably bad secret was supplied.");
+
+        Hasher { machine }
+    }
+
+    /// Hashes password into 'out_place'.
+    /// Salt should be
+    /// Parameter 'out_place' should have length as 'crate::HASH_LENGTH_BYTES', otherwise it will fail.
+    pub fn hash_password(
+        &self,
+        password: &str,
+        salt: &[u8],
+        out_place: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        match self
+            .machine
+            .hash_password_into(password.as_bytes(), salt, out_place)
+        {
+            Ok(_) => Ok(()),
+            Err(argon2::Error::OutputTooLong) => Err(CryptoError::OutPlaceTooBig),
+            Err(argon2::Error::OutputTooShort) => Err(CryptoError::OutPlaceTooSmall),
+            Err(error) => {
+                error!(
+                    "Error occured while conducting hashing process. ERROR = {}",
+                    error
+                );
+                Err(CryptoError::InternalError)
+            }
+        }
+    }
+
+    pub fn password_matches(
+        &self,
+        password: &str,
+        hash: &[u8],
+        salt: &[u8],
+    ) -> Result<bool, CryptoError> {
+        let mut out_hash: [u8; crate::HASH_LENGTH_BYTES] = [0u8; crate::HASH_LENGTH_BYTES];
+
+        self.hash_password(password, salt, &mut out_hash)?;
+
+        Ok(out_hash == hash)
+    }
+}
+
+impl Clone for Hasher<'_> {
+    fn clone(&self) -> Self {
+        Self {
+            machine: self.machine.clone(),
+        }
+    }
+}
+
+pub fn generate_salt() -> [u8; crate::HASH_SALT_LEN] {
+    use rand::prelude::*;
+    let mut result = [0u8; crate::HASH_SALT_LEN];
+
+    let mut rng = rand::thread_rng();
+
+    for val in result.iter_mut() {
+        *val = rng.gen();
+    }
+
+    result
+}
+
+use deadpool_postgres::Pool;
+use tokio_postgres::NoTls;
+
+use crate::config::DatabaseConfig;
+
+/// Function inits deadpool with values from 'database' section of configuration.
+pub async fn init_database_pool(database: &DatabaseConfig) -> Pool {
+    let mut config = deadpool_postgres::Config::new();
+
+    config.dbname = Some(database.dbname.clone()); // Database name
+
+    config.user = Some(database.user.clone()); // Username for application account
+
+    config.password = database.password.clone(); // Password for application account
+
+    config.host = Some(database.host.clone()); // Address of the database
+
+    config.port = Some(database.port); // Port at which database is running.
+
+    let pool = config
+        .create_pool(Some(deadpool_postgres::Runtime::Tokio1), NoTls)
+        .expect("Unable to create dabatabse pool.");
+
+    let _connection_testing = pool
+        .get()
+        .await
+        .expect("Connection with database cannot be established.");
+
+    info!("Creation of database pool and connection checking were successful.");
+    pool
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut result_string = String::with_capacity(2 * bytes.len());
+
+    for byte in bytes {
+        result_string.push_str(&format!("{:02x}", byte));
+    }
+
+    result_string
+}
+
+/// Decodes string produced by [encode_hex], returns `None` if it is not valid hex.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: KeyId) -> EncryptionKey {
+        EncryptionKey {
+            id,
+            key: [id + 1; keys::ENCRYPTION_KEY_LEN],
+        }
+    }
+
+    /// Ciphertext in the format used before random nonces, with nonce taken from
+    /// 'salt'.
+    fn legacy_ciphertext(key: &EncryptionKey, salt: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let machine = aes_gcm::Aes256Gcm::new(aes_gcm::Key::from_slice(&key.key));
+
+        machine
+            .encrypt(
+                Nonce::from_slice(&salt[..crate::ENCRYPT_NONCE_LEN]),
+                plaintext,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn encryption_round_trip() {
+        let encryptor = Encryptor::new(&[key(0), key(1)], 1);
+
+        let ciphertext = encryptor.encrypt(b"user@example.com").unwrap();
+
+        assert_eq!(ciphertext[0], 1);
+        assert_eq!(
+            encryptor.decrypt_with_key_id(&ciphertext, None).unwrap(),
+            (b"user@example.com".to_vec(), Some(1))
+        );
+    }
+
+    #[test]
+    fn encryption_uses_fresh_nonces() {
+        let encryptor = Encryptor::new(&[key(0)], 0);
+
+        let first = encryptor.encrypt(b"user@example.com").unwrap();
+        let second = encryptor.encrypt(b"user@example.com").unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn data_of_retired_key_is_decrypted() {
+        let old = Encryptor::new(&[key(0)], 0);
+        let rotated = Encryptor::new(&[key(0), key(1)], 1);
+
+        let ciphertext = old.encrypt(b"user@example.com").unwrap();
+
+        assert_eq!(
+            rotated.decrypt_with_key_id(&ciphertext, None).unwrap(),
+            (b"user@example.com".to_vec(), Some(0))
+        );
+    }
+
+    #[test]
+    fn data_of_unknown_key_is_rejected() {
+        let encrypted_with = Encryptor::new(&[key(1)], 1);
+        let encryptor = Encryptor::new(&[key(0)], 0);
+
+        let ciphertext = encrypted_with.encrypt(b"user@example.com").unwrap();
+
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, None),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn data_with_mismatched_key_id_is_rejected() {
+        let encryptor = Encryptor::new(&[key(0), key(1)], 0);
+
+        let mut ciphertext = encryptor.encrypt(b"user@example.com").unwrap();
+        ciphertext[0] = 1;
+
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, None),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn modified_data_is_rejected() {
+        let encryptor = Encryptor::new(&[key(0)], 0);
+
+        let mut ciphertext = encryptor.encrypt(b"user@example.com").unwrap();
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, None),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn legacy_data_without_key_id_is_decrypted() {
+        let salt = generate_salt();
+        let encryptor = Encryptor::new(&[key(0), key(1)], 1);
+        let ciphertext = legacy_ciphertext(&key(0), &salt, b"user@example.com");
+
+        assert_eq!(
+            encryptor
+                .decrypt_with_key_id(&ciphertext, Some(&salt))
+                .unwrap(),
+            (b"user@example.com".to_vec(), None)
+        );
+    }
+
+    #[test]
+    fn legacy_data_with_key_id_is_decrypted() {
+        let salt = generate_salt();
+        let encryptor = Encryptor::new(&[key(0), key(1)], 1);
+        let mut ciphertext = vec![1];
+        ciphertext.extend(legacy_ciphertext(&key(1), &salt, b"user@example.com"));
+
+        assert_eq!(
+            encryptor
+                .decrypt_with_key_id(&ciphertext, Some(&salt))
+                .unwrap(),
+            (b"user@example.com".to_vec(), None)
+        );
+    }
+
+    #[test]
+    fn legacy_data_requires_salt() {
+        let salt = generate_salt();
+        let encryptor = Encryptor::new(&[key(0)], 0);
+        let ciphertext = legacy_ciphertext(&key(0), &salt, b"user@example.com");
+
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, None),
+            Err(CryptoError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, Some(&salt[..4])),
+            Err(CryptoError::UserSaltTooSmall)
+        ));
+    }
+
+    #[test]
+    fn legacy_data_with_wrong_salt_is_rejected() {
+        let salt = generate_salt();
+        let other_salt = generate_salt();
+        let encryptor = Encryptor::new(&[key(0)], 0);
+        let ciphertext = legacy_ciphertext(&key(0), &salt, b"user@example.com");
+
+        assert!(matches!(
+            encryptor.decrypt_with_key_id(&ciphertext, Some(&other_salt)),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0x0f, 0xa5, 0xff];
+
+        assert_eq!(encode_hex(&bytes), "000fa5ff");
+        assert_eq!(decode_hex("000fa5ff"), Some(bytes.to_vec()));
+        assert_eq!(decode_hex("000FA5FF"), Some(bytes.to_vec()));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn decode_hex_rejects_invalid_input() {
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("ąb"), None);
+    }
+}
@@ -0,0 +1,3 @@
+INSERT INTO exercise_data.exercises (title, statement, limits, languages, visible, author_id)
+VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING id;
\ No newline at end of file
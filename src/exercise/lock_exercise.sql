@@ -0,0 +1,4 @@
+SELECT id
+FROM exercise_data.exercises
+WHERE id = $1
+FOR UPDATE;
\ No newline at end of file
@@ -0,0 +1,875 @@
+//! Exercises stored in `exercise_data.exercises`. Teachers create and update
+//! them; students see only visible exercises and can submit tickets only to them,
+//! in one of the allowed languages. Tests of the exercise are uploaded as
+//! packages described in [package] and stored on disk, see [crate::judge].
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use actix_multipart::Multipart;
+use actix_web::{guard, web, HttpResponse};
+use deadpool_postgres::{Object, Pool};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+
+use crate::account::{AuthenticatedUser, Role, RoleGuard, UserId};
+use crate::config::Config;
+use crate::crypto::encode_hex;
+use crate::judge::LimitOverrides;
+use crate::ticket::{ExerciseId, Language};
+
+pub mod package;
+
+/// Name of the multipart field carrying test package.
+const PACKAGE_FIELD: &str = "tests";
+
+/// Maximal length of the title in characters.
+const MAX_TITLE_LENGTH: usize = 200;
+
+#[derive(Debug)]
+pub enum ExerciseError {
+    DatabaseError,
+    WrongExerciseId,
+}
+
+#[derive(Serialize)]
+struct Exercise {
+    id: ExerciseId,
+    title: String,
+    statement: String,
+    limits: LimitOverrides,
+    languages: Vec<Language>,
+    visible: bool,
+    author_id: Option<UserId>,
+    created_at: i64,
+    updated_at: i64,
+    /// Hex encoded SHA-256 of the current test package, `None` until it is uploaded.
+    tests_hash: Option<String>,
+    tests_uploaded_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ExerciseSummary {
+    id: ExerciseId,
+    title: String,
+    languages: Vec<Language>,
+    visible: bool,
+}
+
+#[derive(Serialize)]
+struct CreatedExercise {
+    id: ExerciseId,
+}
+
+#[derive(Serialize)]
+struct UploadedPackage {
+    tests: usize,
+    tests_hash: String,
+}
+
+#[derive(Deserialize)]
+struct ExerciseForm {
+    title: String,
+    statement: String,
+    #[serde(default)]
+    limits: LimitOverrides,
+    languages: Vec<Language>,
+    /// Hidden exercises can be prepared before students see them.
+    #[serde(default)]
+    visible: bool,
+}
+
+impl ExerciseForm {
+    /// Returns description of the first problem with the form.
+    fn validate(&self) -> Result<(), String> {
+        if self.title.trim().is_empty() || self.title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(format!(
+                "Title must not be empty and must have at most {MAX_TITLE_LENGTH} characters."
+            ));
+        }
+
+        if self.languages.is_empty() {
+            return Err(String::from("At least one language has to be allowed."));
+        }
+
+        if let Some(limit) = self.limits.invalid_limit() {
+            return Err(format!("Limit '{limit}' has to be positive."));
+        }
+
+        Ok(())
+    }
+
+    /// Allowed languages as they are stored, without duplicates.
+    fn stored_languages(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+
+        self.languages
+            .iter()
+            .filter(|language| seen.insert(*language))
+            .map(Language::to_string)
+            .collect()
+    }
+}
+
+/// What is needed to accept a ticket for the exercise.
+pub struct SubmissionRules {
+    pub visible: bool,
+    pub languages: Vec<Language>,
+    /// Whether test package was uploaded, tickets cannot be judged without it.
+    pub has_tests: bool,
+}
+
+fn parse_languages(languages: Vec<String>, exercise_id: ExerciseId) -> Vec<Language> {
+    languages
+        .iter()
+        .filter_map(|language| match Language::from_str(language) {
+            Ok(language) => Some(language),
+            Err(_) => {
+                error!(
+                    "Exercise has unknown language stored. ExerciseId = {}, Language = {}",
+                    exercise_id, language
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Reads limits from 'column' of 'row'. Limits which cannot be read are logged
+/// and replaced with defaults.
+fn parse_limits(row: &Row, column: usize, exercise_id: ExerciseId) -> LimitOverrides {
+    match row.try_get::<_, Json<LimitOverrides>>(column) {
+        Ok(Json(limits)) => limits,
+        Err(error) => {
+            error!(
+                "Exercise has limits in wrong format. Using defaults. ExerciseId = {}, ERROR = {:?}",
+                exercise_id, error
+            );
+            LimitOverrides::default()
+        }
+    }
+}
+
+async fn get_client(db: &Pool) -> Result<Object, ExerciseError> {
+    match db.get().await {
+        Ok(client) => Ok(client),
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            Err(ExerciseError::DatabaseError)
+        }
+    }
+}
+
+/// Function reads visibility, allowed languages and presence of tests of the exercise.
+pub async fn get_submission_rules(
+    client: &Object,
+    exercise_id: ExerciseId,
+) -> Result<SubmissionRules, ExerciseError> {
+    let select_stmt = include_str!("query_submission_rules.sql");
+
+    match client.query_opt(select_stmt, &[&exercise_id]).await {
+        Ok(Some(row)) => Ok(SubmissionRules {
+            visible: row.get(0),
+            languages: parse_languages(row.get(1), exercise_id),
+            has_tests: row.get(2),
+        }),
+        Ok(None) => Err(ExerciseError::WrongExerciseId),
+        Err(error) => {
+            error!(
+                "Error occured while querying exercise. ExerciseId = {}, ERROR = {:?}",
+                exercise_id, error
+            );
+            Err(ExerciseError::DatabaseError)
+        }
+    }
+}
+
+/// Function reads limits set for the exercise.
+pub async fn get_limits(
+    exercise_id: ExerciseId,
+    db: &Pool,
+) -> Result<LimitOverrides, ExerciseError> {
+    let select_stmt = include_str!("query_limits.sql");
+    let client = get_client(db).await?;
+
+    match client.query_opt(select_stmt, &[&exercise_id]).await {
+        Ok(Some(row)) => Ok(parse_limits(&row, 0, exercise_id)),
+        Ok(None) => Err(ExerciseError::WrongExerciseId),
+        Err(error) => {
+            error!(
+                "Error occured while querying exercise limits. ExerciseId = {}, ERROR = {:?}",
+                exercise_id, error
+            );
+            Err(ExerciseError::DatabaseError)
+        }
+    }
+}
+
+/// Creates new exercise. Available to teachers and admins. Possible responses:
+///     HTTP 201 => Exercise was created, its id is in JSON body: `{"id": 1}`.
+///     HTTP 403 => User is not a teacher.
+///     HTTP 422 => Form is invalid, reason is in the body.
+///     HTTP 503 => Server problem, try again later.
+async fn create_exercise(
+    user: AuthenticatedUser,
+    form: web::Json<ExerciseForm>,
+    db: web::Data<Pool>,
+) -> HttpResponse {
+    let insert_stmt = include_str!("insert_exercise.sql");
+
+    if let Err(reason) = form.validate() {
+        return HttpResponse::UnprocessableEntity().body(reason);
+    }
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match client
+        .query_one(
+            insert_stmt,
+            &[
+                &form.title,
+                &form.statement,
+                &Json(&form.limits),
+                &form.stored_languages(),
+                &form.visible,
+                &user.user_id,
+            ],
+        )
+        .await
+    {
+        Ok(row) => {
+            let id: ExerciseId = row.get(0);
+            info!(
+                "Exercise created. ExerciseId = {}, UserId = {}",
+                id, user.user_id
+            );
+            HttpResponse::Created().json(CreatedExercise { id })
+        }
+        Err(error) => {
+            error!(
+                "Error occured while inserting exercise. ERROR = {:?}",
+                error
+            );
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+/// Replaces title, statement, limits, languages and visibility of the exercise.
+/// Available to teachers and admins. Possible responses:
+///     HTTP 204 => Exercise was updated.
+///     HTTP 403 => User is not a teacher.
+///     HTTP 404 => Exercise does not exist.
+///     HTTP 422 => Form is invalid, reason is in the body.
+///     HTTP 503 => Server problem, try again later.
+async fn update_exercise(
+    user: AuthenticatedUser,
+    exercise_id: web::Path<ExerciseId>,
+    form: web::Json<ExerciseForm>,
+    db: web::Data<Pool>,
+) -> HttpResponse {
+    let update_stmt = include_str!("update_exercise.sql");
+
+    if let Err(reason) = form.validate() {
+        return HttpResponse::UnprocessableEntity().body(reason);
+    }
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match client
+        .execute(
+            update_stmt,
+            &[
+                &*exercise_id,
+                &form.title,
+                &form.statement,
+                &Json(&form.limits),
+                &form.stored_languages(),
+                &form.visible,
+            ],
+        )
+        .await
+    {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => {
+            info!(
+                "Exercise updated. ExerciseId = {}, UserId = {}",
+                exercise_id, user.user_id
+            );
+            HttpResponse::NoContent().finish()
+        }
+        Err(error) => {
+            error!("Error occured while updating exercise. ERROR = {:?}", error);
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+/// Lists exercises, students see only the visible ones. Possible responses:
+///     HTTP 200 => Exercises without statements in JSON body.
+///     HTTP 503 => Server problem, try again later.
+async fn list_exercises(user: AuthenticatedUser, db: web::Data<Pool>) -> HttpResponse {
+    let select_stmt = include_str!("query_exercises.sql");
+    let show_hidden = user.role.includes(Role::Teacher);
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match client.query(select_stmt, &[&show_hidden]).await {
+        Ok(rows) => {
+            let exercises: Vec<ExerciseSummary> = rows
+                .iter()
+                .map(|row| {
+                    let id: ExerciseId = row.get(0);
+
+                    ExerciseSummary {
+                        id,
+                        title: row.get(1),
+                        languages: parse_languages(row.get(2), id),
+                        visible: row.get(3),
+                    }
+                })
+                .collect();
+
+            HttpResponse::Ok().json(exercises)
+        }
+        Err(error) => {
+            error!(
+                "Error occured while querying exercises. ERROR = {:?}",
+                error
+            );
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
+
+/// Returns the exercise with its statement. Possible responses:
+///     HTTP 200 => Exercise in JSON body.
+///     HTTP 404 => Exercise does not exist or it is hidden from the user.
+///     HTTP 503 => Server problem, try again later.
+async fn get_exercise(
+    user: AuthenticatedUser,
+    exercise_id: web::Path<ExerciseId>,
+    db: web::Data<Pool>,
+) -> HttpResponse {
+    let select_stmt = include_str!("query_exercise.sql");
+    let exercise_id = exercise_id.into_inner();
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let row = match client.query_opt(select_stmt, &[&exercise_id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(error) => {
+            error!("Error occured while querying exercise. ERROR = {:?}", error);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let visible: bool = row.get(5);
+
+    if !visible && !user.role.includes(Role::Teacher) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    HttpResponse::Ok().json(Exercise {
+        id: exercise_id,
+        title: row.get(1),
+        statement: row.get(2),
+        limits: parse_limits(&row, 3, exercise_id),
+        languages: parse_languages(row.get(4), exercise_id),
+        visible,
+        author_id: row.get(6),
+        created_at: row.get(7),
+        updated_at: row.get(8),
+        tests_hash: row
+            .get::<_, Option<Vec<u8>>>(9)
+            .map(|hash| encode_hex(&hash)),
+        tests_uploaded_at: row.get(10),
+    })
+}
+
+/// Reads package from 'tests' field of the form. Other fields are skipped.
+/// Returns `Ok(None)` when there is no such field.
+async fn read_package(payload: &mut Multipart) -> Result<Option<Vec<u8>>, HttpResponse> {
+    let bad_request = |error: actix_multipart::MultipartError| {
+        HttpResponse::BadRequest().body(format!("Malformed multipart form: {error}."))
+    };
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(bad_request)?;
+        let is_package = field.name() == PACKAGE_FIELD;
+        let mut package = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(bad_request)?;
+
+            if !is_package {
+                continue;
+            }
+
+            if package.len() + chunk.len() > package::MAX_PACKAGE_BYTES {
+                return Err(HttpResponse::PayloadTooLarge().body(format!(
+                    "Package must not be larger than {} bytes.",
+                    package::MAX_PACKAGE_BYTES
+                )));
+            }
+
+            package.extend_from_slice(&chunk);
+        }
+
+        if is_package {
+            return Ok(Some(package));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Checks that exercise exists before its package is received.
+async fn exercise_exists(client: &Object, exercise_id: ExerciseId) -> Result<bool, ()> {
+    let select_stmt = include_str!("query_exercise_id.sql");
+
+    match client.query_opt(select_stmt, &[&exercise_id]).await {
+        Ok(row) => Ok(row.is_some()),
+        Err(error) => {
+            error!("Error occured while querying exercise. ERROR = {:?}", error);
+            Err(())
+        }
+    }
+}
+
+/// Replaces test package of the exercise with tar archive sent in 'tests' field
+/// of multipart form. New tickets are judged against the new package, tickets
+/// being judged keep the package they have read. Available to teachers and admins.
+/// Possible responses:
+///     HTTP 200 => Package was stored, number of tests and hash are in JSON body.
+///     HTTP 400 => Form is malformed or it has no 'tests' field.
+///     HTTP 403 => User is not a teacher.
+///     HTTP 404 => Exercise does not exist.
+///     HTTP 413 => Package is too large.
+///     HTTP 422 => Package has invalid layout, reason is in the body.
+///     HTTP 503 => Server problem, try again later.
+async fn upload_tests(
+    user: AuthenticatedUser,
+    exercise_id: web::Path<ExerciseId>,
+    mut payload: Multipart,
+    db: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let lock_stmt = include_str!("lock_exercise.sql");
+    let update_stmt = include_str!("update_tests_hash.sql");
+    let exercise_id = exercise_id.into_inner();
+
+    let mut client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    match exercise_exists(&client, exercise_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().finish(),
+        Err(()) => return HttpResponse::ServiceUnavailable().finish(),
+    }
+
+    let package = match read_package(&mut payload).await {
+        Ok(Some(package)) => package,
+        Ok(None) => {
+            return HttpResponse::BadRequest()
+                .body(format!("Package is expected in '{PACKAGE_FIELD}' field."));
+        }
+        Err(response) => return response,
+    };
+
+    let tests = match package::validate(&package).await {
+        Ok(manifest) => manifest.test_count(),
+        Err(error) => return HttpResponse::UnprocessableEntity().body(error.to_string()),
+    };
+
+    let tests_hash = package::content_hash(&package);
+    let exercise_dir = config.tests_path.join(exercise_id.to_string());
+
+    let temporary_path = match package::write_temporary(&exercise_dir, &package).await {
+        Ok(path) => path,
+        Err(error) => {
+            error!(
+                "Error occured while writing test package. ExerciseId = {}, ERROR = {:?}",
+                exercise_id, error
+            );
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    // Row lock orders concurrent uploads, so stored hash matches the stored package.
+    let transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            error!("Unable to start transaction. ERROR = {:?}", error);
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    // Package becomes current as the last step, so it is replaced only when the
+    // hash is stored. It is restored when the transaction fails to commit.
+    let store = async {
+        if transaction
+            .query_opt(lock_stmt, &[&exercise_id])
+            .await
+            .map_err(|error| format!("{:?}", error))?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        transaction
+            .execute(update_stmt, &[&exercise_id, &tests_hash])
+            .await
+            .map_err(|error| format!("{:?}", error))?;
+
+        package::replace(&exercise_dir, &temporary_path)
+            .await
+            .map(Some)
+            .map_err(|error| format!("{:?}", error))
+    };
+
+    let previous = match store.await {
+        Ok(Some(previous)) => previous,
+        Ok(None) => {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            return HttpResponse::NotFound().finish();
+        }
+        Err(error) => {
+            error!(
+                "Error occured while storing test package. ExerciseId = {}, ERROR = {}",
+                exercise_id, error
+            );
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    if let Err(error) = transaction.commit().await {
+        error!(
+            "Error occured while committing test package. ExerciseId = {}, ERROR = {:?}",
+            exercise_id, error
+        );
+
+        if let Err(error) = package::restore(&exercise_dir, previous).await {
+            error!(
+                "Error occured while restoring previous test package. ExerciseId = {}, ERROR = {:?}",
+                exercise_id, error
+            );
+        }
+
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+
+    package::discard(previous).await;
+
+    let tests_hash = encode_hex(&tests_hash);
+    info!(
+        "Test package uploaded. ExerciseId = {}, Tests = {}, Hash = {}, UserId = {}",
+        exercise_id, tests, tests_hash, user.user_id
+    );
+
+    HttpResponse::Ok().json(UploadedPackage { tests, tests_hash })
+}
+
+/// Function is used to handle "/exercise" route. Every logged in user can read
+/// exercises, only teachers and admins can change them.
+pub fn exercise_handler(cfg: &mut web::ServiceConfig) {
+    // Scope with changes does not match other methods, so reads fall through.
+    cfg.service(
+        web::scope("")
+            .guard(guard::Any(guard::Post()).or(guard::Put()))
+            .wrap(RoleGuard::new(Role::Teacher))
+            .route("", web::post().to(create_exercise))
+            .route("/{exercise_id}", web::put().to(update_exercise))
+            .route("/{exercise_id}/tests", web::put().to(upload_tests)),
+    );
+    cfg.service(
+        web::scope("")
+            .wrap(RoleGuard::new(Role::Student))
+            .route("", web::get().to(list_exercises))
+            .route("/{exercise_id}", web::get().to(get_exercise)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::{Service as _, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{App, HttpMessage};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::migrations::test_database;
+
+    fn form(json: Value) -> Result<ExerciseForm, serde_json::Error> {
+        serde_json::from_value(json)
+    }
+
+    fn valid_form() -> Value {
+        json!({"title": "Sum", "statement": "Add two numbers.", "languages": ["C"]})
+    }
+
+    /// Sends request to exercise routes as 'user'.
+    async fn send(db: &Pool, user: AuthenticatedUser, req: TestRequest) -> ServiceResponse {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .route("/exercise", web::post().to(create_exercise))
+                .route("/exercise", web::get().to(list_exercises))
+                .route("/exercise/{exercise_id}", web::put().to(update_exercise))
+                .route("/exercise/{exercise_id}", web::get().to(get_exercise))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(user);
+                    srv.call(req)
+                }),
+        )
+        .await;
+
+        test::call_service(&app, req.to_request()).await
+    }
+
+    /// Inserts teacher who authors exercises of the test.
+    async fn create_teacher(db: &Pool) -> AuthenticatedUser {
+        let user_id = UserId::from(rand::random::<u32>());
+
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "INSERT INTO user_data.users (id, username, password_hash, user_salt, email, role)
+                VALUES ($1, $2, '', '', '', 'Teacher')",
+                &[&user_id, &format!("exercise-test-{user_id}")],
+            )
+            .await
+            .unwrap();
+
+        AuthenticatedUser {
+            user_id,
+            role: Role::Teacher,
+        }
+    }
+
+    async fn create(db: &Pool, teacher: AuthenticatedUser, form: Value) -> ExerciseId {
+        let response = send(
+            db,
+            teacher,
+            TestRequest::post().uri("/exercise").set_json(form),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let created: Value = test::read_body_json(response).await;
+        created["id"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn valid_form_is_accepted() {
+        let form = form(valid_form()).unwrap();
+
+        assert!(form.validate().is_ok());
+        assert!(!form.visible);
+    }
+
+    #[test]
+    fn title_has_to_be_short_and_not_blank() {
+        for title in [String::from(" "), "a".repeat(MAX_TITLE_LENGTH + 1)] {
+            let mut json = valid_form();
+            json["title"] = json!(title);
+
+            assert!(form(json).unwrap().validate().is_err());
+        }
+
+        let mut json = valid_form();
+        json["title"] = json!("á".repeat(MAX_TITLE_LENGTH));
+        assert!(form(json).unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn some_language_has_to_be_allowed() {
+        let mut json = valid_form();
+        json["languages"] = json!([]);
+
+        assert!(form(json).unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn limits_have_to_be_known_and_positive() {
+        let mut json = valid_form();
+        json["limits"] = json!({"memory_mb": 0});
+        assert_eq!(
+            form(json).unwrap().validate(),
+            Err(String::from("Limit 'memory_mb' has to be positive."))
+        );
+
+        let mut json = valid_form();
+        json["limits"] = json!({"memory": 256});
+        assert!(form(json).is_err());
+    }
+
+    #[test]
+    fn languages_are_stored_once() {
+        let mut json = valid_form();
+        json["languages"] = json!(["Rust", "C", "Rust"]);
+
+        assert_eq!(form(json).unwrap().stored_languages(), ["Rust", "C"]);
+    }
+
+    #[test]
+    fn unknown_stored_languages_are_skipped() {
+        let languages = vec![String::from("Cpp"), String::from("Java")];
+
+        assert_eq!(parse_languages(languages, 1), [Language::Cpp]);
+    }
+
+    #[actix_web::test]
+    async fn hidden_exercises_are_shown_only_to_teachers() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let teacher = create_teacher(&db).await;
+        let student = AuthenticatedUser {
+            user_id: teacher.user_id,
+            role: Role::Student,
+        };
+        let hidden = create(&db, teacher, valid_form()).await;
+        let mut visible_form = valid_form();
+        visible_form["visible"] = json!(true);
+        let visible = create(&db, teacher, visible_form).await;
+
+        let ids = |exercises: Value| -> Vec<i64> {
+            exercises
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|exercise| exercise["id"].as_i64().unwrap())
+                .collect()
+        };
+        let listed_to_student = ids(test::read_body_json(
+            send(&db, student, TestRequest::get().uri("/exercise")).await,
+        )
+        .await);
+        let listed_to_teacher = ids(test::read_body_json(
+            send(&db, teacher, TestRequest::get().uri("/exercise")).await,
+        )
+        .await);
+
+        assert!(listed_to_student.contains(&visible) && !listed_to_student.contains(&hidden));
+        assert!(listed_to_teacher.contains(&visible) && listed_to_teacher.contains(&hidden));
+
+        let hidden_uri = format!("/exercise/{hidden}");
+        let to_student = send(&db, student, TestRequest::get().uri(&hidden_uri)).await;
+        let to_teacher = send(&db, teacher, TestRequest::get().uri(&hidden_uri)).await;
+
+        assert_eq!(to_student.status(), StatusCode::NOT_FOUND);
+        assert_eq!(to_teacher.status(), StatusCode::OK);
+        let exercise: Value = test::read_body_json(to_teacher).await;
+        assert_eq!(exercise["author_id"], teacher.user_id);
+        assert_eq!(exercise["tests_hash"], Value::Null);
+    }
+
+    #[actix_web::test]
+    async fn updated_exercise_has_new_rules_and_limits() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let teacher = create_teacher(&db).await;
+        let exercise_id = create(&db, teacher, valid_form()).await;
+        let mut json = valid_form();
+        json["languages"] = json!(["Rust"]);
+        json["limits"] = json!({"wall_time_secs": 5});
+        json["visible"] = json!(true);
+
+        let response = send(
+            &db,
+            teacher,
+            TestRequest::put()
+                .uri(&format!("/exercise/{exercise_id}"))
+                .set_json(json),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let client = db.get().await.unwrap();
+        let rules = get_submission_rules(&client, exercise_id).await.unwrap();
+        let limits = get_limits(exercise_id, &db).await.unwrap();
+
+        assert!(rules.visible && !rules.has_tests);
+        assert_eq!(rules.languages, [Language::Rust]);
+        assert_eq!(limits.wall_time_secs, Some(5));
+        assert_eq!(limits.memory_mb, None);
+    }
+
+    #[actix_web::test]
+    async fn missing_exercise_is_not_found() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let teacher = create_teacher(&db).await;
+
+        let response = send(
+            &db,
+            teacher,
+            TestRequest::put()
+                .uri("/exercise/-1")
+                .set_json(valid_form()),
+        )
+        .await;
+        let client = db.get().await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(matches!(
+            get_submission_rules(&client, -1).await,
+            Err(ExerciseError::WrongExerciseId)
+        ));
+        assert!(matches!(
+            get_limits(-1, &db).await,
+            Err(ExerciseError::WrongExerciseId)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn invalid_form_is_rejected_before_database() {
+        let teacher = AuthenticatedUser {
+            user_id: 1,
+            role: Role::Teacher,
+        };
+        let mut json = valid_form();
+        json["languages"] = json!([]);
+
+        // Pool is never connected to.
+        let mut config = deadpool_postgres::Config::new();
+        config.dbname = Some(String::from("alsit_test"));
+        let db = config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap();
+        let response = send(
+            &db,
+            teacher,
+            TestRequest::post().uri("/exercise").set_json(json),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,498 @@
+//! Test packages of exercises, uploaded as tar archives.
+//!
+//! Package contains pairs of regular files `<test>.in` (input) and `<test>.out`
+//! (expected output) in its root directory, where test name consists of letters,
+//! digits, '_' and '-', and optionally `manifest.json` described in
+//! [crate::judge::manifest] together with source of the custom checker it names.
+//! Anything else, including links and nested directories, is rejected, so
+//! package can be safely unpacked into the sandbox.
+use std::fmt::Display;
+use std::path::{Component, Path, PathBuf};
+
+use async_tar::{Archive, EntryType};
+use futures::{AsyncReadExt, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::judge::manifest::{Manifest, ManifestError, MANIFEST_FILE};
+
+/// Maximal size of uploaded package in bytes.
+pub const MAX_PACKAGE_BYTES: usize = 64 * 1024 * 1024;
+/// Maximal number of tests in the package.
+const MAX_TESTS: usize = 1000;
+/// Maximal length of test name.
+const MAX_TEST_NAME_LENGTH: usize = 64;
+/// Name of the package in exercise directory.
+pub const PACKAGE_FILE: &str = "tests.tar";
+
+#[derive(Debug)]
+pub enum PackageError {
+    /// Archive cannot be read.
+    Malformed(String),
+    /// Entry is a link, device or directory.
+    UnsupportedEntry(String),
+    /// Entry escapes root directory or has disallowed name.
+    InvalidName(String),
+    /// Test has input without expected output or the other way round.
+    UnpairedFile(String),
+    DuplicateFile(String),
+    Empty,
+    TooManyTests,
+    /// Manifest names checker source which is not in the package.
+    MissingChecker(String),
+    Manifest(ManifestError),
+}
+
+impl Display for PackageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageError::Malformed(reason) => write!(f, "Archive cannot be read: {reason}."),
+            PackageError::UnsupportedEntry(path) => {
+                write!(f, "'{path}' is not a regular file.")
+            }
+            PackageError::InvalidName(path) => write!(
+                f,
+                "'{path}' is not a valid test file name, expected '<test>.in', '<test>.out' or source of the checker in root directory."
+            ),
+            PackageError::UnpairedFile(path) => {
+                write!(f, "'{path}' has no matching input or expected output.")
+            }
+            PackageError::DuplicateFile(path) => write!(f, "'{path}' occurs more than once."),
+            PackageError::Empty => write!(f, "Package contains no tests."),
+            PackageError::TooManyTests => {
+                write!(f, "Package contains more than {MAX_TESTS} tests.")
+            }
+            PackageError::MissingChecker(path) => {
+                write!(f, "Checker source '{path}' is not in the package.")
+            }
+            PackageError::Manifest(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+/// Splits file name into test name and whether it is an input file.
+fn parse_test_file(name: &str) -> Option<(&str, bool)> {
+    let (test, is_input) = match name.rsplit_once('.')? {
+        (test, "in") => (test, true),
+        (test, "out") => (test, false),
+        _ => return None,
+    };
+
+    let valid = !test.is_empty()
+        && test.len() <= MAX_TEST_NAME_LENGTH
+        && test
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    valid.then_some((test, is_input))
+}
+
+/// Returns file name of the entry in root directory, `None` for the root itself.
+fn root_file_name(path: &Path) -> Result<Option<String>, PackageError> {
+    let invalid = || PackageError::InvalidName(path.display().to_string());
+    let mut components = path
+        .components()
+        .filter(|component| *component != Component::CurDir);
+
+    let name = match components.next() {
+        Some(Component::Normal(name)) => name.to_str().ok_or_else(invalid)?,
+        Some(_) => return Err(invalid()),
+        None => return Ok(None),
+    };
+
+    if components.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(Some(String::from(name)))
+}
+
+/// Checks layout of the package. Returns its manifest, or the default one when
+/// package has no manifest.
+pub async fn validate(package: &[u8]) -> Result<Manifest, PackageError> {
+    let malformed = |error: std::io::Error| PackageError::Malformed(error.to_string());
+
+    let mut entries = Archive::new(package).entries().map_err(malformed)?;
+    let mut inputs = std::collections::HashSet::new();
+    let mut outputs = std::collections::HashSet::new();
+    let mut manifest = None;
+    // Files which are not tests, they may be source of the checker.
+    let mut other_files = std::collections::HashSet::new();
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.map_err(malformed)?;
+        let path: PathBuf = entry.path().map_err(malformed)?.into_owned().into();
+        let entry_type = entry.header().entry_type();
+
+        let name = match root_file_name(&path)? {
+            None if entry_type == EntryType::Directory => continue,
+            None => return Err(PackageError::InvalidName(path.display().to_string())),
+            Some(name) => name,
+        };
+
+        if entry_type != EntryType::Regular {
+            return Err(PackageError::UnsupportedEntry(name));
+        }
+
+        if name == MANIFEST_FILE {
+            if manifest.is_some() {
+                return Err(PackageError::DuplicateFile(name));
+            }
+
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).await.map_err(malformed)?;
+            manifest = Some(Manifest::parse(&content).map_err(PackageError::Manifest)?);
+            continue;
+        }
+
+        let (test, is_input) = match parse_test_file(&name) {
+            Some(test_file) => test_file,
+            None => {
+                if !other_files.insert(name.clone()) {
+                    return Err(PackageError::DuplicateFile(name));
+                }
+                continue;
+            }
+        };
+
+        let files = if is_input { &mut inputs } else { &mut outputs };
+
+        if !files.insert(String::from(test)) {
+            return Err(PackageError::DuplicateFile(name));
+        }
+
+        if inputs.len() > MAX_TESTS || outputs.len() > MAX_TESTS {
+            return Err(PackageError::TooManyTests);
+        }
+    }
+
+    if let Some(test) = inputs.difference(&outputs).next() {
+        return Err(PackageError::UnpairedFile(format!("{test}.in")));
+    }
+
+    if let Some(test) = outputs.difference(&inputs).next() {
+        return Err(PackageError::UnpairedFile(format!("{test}.out")));
+    }
+
+    if inputs.is_empty() {
+        return Err(PackageError::Empty);
+    }
+
+    let manifest = manifest.unwrap_or_else(|| Manifest::for_tests(&inputs));
+    manifest.validate(&inputs).map_err(PackageError::Manifest)?;
+
+    let checker_source = manifest.checker.source();
+
+    if let Some(file) = other_files
+        .iter()
+        .find(|file| Some(file.as_str()) != checker_source)
+    {
+        return Err(PackageError::InvalidName(file.clone()));
+    }
+
+    if let Some(source) = checker_source {
+        if !other_files.contains(source) {
+            return Err(PackageError::MissingChecker(String::from(source)));
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// SHA-256 of the package, identifying its version.
+pub fn content_hash(package: &[u8]) -> Vec<u8> {
+    Sha256::digest(package).to_vec()
+}
+
+/// Writes package next to the current one in 'exercise_dir'. It becomes
+/// current after [replace] is called with the returned path.
+pub async fn write_temporary(exercise_dir: &Path, package: &[u8]) -> std::io::Result<PathBuf> {
+    use rand::RngCore;
+
+    tokio::fs::create_dir_all(exercise_dir).await?;
+
+    let mut suffix = [0u8; 8];
+    rand::rngs::OsRng.fill_bytes(&mut suffix);
+    let temporary_path = exercise_dir.join(format!(
+        "{PACKAGE_FILE}.upload-{}",
+        crate::crypto::encode_hex(&suffix)
+    ));
+
+    let write = async {
+        let mut file = tokio::fs::File::create(&temporary_path).await?;
+        file.write_all(package).await?;
+        file.sync_all().await
+    };
+
+    match write.await {
+        Ok(()) => Ok(temporary_path),
+        Err(error) => {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            Err(error)
+        }
+    }
+}
+
+/// Atomically replaces current package of the exercise with the temporary one,
+/// judges read either the old or the new package as a whole. Previous package is
+/// kept aside, so replacement can be undone with [restore] until it is dropped
+/// with [discard]; its path is returned, `None` if there was no package.
+pub async fn replace(
+    exercise_dir: &Path,
+    temporary_path: &Path,
+) -> std::io::Result<Option<PathBuf>> {
+    let current_path = exercise_dir.join(PACKAGE_FILE);
+    let mut previous_path = temporary_path.as_os_str().to_owned();
+    previous_path.push(".previous");
+    let previous_path = PathBuf::from(previous_path);
+
+    // Link keeps current package in place until it is replaced by rename.
+    let previous = match tokio::fs::hard_link(&current_path, &previous_path).await {
+        Ok(()) => Some(previous_path),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+
+    if let Err(error) = tokio::fs::rename(temporary_path, &current_path).await {
+        discard(previous).await;
+        return Err(error);
+    }
+
+    Ok(previous)
+}
+
+/// Brings back package which was current before [replace] returned 'previous'.
+pub async fn restore(exercise_dir: &Path, previous: Option<PathBuf>) -> std::io::Result<()> {
+    let current_path = exercise_dir.join(PACKAGE_FILE);
+
+    match previous {
+        Some(previous_path) => tokio::fs::rename(previous_path, current_path).await,
+        None => tokio::fs::remove_file(current_path).await,
+    }
+}
+
+/// Removes package kept aside by [replace].
+pub async fn discard(previous: Option<PathBuf>) {
+    if let Some(previous_path) = previous {
+        let _ = tokio::fs::remove_file(previous_path).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tar::Header;
+
+    use super::*;
+
+    /// Builds tar archive from entries of given path, type and content. Paths are
+    /// written as they are, so they can escape root directory.
+    fn archive(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+
+        for (path, entry_type, content) in entries {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+
+            archive.extend_from_slice(header.as_bytes());
+            archive.extend_from_slice(content);
+            archive.resize(archive.len().next_multiple_of(512), 0);
+        }
+
+        archive.resize(archive.len() + 1024, 0);
+        archive
+    }
+
+    fn files(paths: &[&str]) -> Vec<u8> {
+        let entries: Vec<(&str, EntryType, &[u8])> = paths
+            .iter()
+            .map(|path| (*path, EntryType::Regular, b"1\n".as_slice()))
+            .collect();
+
+        archive(&entries)
+    }
+
+    #[tokio::test]
+    async fn package_without_manifest_gets_group_for_every_test() {
+        let manifest = validate(&files(&["./b.out", "b.in", "a.in", "a.out"]))
+            .await
+            .unwrap();
+
+        let groups: Vec<&str> = manifest
+            .groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(groups, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn root_directory_entry_is_skipped() {
+        let package = archive(&[
+            ("./", EntryType::Directory, b""),
+            ("a.in", EntryType::Regular, b"1"),
+            ("a.out", EntryType::Regular, b"1"),
+        ]);
+
+        assert!(validate(&package).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn path_traversal_is_rejected() {
+        let result = validate(&files(&["../a.in", "a.out"])).await;
+
+        assert!(matches!(result, Err(PackageError::InvalidName(_))));
+    }
+
+    #[tokio::test]
+    async fn nested_files_are_rejected() {
+        let result = validate(&files(&["tests/a.in", "tests/a.out"])).await;
+
+        assert!(matches!(result, Err(PackageError::InvalidName(_))));
+    }
+
+    #[tokio::test]
+    async fn absolute_paths_are_rejected() {
+        let result = validate(&files(&["/a.in", "/a.out"])).await;
+
+        assert!(matches!(result, Err(PackageError::InvalidName(_))));
+    }
+
+    #[tokio::test]
+    async fn links_are_rejected() {
+        for entry_type in [EntryType::Symlink, EntryType::Link] {
+            let package = archive(&[
+                ("a.in", entry_type, b""),
+                ("a.out", EntryType::Regular, b"1"),
+            ]);
+
+            let result = validate(&package).await;
+
+            assert!(matches!(result, Err(PackageError::UnsupportedEntry(name)) if name == "a.in"));
+        }
+    }
+
+    #[tokio::test]
+    async fn unpaired_files_are_rejected() {
+        let result = validate(&files(&["a.in", "a.out", "b.in"])).await;
+        assert!(matches!(result, Err(PackageError::UnpairedFile(name)) if name == "b.in"));
+
+        let result = validate(&files(&["a.in", "a.out", "b.out"])).await;
+        assert!(matches!(result, Err(PackageError::UnpairedFile(name)) if name == "b.out"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_files_are_rejected() {
+        let result = validate(&files(&["a.in", "a.out", "./a.in"])).await;
+
+        assert!(matches!(result, Err(PackageError::DuplicateFile(name)) if name == "a.in"));
+    }
+
+    #[tokio::test]
+    async fn empty_package_is_rejected() {
+        assert!(matches!(
+            validate(&archive(&[])).await,
+            Err(PackageError::Empty)
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_test_names_are_rejected() {
+        let result = validate(&files(&["a.in", "a.out", "a b.in"])).await;
+
+        assert!(matches!(result, Err(PackageError::InvalidName(name)) if name == "a b.in"));
+    }
+
+    fn with_manifest(manifest: &str, other_paths: &[&str]) -> Vec<u8> {
+        let mut entries: Vec<(&str, EntryType, &[u8])> = vec![
+            (MANIFEST_FILE, EntryType::Regular, manifest.as_bytes()),
+            ("a.in", EntryType::Regular, b"1"),
+            ("a.out", EntryType::Regular, b"1"),
+        ];
+        entries.extend(
+            other_paths
+                .iter()
+                .map(|path| (*path, EntryType::Regular, b"".as_slice())),
+        );
+
+        archive(&entries)
+    }
+
+    const CUSTOM_CHECKER: &str = r#"{
+        "checker": {"kind": "custom", "source": "checker.cpp", "language": "Cpp"},
+        "groups": [{"name": "all", "points": 1, "tests": ["a"]}]
+    }"#;
+
+    #[tokio::test]
+    async fn checker_source_is_allowed() {
+        let manifest = validate(&with_manifest(CUSTOM_CHECKER, &["checker.cpp"]))
+            .await
+            .unwrap();
+
+        assert_eq!(manifest.checker.source(), Some("checker.cpp"));
+    }
+
+    #[tokio::test]
+    async fn missing_checker_source_is_rejected() {
+        let result = validate(&with_manifest(CUSTOM_CHECKER, &[])).await;
+
+        assert!(
+            matches!(result, Err(PackageError::MissingChecker(source)) if source == "checker.cpp")
+        );
+    }
+
+    #[tokio::test]
+    async fn other_files_are_rejected() {
+        let result = validate(&with_manifest(
+            CUSTOM_CHECKER,
+            &["checker.cpp", "notes.txt"],
+        ))
+        .await;
+        assert!(matches!(result, Err(PackageError::InvalidName(name)) if name == "notes.txt"));
+
+        let result = validate(&files(&["a.in", "a.out", "checker.cpp"])).await;
+        assert!(matches!(result, Err(PackageError::InvalidName(name)) if name == "checker.cpp"));
+    }
+
+    #[tokio::test]
+    async fn invalid_manifest_is_rejected() {
+        let manifest = r#"{"groups": [{"name": "all", "tests": ["a", "b"]}]}"#;
+
+        let result = validate(&with_manifest(manifest, &[])).await;
+
+        assert!(matches!(
+            result,
+            Err(PackageError::Manifest(ManifestError::UnknownTest(test))) if test == "b"
+        ));
+    }
+
+    #[tokio::test]
+    async fn replacement_can_be_restored() {
+        let exercise_dir =
+            std::env::temp_dir().join(format!("alsit-package-{}", std::process::id()));
+        let _ = tokio::fs::remove_dir_all(&exercise_dir).await;
+        let current_path = exercise_dir.join(PACKAGE_FILE);
+
+        let first = write_temporary(&exercise_dir, b"first").await.unwrap();
+        let previous = replace(&exercise_dir, &first).await.unwrap();
+        assert!(previous.is_none());
+
+        let second = write_temporary(&exercise_dir, b"second").await.unwrap();
+        let previous = replace(&exercise_dir, &second).await.unwrap();
+        assert_eq!(tokio::fs::read(&current_path).await.unwrap(), b"second");
+
+        restore(&exercise_dir, previous).await.unwrap();
+        assert_eq!(tokio::fs::read(&current_path).await.unwrap(), b"first");
+
+        restore(&exercise_dir, None).await.unwrap();
+        assert!(!current_path.exists());
+
+        let mut left = tokio::fs::read_dir(&exercise_dir).await.unwrap();
+        assert!(left.next_entry().await.unwrap().is_none());
+
+        let _ = tokio::fs::remove_dir_all(&exercise_dir).await;
+    }
+}
@@ -0,0 +1,5 @@
+SELECT id, title, statement, limits, languages, visible, author_id,
+    EXTRACT(EPOCH FROM created_at)::BIGINT, EXTRACT(EPOCH FROM updated_at)::BIGINT,
+    tests_hash, EXTRACT(EPOCH FROM tests_uploaded_at)::BIGINT
+FROM exercise_data.exercises
+WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT id
+FROM exercise_data.exercises
+WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,4 @@
+SELECT id, title, languages, visible
+FROM exercise_data.exercises
+WHERE $1 OR visible
+ORDER BY id;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT visible, languages, tests_hash IS NOT NULL
+FROM exercise_data.exercises
+WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,3 @@
+UPDATE exercise_data.exercises
+SET title = $2, statement = $3, limits = $4, languages = $5, visible = $6, updated_at = now()
+WHERE id = $1;
\ No newline at end of file
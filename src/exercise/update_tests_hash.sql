@@ -0,0 +1,3 @@
+UPDATE exercise_data.exercises
+SET tests_hash = $2, tests_uploaded_at = now()
+WHERE id = $1;
\ No newline at end of file
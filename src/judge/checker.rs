@@ -0,0 +1,868 @@
+//! Checking outputs of the submission against expected outputs from the test
+//! package, with the checker chosen in [super::manifest].
+//!
+//! Testing program reports test as `OK` when the submission finished within
+//! limits and leaves its output in output directory (see [super::sandbox]).
+//! Output of every such test is checked by the judge: built-in checkers run in
+//! the server, custom checker is compiled from the package and run on the judging
+//! machine as `<checker> <input> <expected output> <output of submission>`, with
+//! time and memory limits of the test. It writes `OK` or `WA` to stdout, optionally
+//! followed by score of the test from 0 to 1, for example `WA 0.5`.
+//!
+//! Test gets verdict IE when its output is missing, or when custom checker does
+//! not compile, exceeds limits, exits with error or writes anything else.
+//! Compilers of checker languages (`gcc`, `g++`, `rustc`) have to be installed
+//! on the judging machine.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures::{AsyncReadExt, StreamExt};
+use tokio::process::Command;
+
+use super::manifest::{CheckerKind, Manifest};
+use super::sandbox::set_limit;
+use crate::ticket::results::{TestResult, Verdict};
+use crate::ticket::{Language, TicketId};
+
+/// Wall time limit of compilation of custom checker.
+const CHECKER_COMPILATION_TIME: Duration = Duration::from_secs(30);
+/// Maximal number of bytes of checker's stdout which are read.
+const MAX_CHECKER_OUTPUT_LEN: u64 = 4096;
+/// Name of compiled custom checker in its directory.
+const CHECKER_BINARY: &str = "checker";
+
+/// Outcome of checking output of the submission on a single test.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CheckResult {
+    /// `Ok`, `WrongAnswer`, or `InternalError` when output could not be checked.
+    pub verdict: Verdict,
+    /// Partial score of the test, reported only by custom checkers.
+    pub score: Option<f64>,
+}
+
+impl CheckResult {
+    fn accepted(accepted: bool) -> CheckResult {
+        CheckResult {
+            verdict: if accepted {
+                Verdict::Ok
+            } else {
+                Verdict::WrongAnswer
+            },
+            score: None,
+        }
+    }
+
+    fn internal_error() -> CheckResult {
+        CheckResult {
+            verdict: Verdict::InternalError,
+            score: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum CheckerError {
+    Io(std::io::Error),
+    /// Compiler exited with error, its stderr is kept.
+    Compilation(String),
+    TimedOut,
+    /// Checker exited with error or was killed by a signal.
+    Failed(Option<i32>),
+    /// Checker wrote something else than verdict.
+    InvalidOutput(String),
+}
+
+impl std::fmt::Display for CheckerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckerError::Io(error) => write!(f, "{error}"),
+            CheckerError::Compilation(output) => {
+                write!(f, "Checker does not compile: {output}")
+            }
+            CheckerError::TimedOut => write!(f, "Checker exceeded time limit."),
+            CheckerError::Failed(Some(code)) => write!(f, "Checker exited with code {code}."),
+            CheckerError::Failed(None) => write!(f, "Checker was killed by a signal."),
+            CheckerError::InvalidOutput(output) => {
+                write!(f, "Checker wrote invalid verdict: {output:?}")
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for CheckerError {
+    fn from(error: std::io::Error) -> Self {
+        CheckerError::Io(error)
+    }
+}
+
+/// Checks outputs of tests which testing program reported as `OK` and sets their
+/// verdicts and scores. 'package' is tar with tests of the exercise.
+pub async fn check_outputs(
+    manifest: &Manifest,
+    package: &[u8],
+    tests: &mut [TestResult],
+    outputs: &HashMap<String, Vec<u8>>,
+    ticket_id: TicketId,
+) {
+    let names: Vec<String> = tests
+        .iter()
+        .filter(|test| test.verdict == Verdict::Ok)
+        .map(|test| test.name.clone())
+        .collect();
+
+    if names.is_empty() {
+        return;
+    }
+
+    let checks = match &manifest.checker {
+        CheckerKind::Custom { source, language } => {
+            let checker = CustomCheck {
+                manifest,
+                source,
+                language,
+                ticket_id,
+            };
+            checker.check_all(package, &names, outputs).await
+        }
+        checker => check_all_builtin(checker, package, &names, outputs, ticket_id).await,
+    };
+
+    for test in tests.iter_mut().filter(|test| test.verdict == Verdict::Ok) {
+        let check = checks
+            .get(&test.name)
+            .copied()
+            .unwrap_or_else(CheckResult::internal_error);
+
+        test.verdict = check.verdict;
+        test.score = check.score;
+    }
+}
+
+/// Checks 'output' against 'expected' with built-in checker, `None` for custom one.
+pub fn check_builtin(checker: &CheckerKind, expected: &[u8], output: &[u8]) -> Option<bool> {
+    let accepted = match checker {
+        CheckerKind::Exact => expected == output,
+        CheckerKind::Tokens => tokens(expected).eq(tokens(output)),
+        CheckerKind::Whitespace => lines(expected) == lines(output),
+        CheckerKind::Float { epsilon } => floats_match(expected, output, *epsilon),
+        CheckerKind::Custom { .. } => return None,
+    };
+
+    Some(accepted)
+}
+
+fn tokens(text: &[u8]) -> impl Iterator<Item = &[u8]> {
+    text.split(u8::is_ascii_whitespace)
+        .filter(|token| !token.is_empty())
+}
+
+/// Tokens of every line, without empty lines at the end.
+fn lines(text: &[u8]) -> Vec<Vec<&[u8]>> {
+    let mut lines: Vec<Vec<&[u8]>> = text
+        .split(|byte| *byte == b'\n')
+        .map(|line| tokens(line).collect())
+        .collect();
+
+    while lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+
+    lines
+}
+
+fn floats_match(expected: &[u8], output: &[u8], epsilon: f64) -> bool {
+    let mut expected = tokens(expected);
+    let mut output = tokens(output);
+
+    loop {
+        match (expected.next(), output.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(output)) if float_tokens_match(expected, output, epsilon) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Tokens which are not finite numbers have to be equal.
+fn float_tokens_match(expected: &[u8], output: &[u8], epsilon: f64) -> bool {
+    if expected == output {
+        return true;
+    }
+
+    match (parse_float(expected), parse_float(output)) {
+        (Some(expected), Some(output)) => {
+            let difference = (expected - output).abs();
+            difference <= epsilon || difference <= epsilon * expected.abs()
+        }
+        _ => false,
+    }
+}
+
+fn parse_float(token: &[u8]) -> Option<f64> {
+    std::str::from_utf8(token)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+async fn check_all_builtin(
+    checker: &CheckerKind,
+    package: &[u8],
+    names: &[String],
+    outputs: &HashMap<String, Vec<u8>>,
+    ticket_id: TicketId,
+) -> HashMap<String, CheckResult> {
+    let expected_outputs = match read_expected_outputs(package, names).await {
+        Ok(expected_outputs) => expected_outputs,
+        Err(error) => {
+            error!(
+                "Error occured while reading expected outputs. TicketId = {}, ERROR = {:?}",
+                ticket_id, error
+            );
+            return HashMap::new();
+        }
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            let check = match (expected_outputs.get(name), outputs.get(name)) {
+                (Some(expected), Some(output)) => check_builtin(checker, expected, output)
+                    .map_or_else(CheckResult::internal_error, CheckResult::accepted),
+                _ => {
+                    warn!(
+                        "Testing program did not write output of passed test. TicketId = {}, Test = {}",
+                        ticket_id, name
+                    );
+                    CheckResult::internal_error()
+                }
+            };
+
+            (name.clone(), check)
+        })
+        .collect()
+}
+
+/// Reads expected outputs of tests 'names' from the package.
+async fn read_expected_outputs(
+    package: &[u8],
+    names: &[String],
+) -> std::io::Result<HashMap<String, Vec<u8>>> {
+    let mut entries = async_tar::Archive::new(package).entries()?;
+    let mut expected_outputs = HashMap::new();
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // Package is validated, so all tests are in its root directory.
+        let test = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".out"))
+        {
+            Some(test) if names.iter().any(|name| name == test) => String::from(test),
+            _ => continue,
+        };
+
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).await?;
+        expected_outputs.insert(test, content);
+    }
+
+    Ok(expected_outputs)
+}
+
+/// Checking of a ticket with custom checker.
+struct CustomCheck<'a> {
+    manifest: &'a Manifest,
+    source: &'a str,
+    language: &'a Language,
+    ticket_id: TicketId,
+}
+
+impl CustomCheck<'_> {
+    /// Directory in which package is unpacked and checker is compiled.
+    fn dir(&self) -> PathBuf {
+        std::env::temp_dir().join(format!("alsit-checker-{}", self.ticket_id))
+    }
+
+    async fn check_all(
+        &self,
+        package: &[u8],
+        names: &[String],
+        outputs: &HashMap<String, Vec<u8>>,
+    ) -> HashMap<String, CheckResult> {
+        let dir = self.dir();
+        // Directory may be left over from previous judging of the same ticket.
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        let checks = match self.check_in(&dir, package, names, outputs).await {
+            Ok(checks) => checks,
+            Err(error) => {
+                error!(
+                    "Error occured while running custom checker. TicketId = {}, ERROR = {}",
+                    self.ticket_id, error
+                );
+                HashMap::new()
+            }
+        };
+
+        if let Err(error) = tokio::fs::remove_dir_all(&dir).await {
+            error!(
+                "Error occured while removing checker directory. TicketId = {}, ERROR = {}",
+                self.ticket_id, error
+            );
+        }
+
+        checks
+    }
+
+    async fn check_in(
+        &self,
+        dir: &Path,
+        package: &[u8],
+        names: &[String],
+        outputs: &HashMap<String, Vec<u8>>,
+    ) -> Result<HashMap<String, CheckResult>, CheckerError> {
+        let tests_dir = dir.join("tests");
+        let outputs_dir = dir.join("outputs");
+
+        tokio::fs::create_dir_all(&outputs_dir).await?;
+        async_tar::Archive::new(package).unpack(&tests_dir).await?;
+
+        let checker =
+            CustomChecker::compile(&tests_dir.join(self.source), self.language, dir).await?;
+
+        let mut checks = HashMap::new();
+
+        for name in names {
+            let output = match outputs.get(name) {
+                Some(output) => output,
+                None => {
+                    warn!(
+                        "Testing program did not write output of passed test. TicketId = {}, Test = {}",
+                        self.ticket_id, name
+                    );
+                    checks.insert(name.clone(), CheckResult::internal_error());
+                    continue;
+                }
+            };
+
+            let output_path = outputs_dir.join(format!("{name}.out"));
+            tokio::fs::write(&output_path, output).await?;
+
+            let (time_limit_ms, memory_limit_mb) = self.manifest.limits_of(name);
+            let files = [
+                tests_dir.join(format!("{name}.in")),
+                tests_dir.join(format!("{name}.out")),
+                output_path,
+            ];
+
+            let check = match checker
+                .run(
+                    &files,
+                    Duration::from_millis(time_limit_ms),
+                    memory_limit_mb,
+                )
+                .await
+            {
+                Ok(check) => check,
+                Err(error) => {
+                    warn!(
+                        "Custom checker failed. TicketId = {}, Test = {}, ERROR = {}",
+                        self.ticket_id, name, error
+                    );
+                    CheckResult::internal_error()
+                }
+            };
+
+            checks.insert(name.clone(), check);
+        }
+
+        Ok(checks)
+    }
+}
+
+/// Custom checker compiled from the package.
+struct CustomChecker {
+    binary: PathBuf,
+}
+
+/// Command compiling checker 'source' in 'language' into 'binary'.
+fn compiler_command(language: &Language, source: &Path, binary: &Path) -> Command {
+    let mut command = match language {
+        Language::C => {
+            let mut command = Command::new("gcc");
+            command.args(["-O2", "-std=c11", "-o"]);
+            command
+        }
+        Language::Cpp => {
+            let mut command = Command::new("g++");
+            command.args(["-O2", "-std=c++17", "-o"]);
+            command
+        }
+        Language::Rust => {
+            let mut command = Command::new("rustc");
+            command.args(["-O", "-o"]);
+            command
+        }
+    };
+
+    command.arg(binary).arg(source);
+
+    if *language == Language::C {
+        command.arg("-lm");
+    }
+
+    command
+}
+
+impl CustomChecker {
+    /// Compiles 'source' into 'dir'.
+    async fn compile(
+        source: &Path,
+        language: &Language,
+        dir: &Path,
+    ) -> Result<CustomChecker, CheckerError> {
+        let binary = dir.join(CHECKER_BINARY);
+
+        let mut command = compiler_command(language, source, &binary);
+        command
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let output = tokio::time::timeout(CHECKER_COMPILATION_TIME, command.output())
+            .await
+            .map_err(|_| CheckerError::TimedOut)??;
+
+        if !output.status.success() {
+            return Err(CheckerError::Compilation(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+
+        Ok(CustomChecker { binary })
+    }
+
+    /// Runs checker with paths of input, expected output and output of the
+    /// submission as arguments.
+    async fn run(
+        &self,
+        files: &[PathBuf; 3],
+        time_limit: Duration,
+        memory_limit_mb: u64,
+    ) -> Result<CheckResult, CheckerError> {
+        let mut command = Command::new(&self.binary);
+        command
+            .args(files)
+            .env_clear()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+
+        let memory_bytes = memory_limit_mb * 1024 * 1024;
+        let cpu_secs = time_limit.as_secs() + 1;
+
+        // SAFETY: closure runs in the child process between fork and exec, and calls
+        // only async-signal-safe functions (setpgid, setrlimit).
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                set_limit(libc::RLIMIT_AS, memory_bytes)?;
+                set_limit(libc::RLIMIT_CPU, cpu_secs)?;
+                set_limit(libc::RLIMIT_CORE, 0)?;
+
+                Ok(())
+            });
+        }
+
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take();
+        let process_group = child.id().map(|pid| pid as libc::pid_t);
+
+        let running = async {
+            use tokio::io::AsyncReadExt;
+
+            let mut output = Vec::new();
+
+            if let Some(stdout) = stdout {
+                stdout
+                    .take(MAX_CHECKER_OUTPUT_LEN)
+                    .read_to_end(&mut output)
+                    .await?;
+            }
+
+            child.wait().await.map(|status| (status, output))
+        };
+
+        let (status, output) = match tokio::time::timeout(time_limit, running).await {
+            Ok(result) => result?,
+            Err(_) => {
+                if let Some(process_group) = process_group {
+                    // SAFETY: killpg has no memory safety requirements. Checker was
+                    // not waited for, so its process group still exists.
+                    unsafe {
+                        libc::killpg(process_group, libc::SIGKILL);
+                    }
+                }
+
+                let _ = child.kill().await;
+                return Err(CheckerError::TimedOut);
+            }
+        };
+
+        if !status.success() {
+            return Err(CheckerError::Failed(status.code()));
+        }
+
+        parse_checker_output(&output).ok_or_else(|| {
+            CheckerError::InvalidOutput(String::from_utf8_lossy(&output).into_owned())
+        })
+    }
+}
+
+/// Parses verdict written by custom checker: `OK` or `WA`, optionally followed
+/// by score from 0 to 1.
+fn parse_checker_output(output: &[u8]) -> Option<CheckResult> {
+    let mut words = std::str::from_utf8(output).ok()?.split_ascii_whitespace();
+
+    let verdict = match words.next()? {
+        "OK" => Verdict::Ok,
+        "WA" => Verdict::WrongAnswer,
+        _ => return None,
+    };
+
+    let score = match words.next() {
+        Some(score) => Some(
+            score
+                .parse::<f64>()
+                .ok()
+                .filter(|score| (0.0..=1.0).contains(score))?,
+        ),
+        None => None,
+    };
+
+    if words.next().is_some() {
+        return None;
+    }
+
+    Some(CheckResult { verdict, score })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECK_FIRST_TOKEN: &str = r#"
+        #include <stdio.h>
+        int main(int argc, char **argv) {
+            int expected = 0, output = 0;
+            FILE *e = fopen(argv[2], "r"), *o = fopen(argv[3], "r");
+            fscanf(e, "%d", &expected);
+            fscanf(o, "%d", &output);
+            printf(expected == output ? "OK\n" : "WA 0.5\n");
+            return 0;
+        }
+    "#;
+
+    fn check(checker: &CheckerKind, expected: &str, output: &str) -> bool {
+        check_builtin(checker, expected.as_bytes(), output.as_bytes()).unwrap()
+    }
+
+    fn passed(name: &str) -> TestResult {
+        TestResult {
+            name: String::from(name),
+            verdict: Verdict::Ok,
+            runtime_ms: Some(1),
+            peak_memory_kb: Some(1),
+            exit_code: Some(0),
+            score: None,
+        }
+    }
+
+    fn manifest(checker: &str, tests: &str) -> Manifest {
+        Manifest::parse(
+            format!(r#"{{"checker": {checker}, "groups": [{{"name": "a", "tests": {tests}}}]}}"#)
+                .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    async fn package(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        let mut builder = async_tar::Builder::new(&mut tar);
+
+        for (path, content) in files {
+            let mut header = async_tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).await.unwrap();
+        }
+
+        builder.into_inner().await.unwrap();
+        tar
+    }
+
+    fn outputs(files: &[(&str, &str)]) -> HashMap<String, Vec<u8>> {
+        files
+            .iter()
+            .map(|(name, content)| (String::from(*name), content.as_bytes().to_vec()))
+            .collect()
+    }
+
+    /// Checker made of shell 'script', so that it needs no compiler.
+    async fn script_checker(name: &str, script: &str) -> (PathBuf, CustomChecker) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("alsit-checker-test-{name}"));
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let binary = dir.join(CHECKER_BINARY);
+        tokio::fs::write(&binary, format!("#!/bin/sh\n{script}"))
+            .await
+            .unwrap();
+        tokio::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        (dir, CustomChecker { binary })
+    }
+
+    fn files(dir: &Path) -> [PathBuf; 3] {
+        [dir.join("in"), dir.join("expected"), dir.join("output")]
+    }
+
+    #[test]
+    fn exact_checker_compares_bytes() {
+        assert!(check(&CheckerKind::Exact, "1 2\n", "1 2\n"));
+        assert!(!check(&CheckerKind::Exact, "1 2\n", "1 2"));
+        assert!(!check(&CheckerKind::Exact, "1 2\n", "1  2\n"));
+    }
+
+    #[test]
+    fn tokens_checker_ignores_whitespace() {
+        assert!(check(&CheckerKind::Tokens, "1 2\n3\n", "1\n2 3"));
+        assert!(check(&CheckerKind::Tokens, "", " \n"));
+        assert!(!check(&CheckerKind::Tokens, "1 2", "1 2 3"));
+        assert!(!check(&CheckerKind::Tokens, "1 2", "12"));
+    }
+
+    #[test]
+    fn whitespace_checker_keeps_lines() {
+        assert!(check(
+            &CheckerKind::Whitespace,
+            "1 2\n3\n",
+            "  1   2 \r\n3\n\n\n"
+        ));
+        assert!(check(&CheckerKind::Whitespace, "a\n\nb", "a\n\nb\n"));
+        assert!(!check(&CheckerKind::Whitespace, "1 2\n3\n", "1\n2 3\n"));
+        assert!(!check(&CheckerKind::Whitespace, "a\n\nb", "a\nb"));
+    }
+
+    #[test]
+    fn float_checker_allows_absolute_or_relative_error() {
+        let checker = CheckerKind::Float { epsilon: 1e-6 };
+
+        assert!(check(&checker, "0.5 abc", "0.5000001 abc"));
+        assert!(check(&checker, "1000000", "1000000.5"));
+        assert!(check(&checker, "1e3", "1000"));
+        assert!(!check(&checker, "0.5", "0.50001"));
+        assert!(!check(&checker, "0.5 abc", "0.5 abd"));
+        assert!(!check(&checker, "0.5", "0.5 0.5"));
+        assert!(!check(&checker, "inf", "1e308"));
+    }
+
+    #[test]
+    fn custom_checker_is_not_built_in() {
+        let checker = CheckerKind::Custom {
+            source: String::from("check.c"),
+            language: Language::C,
+        };
+
+        assert_eq!(check_builtin(&checker, b"1", b"1"), None);
+    }
+
+    #[test]
+    fn checker_output_is_parsed() {
+        assert_eq!(
+            parse_checker_output(b"OK\n"),
+            Some(CheckResult::accepted(true))
+        );
+        assert_eq!(
+            parse_checker_output(b"WA 0.25\n"),
+            Some(CheckResult {
+                verdict: Verdict::WrongAnswer,
+                score: Some(0.25),
+            })
+        );
+        assert_eq!(parse_checker_output(b"ok"), None);
+        assert_eq!(parse_checker_output(b""), None);
+        assert_eq!(parse_checker_output(b"OK 1.5"), None);
+        assert_eq!(parse_checker_output(b"OK NaN"), None);
+        assert_eq!(parse_checker_output(b"OK 1 extra"), None);
+    }
+
+    #[tokio::test]
+    async fn builtin_checker_sets_verdicts_of_passed_tests() {
+        let manifest = manifest(r#""tokens""#, r#"["a", "b", "c", "d"]"#);
+        let package = package(&[
+            ("manifest.json", "{}"),
+            ("a.in", ""),
+            ("a.out", "1 2"),
+            ("b.in", ""),
+            ("b.out", "1 2"),
+            ("c.in", ""),
+            ("c.out", "1 2"),
+            ("./d.in", ""),
+            ("./d.out", "3"),
+        ])
+        .await;
+        let mut tests = vec![passed("a"), passed("b"), passed("c"), passed("d")];
+        tests[2].verdict = Verdict::TimeLimitExceeded;
+        let outputs = outputs(&[("a", "1\n2\n"), ("b", "2 1"), ("d", "3")]);
+
+        check_outputs(&manifest, &package, &mut tests, &outputs, 1).await;
+
+        let verdicts: Vec<Verdict> = tests.iter().map(|test| test.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Ok,
+                Verdict::WrongAnswer,
+                Verdict::TimeLimitExceeded,
+                Verdict::Ok
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_output_is_internal_error() {
+        let manifest = manifest(r#""exact""#, r#"["a"]"#);
+        let package = package(&[("a.in", ""), ("a.out", "1")]).await;
+        let mut tests = vec![passed("a")];
+
+        check_outputs(&manifest, &package, &mut tests, &HashMap::new(), 1).await;
+
+        assert_eq!(tests[0].verdict, Verdict::InternalError);
+    }
+
+    #[tokio::test]
+    async fn custom_checker_gets_files_as_arguments() {
+        let (dir, checker) = script_checker(
+            "arguments",
+            r#"cmp -s "$2" "$3" && echo OK || echo "WA 0.5""#,
+        )
+        .await;
+        let files = files(&dir);
+        tokio::fs::write(&files[1], "42").await.unwrap();
+        tokio::fs::write(&files[2], "42").await.unwrap();
+
+        let accepted = checker.run(&files, Duration::from_secs(1), 64).await;
+        tokio::fs::write(&files[2], "41").await.unwrap();
+        let rejected = checker.run(&files, Duration::from_secs(1), 64).await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(accepted.unwrap(), CheckResult::accepted(true));
+        assert_eq!(
+            rejected.unwrap(),
+            CheckResult {
+                verdict: Verdict::WrongAnswer,
+                score: Some(0.5),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_custom_checker_is_error() {
+        let (dir, failing) = script_checker("failing", "echo OK; exit 1").await;
+        let failed = failing.run(&files(&dir), Duration::from_secs(1), 64).await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        let (dir, invalid) = script_checker("invalid", "echo accepted").await;
+        let invalid = invalid.run(&files(&dir), Duration::from_secs(1), 64).await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert!(matches!(failed, Err(CheckerError::Failed(Some(1)))));
+        assert!(matches!(invalid, Err(CheckerError::InvalidOutput(_))));
+    }
+
+    #[tokio::test]
+    async fn custom_checker_is_killed_after_time_limit() {
+        let (dir, checker) = script_checker("timeout", "sleep 10 & wait").await;
+        let started = std::time::Instant::now();
+
+        let result = checker
+            .run(&files(&dir), Duration::from_millis(200), 64)
+            .await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert!(matches!(result, Err(CheckerError::TimedOut)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn custom_checker_is_compiled_from_package() {
+        if std::process::Command::new("gcc")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+
+        let manifest = manifest(
+            r#"{"kind": "custom", "source": "check.c", "language": "C"}"#,
+            r#"["a", "b", "c"]"#,
+        );
+        let package = package(&[
+            ("check.c", CHECK_FIRST_TOKEN),
+            ("a.in", ""),
+            ("a.out", "1"),
+            ("b.in", ""),
+            ("b.out", "2"),
+            ("c.in", ""),
+            ("c.out", "3"),
+        ])
+        .await;
+        let mut tests = vec![passed("a"), passed("b"), passed("c")];
+        let outputs = outputs(&[("a", " 1\n"), ("b", "3")]);
+
+        check_outputs(&manifest, &package, &mut tests, &outputs, -1).await;
+
+        assert_eq!((tests[0].verdict, tests[0].score), (Verdict::Ok, None));
+        assert_eq!(
+            (tests[1].verdict, tests[1].score),
+            (Verdict::WrongAnswer, Some(0.5))
+        );
+        assert_eq!(tests[2].verdict, Verdict::InternalError);
+        assert!(!std::env::temp_dir().join("alsit-checker--1").exists());
+    }
+
+    #[tokio::test]
+    async fn custom_checker_which_does_not_compile_is_internal_error() {
+        let manifest = manifest(
+            r#"{"kind": "custom", "source": "check.c", "language": "C"}"#,
+            r#"["a"]"#,
+        );
+        let package = package(&[("check.c", "not C"), ("a.in", ""), ("a.out", "1")]).await;
+        let mut tests = vec![passed("a")];
+
+        check_outputs(&manifest, &package, &mut tests, &outputs(&[("a", "1")]), -2).await;
+
+        assert_eq!(tests[0].verdict, Verdict::InternalError);
+    }
+}
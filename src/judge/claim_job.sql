@@ -0,0 +1,15 @@
+UPDATE ticket_data.judge_queue
+SET job_state = 'Claimed',
+    worker_id = $1,
+    attempts = attempts + 1,
+    lease_expires = now() + make_interval(secs => $2)
+WHERE ticket_id = (
+    SELECT ticket_id
+    FROM ticket_data.judge_queue
+    WHERE job_state = 'Queued'
+        OR (job_state IN ('Claimed', 'Running') AND lease_expires < now() AND attempts < $3)
+    ORDER BY queued_at
+    LIMIT 1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING ticket_id;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT COUNT(*)
+FROM ticket_data.judge_queue
+WHERE job_state = 'Queued';
\ No newline at end of file
@@ -0,0 +1,2 @@
+INSERT INTO ticket_data.judge_queue (ticket_id, job_state, attempts, queued_at)
+VALUES ($1, 'Queued', 0, now());
\ No newline at end of file
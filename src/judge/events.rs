@@ -0,0 +1,76 @@
+//! Status transitions of tickets, published by judges as judging progresses.
+//!
+//! Events are sent through in-process broadcast channel, so only subscribers
+//! listening at the moment of transition receive them; current state of the
+//! ticket has to be read from the database first.
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::ticket::results::Verdict;
+use crate::ticket::TicketId;
+
+/// Number of events kept for subscribers which fall behind.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Stage of judging a ticket.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(tag = "status")]
+pub enum TicketProgress {
+    /// Ticket waits in the judging queue.
+    Queued,
+    /// Submission is being compiled.
+    Compiling,
+    /// Test number 'test' (counting from 1) out of 'total' is running.
+    Running { test: u32, total: u32 },
+    /// Results are stored.
+    Finished { verdict: Verdict },
+    /// Ticket cannot be judged because of a problem on the server side.
+    SystemError,
+    /// Ticket was withdrawn before it was judged.
+    Cancelled,
+}
+
+impl TicketProgress {
+    /// Whether no more events follow this one.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            TicketProgress::Finished { .. }
+                | TicketProgress::SystemError
+                | TicketProgress::Cancelled
+        )
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct TicketEvent {
+    pub ticket_id: TicketId,
+    #[serde(flatten)]
+    pub progress: TicketProgress,
+}
+
+/// Sending side of the channel with ticket events.
+#[derive(Clone)]
+pub struct TicketEvents {
+    sender: broadcast::Sender<TicketEvent>,
+}
+
+impl TicketEvents {
+    pub fn new() -> TicketEvents {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        TicketEvents { sender }
+    }
+
+    /// Sends event to all current subscribers. Events without subscribers are dropped.
+    pub fn publish(&self, ticket_id: TicketId, progress: TicketProgress) {
+        let _ = self.sender.send(TicketEvent {
+            ticket_id,
+            progress,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TicketEvent> {
+        self.sender.subscribe()
+    }
+}
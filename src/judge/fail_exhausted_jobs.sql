@@ -0,0 +1,4 @@
+UPDATE ticket_data.judge_queue
+SET job_state = 'Failed', lease_expires = NULL
+WHERE job_state IN ('Claimed', 'Running') AND lease_expires < now() AND attempts >= $1
+RETURNING ticket_id;
\ No newline at end of file
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+/// Resource limits of the testing container.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ExerciseLimits {
+    /// Memory available to the container (including compilation) in megabytes.
+    pub memory_mb: i64,
+    /// Number of CPUs available to the container, may be fractional.
+    pub cpus: f64,
+    /// Maximal number of processes and threads in the container.
+    pub pids: i64,
+    /// Size of writable work directory in megabytes. Local sandbox limits only
+    /// size of single files to it.
+    pub work_dir_mb: i64,
+    /// Wall-clock time after which container is killed, covering compilation
+    /// and all tests.
+    pub wall_time_secs: u64,
+}
+
+impl Default for ExerciseLimits {
+    fn default() -> Self {
+        Self {
+            memory_mb: 512,
+            cpus: 1.0,
+            pids: 64,
+            work_dir_mb: 64,
+            wall_time_secs: 60,
+        }
+    }
+}
+
+impl ExerciseLimits {
+    pub fn memory_bytes(&self) -> i64 {
+        self.memory_mb * 1024 * 1024
+    }
+
+    pub fn nano_cpus(&self) -> i64 {
+        (self.cpus * 1_000_000_000.0) as i64
+    }
+}
+
+/// Limits set for single exercise in `exercise_data.exercises.limits`. Limits
+/// which are not set are taken from `judge.default_limits` in configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LimitOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_dir_mb: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_time_secs: Option<u64>,
+}
+
+impl LimitOverrides {
+    /// Overwrites fields of 'defaults' with the ones which are set.
+    pub fn apply(&self, defaults: &ExerciseLimits) -> ExerciseLimits {
+        ExerciseLimits {
+            memory_mb: self.memory_mb.unwrap_or(defaults.memory_mb),
+            cpus: self.cpus.unwrap_or(defaults.cpus),
+            pids: self.pids.unwrap_or(defaults.pids),
+            work_dir_mb: self.work_dir_mb.unwrap_or(defaults.work_dir_mb),
+            wall_time_secs: self.wall_time_secs.unwrap_or(defaults.wall_time_secs),
+        }
+    }
+
+    /// Returns name of the first limit which is set, but is not positive.
+    pub fn invalid_limit(&self) -> Option<&'static str> {
+        let limits = [
+            ("memory_mb", self.memory_mb.map(|limit| limit > 0)),
+            ("cpus", self.cpus.map(|limit| limit > 0.0)),
+            ("pids", self.pids.map(|limit| limit > 0)),
+            ("work_dir_mb", self.work_dir_mb.map(|limit| limit > 0)),
+            ("wall_time_secs", self.wall_time_secs.map(|limit| limit > 0)),
+        ];
+
+        limits
+            .into_iter()
+            .find(|(_, valid)| *valid == Some(false))
+            .map(|(name, _)| name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_converted_to_docker_units() {
+        let limits = ExerciseLimits {
+            memory_mb: 3,
+            cpus: 0.25,
+            ..ExerciseLimits::default()
+        };
+
+        assert_eq!(limits.memory_bytes(), 3 * 1024 * 1024);
+        assert_eq!(limits.nano_cpus(), 250_000_000);
+    }
+
+    #[test]
+    fn missing_limits_are_defaults() {
+        let limits: ExerciseLimits = serde_json::from_str(r#"{"cpus": 2.0}"#).unwrap();
+
+        assert_eq!(limits.cpus, 2.0);
+        assert_eq!(limits.memory_mb, ExerciseLimits::default().memory_mb);
+        assert_eq!(
+            limits.wall_time_secs,
+            ExerciseLimits::default().wall_time_secs
+        );
+    }
+
+    #[test]
+    fn overrides_replace_only_set_limits() {
+        let defaults = ExerciseLimits::default();
+        let overrides = LimitOverrides {
+            memory_mb: Some(1024),
+            wall_time_secs: Some(5),
+            ..LimitOverrides::default()
+        };
+
+        let limits = overrides.apply(&defaults);
+
+        assert_eq!(limits.memory_mb, 1024);
+        assert_eq!(limits.wall_time_secs, 5);
+        assert_eq!(limits.pids, defaults.pids);
+        assert_eq!(limits.cpus, defaults.cpus);
+    }
+
+    #[test]
+    fn first_non_positive_limit_is_invalid() {
+        let overrides = LimitOverrides {
+            cpus: Some(0.0),
+            pids: Some(-1),
+            ..LimitOverrides::default()
+        };
+
+        assert_eq!(overrides.invalid_limit(), Some("cpus"));
+        assert_eq!(LimitOverrides::default().invalid_limit(), None);
+    }
+
+    #[test]
+    fn only_set_overrides_are_serialized() {
+        let overrides: LimitOverrides = serde_json::from_str(r#"{"pids": 8}"#).unwrap();
+
+        assert_eq!(serde_json::to_string(&overrides).unwrap(), r#"{"pids":8}"#);
+        assert!(serde_json::from_str::<LimitOverrides>(r#"{"threads": 8}"#).is_err());
+    }
+}
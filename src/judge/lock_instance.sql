@@ -0,0 +1 @@
+SELECT pg_try_advisory_lock($1, $2);
\ No newline at end of file
@@ -0,0 +1,852 @@
+//! Manifest describing tests of the exercise, stored as `manifest.json` in the
+//! root of the test package:
+//!
+//! ```json
+//! {
+//!     "time_limit_ms": 1000,
+//!     "memory_limit_mb": 256,
+//!     "checker": "exact",
+//!     "groups": [
+//!         {"name": "samples", "points": 0, "tests": ["sample1", "sample2"]},
+//!         {"name": "small", "points": 40, "tests": ["small1", {"name": "small2", "time_limit_ms": 2000}]},
+//!         {"name": "large", "points": 60, "scoring": "min", "tests": ["large1", "large2"]}
+//!     ]
+//! }
+//! ```
+//!
+//! Time and memory limits apply to a single run of the submission; they are
+//! given for the whole exercise and can be overridden for single tests.
+//!
+//! Groups are subtasks of the exercise, score of the ticket is the sum of their
+//! scores. Group with "all" scoring (the default) gives its points when all of
+//! its tests pass, group with "min" scoring gives its points multiplied by the
+//! lowest score of its tests, see [TestResult::score_fraction]. Package without
+//! manifest gets one group with one point for every test, in order of test names.
+//!
+//! Judge uploads the manifest with all limits filled in as `manifest.json` in
+//! tests directory, so testing program does not have to apply defaults. Checker
+//! is always written there as an object with `kind`.
+//!
+//! ## Checkers
+//! Output of the submission is compared with expected output by the checker:
+//!
+//! * `"exact"` - outputs have to be equal byte by byte (the default),
+//! * `"tokens"` - sequences of whitespace separated tokens have to be equal,
+//! * `"whitespace"` - lines have to be equal after trimming them and collapsing
+//!   runs of whitespace, empty lines at the end are ignored,
+//! * `{"kind": "float", "epsilon": 1e-6}` - tokens are compared, numbers are
+//!   equal when their absolute or relative difference is at most `epsilon`
+//!   (1e-6 if not given),
+//! * `{"kind": "custom", "source": "checker.cpp", "language": "Cpp"}` - program
+//!   from the package, its source has to end with extension of the language.
+//!
+//! Outputs are checked by the judge, see [super::checker]. Custom checker is
+//! compiled on the judging machine and run for every test with limits of the
+//! test as `<checker> <input> <expected output> <output of submission>`.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::limits::ExerciseLimits;
+use crate::ticket::results::{round_score, SubtaskResult, TestResult, Verdict};
+use crate::ticket::Language;
+
+/// Name of the manifest in the root of test package.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const DEFAULT_TIME_LIMIT_MS: u64 = 1000;
+const DEFAULT_MEMORY_LIMIT_MB: u64 = 256;
+const DEFAULT_EPSILON: f64 = 1e-6;
+/// Wall time reserved for compilation when container limits are derived from
+/// time limits of tests.
+const COMPILATION_WALL_TIME_SECS: u64 = 30;
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Format(String),
+    NoGroups,
+    DuplicateGroup(String),
+    EmptyGroup(String),
+    DuplicateTest(String),
+    /// Test is listed in manifest, but it is not in the package.
+    UnknownTest(String),
+    /// Test is in the package, but it is not listed in any group.
+    UnlistedTest(String),
+    InvalidLimit(String),
+    InvalidChecker(String),
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::Format(reason) => write!(f, "Manifest has wrong format: {reason}."),
+            ManifestError::NoGroups => write!(f, "Manifest has no test groups."),
+            ManifestError::DuplicateGroup(group) => {
+                write!(f, "Group '{group}' occurs more than once.")
+            }
+            ManifestError::EmptyGroup(group) => write!(f, "Group '{group}' has no tests."),
+            ManifestError::DuplicateTest(test) => {
+                write!(f, "Test '{test}' is listed more than once.")
+            }
+            ManifestError::UnknownTest(test) => {
+                write!(f, "Test '{test}' is listed, but it is not in the package.")
+            }
+            ManifestError::UnlistedTest(test) => {
+                write!(f, "Test '{test}' is not listed in any group.")
+            }
+            ManifestError::InvalidLimit(test) => {
+                write!(f, "Limits of '{test}' have to be positive.")
+            }
+            ManifestError::InvalidChecker(reason) => write!(f, "Checker is invalid: {reason}."),
+        }
+    }
+}
+
+/// How output of the submission is compared with expected output.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum CheckerKind {
+    /// Outputs have to be equal byte by byte.
+    #[default]
+    Exact,
+    /// Sequences of whitespace separated tokens have to be equal.
+    Tokens,
+    /// Lines have to be equal, ignoring amount of whitespace.
+    Whitespace,
+    /// Tokens have to be equal, numbers up to 'epsilon'.
+    Float {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+    },
+    /// Checker program shipped in the package.
+    Custom { source: String, language: Language },
+}
+
+impl CheckerKind {
+    /// File of the package with source of the checker.
+    pub fn source(&self) -> Option<&str> {
+        match self {
+            CheckerKind::Custom { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ManifestError> {
+        match self {
+            CheckerKind::Float { epsilon } if !(epsilon.is_finite() && *epsilon >= 0.0) => {
+                Err(ManifestError::InvalidChecker(String::from(
+                    "epsilon has to be a non-negative number",
+                )))
+            }
+            CheckerKind::Custom { source, language } if !source.ends_with(language.extension()) => {
+                Err(ManifestError::InvalidChecker(format!(
+                    "source of {language} checker has to end with '{}'",
+                    language.extension()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checker given either by name only or as an object with `kind`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CheckerEntry {
+    Name(String),
+    Checker(CheckerKind),
+}
+
+fn deserialize_checker<'de, D>(deserializer: D) -> Result<CheckerKind, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match CheckerEntry::deserialize(deserializer)? {
+        CheckerEntry::Name(name) => match name.as_str() {
+            "exact" => Ok(CheckerKind::Exact),
+            "tokens" => Ok(CheckerKind::Tokens),
+            "whitespace" => Ok(CheckerKind::Whitespace),
+            "float" => Ok(CheckerKind::Float {
+                epsilon: default_epsilon(),
+            }),
+            _ => Err(serde::de::Error::custom(format!(
+                "unknown checker '{name}'"
+            ))),
+        },
+        CheckerEntry::Checker(checker) => Ok(checker),
+    }
+}
+
+/// How points of the group are given for its tests.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupScoring {
+    /// All points if every test passed, nothing otherwise.
+    #[default]
+    All,
+    /// Points multiplied by the lowest score of its tests.
+    Min,
+}
+
+/// Test given either by name only or with its own limits.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TestEntry {
+    Name(String),
+    Case {
+        name: String,
+        time_limit_ms: Option<u64>,
+        memory_limit_mb: Option<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "TestEntry")]
+pub struct TestCase {
+    pub name: String,
+    pub time_limit_ms: Option<u64>,
+    pub memory_limit_mb: Option<u64>,
+}
+
+impl From<TestEntry> for TestCase {
+    fn from(entry: TestEntry) -> Self {
+        match entry {
+            TestEntry::Name(name) => TestCase {
+                name,
+                time_limit_ms: None,
+                memory_limit_mb: None,
+            },
+            TestEntry::Case {
+                name,
+                time_limit_ms,
+                memory_limit_mb,
+            } => TestCase {
+                name,
+                time_limit_ms,
+                memory_limit_mb,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TestGroup {
+    pub name: String,
+    #[serde(default)]
+    pub points: u32,
+    #[serde(default)]
+    pub scoring: GroupScoring,
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default = "default_time_limit_ms")]
+    pub time_limit_ms: u64,
+    #[serde(default = "default_memory_limit_mb")]
+    pub memory_limit_mb: u64,
+    #[serde(default, deserialize_with = "deserialize_checker")]
+    pub checker: CheckerKind,
+    pub groups: Vec<TestGroup>,
+}
+
+fn default_time_limit_ms() -> u64 {
+    DEFAULT_TIME_LIMIT_MS
+}
+
+fn default_memory_limit_mb() -> u64 {
+    DEFAULT_MEMORY_LIMIT_MB
+}
+
+fn default_epsilon() -> f64 {
+    DEFAULT_EPSILON
+}
+
+impl Manifest {
+    pub fn parse(content: &[u8]) -> Result<Manifest, ManifestError> {
+        serde_json::from_slice(content).map_err(|error| ManifestError::Format(error.to_string()))
+    }
+
+    /// Manifest of package without one, every test is a group worth one point.
+    pub fn for_tests(tests: &HashSet<String>) -> Manifest {
+        let mut tests: Vec<&String> = tests.iter().collect();
+        tests.sort();
+
+        Manifest {
+            time_limit_ms: DEFAULT_TIME_LIMIT_MS,
+            memory_limit_mb: DEFAULT_MEMORY_LIMIT_MB,
+            checker: CheckerKind::default(),
+            groups: tests
+                .into_iter()
+                .map(|test| TestGroup {
+                    name: test.clone(),
+                    points: 1,
+                    scoring: GroupScoring::default(),
+                    tests: vec![TestCase {
+                        name: test.clone(),
+                        time_limit_ms: None,
+                        memory_limit_mb: None,
+                    }],
+                })
+                .collect(),
+        }
+    }
+
+    fn tests(&self) -> impl Iterator<Item = &TestCase> {
+        self.groups.iter().flat_map(|group| group.tests.iter())
+    }
+
+    /// Checks that every test of the package is listed exactly once, and nothing else is.
+    pub fn validate(&self, package_tests: &HashSet<String>) -> Result<(), ManifestError> {
+        if self.groups.is_empty() {
+            return Err(ManifestError::NoGroups);
+        }
+
+        if self.time_limit_ms == 0 || self.memory_limit_mb == 0 {
+            return Err(ManifestError::InvalidLimit(String::from("manifest")));
+        }
+
+        self.checker.validate()?;
+
+        let mut groups = HashSet::new();
+
+        for group in &self.groups {
+            if !groups.insert(&group.name) {
+                return Err(ManifestError::DuplicateGroup(group.name.clone()));
+            }
+
+            if group.tests.is_empty() {
+                return Err(ManifestError::EmptyGroup(group.name.clone()));
+            }
+        }
+
+        let mut listed = HashSet::new();
+
+        for test in self.tests() {
+            if !package_tests.contains(&test.name) {
+                return Err(ManifestError::UnknownTest(test.name.clone()));
+            }
+
+            if !listed.insert(&test.name) {
+                return Err(ManifestError::DuplicateTest(test.name.clone()));
+            }
+
+            if test.time_limit_ms == Some(0) || test.memory_limit_mb == Some(0) {
+                return Err(ManifestError::InvalidLimit(test.name.clone()));
+            }
+        }
+
+        if let Some(test) = package_tests.iter().find(|test| !listed.contains(test)) {
+            return Err(ManifestError::UnlistedTest(test.clone()));
+        }
+
+        Ok(())
+    }
+
+    pub fn test_count(&self) -> usize {
+        self.tests().count()
+    }
+
+    /// Copy of the manifest with limits of every test set explicitly.
+    pub fn resolved(&self) -> Manifest {
+        let mut manifest = self.clone();
+
+        for group in &mut manifest.groups {
+            for test in &mut group.tests {
+                test.time_limit_ms.get_or_insert(self.time_limit_ms);
+                test.memory_limit_mb.get_or_insert(self.memory_limit_mb);
+            }
+        }
+
+        manifest
+    }
+
+    /// Time and memory limit of test 'name', limits of the exercise when the
+    /// test is not listed.
+    pub fn limits_of(&self, name: &str) -> (u64, u64) {
+        match self.tests().find(|test| test.name == name) {
+            Some(test) => (
+                test.time_limit_ms.unwrap_or(self.time_limit_ms),
+                test.memory_limit_mb.unwrap_or(self.memory_limit_mb),
+            ),
+            None => (self.time_limit_ms, self.memory_limit_mb),
+        }
+    }
+
+    /// Raises container limits, so that every test can use its whole time and
+    /// memory limit.
+    pub fn container_limits(&self, limits: &ExerciseLimits) -> ExerciseLimits {
+        let total_time_ms: u64 = self
+            .tests()
+            .map(|test| test.time_limit_ms.unwrap_or(self.time_limit_ms))
+            .sum();
+
+        let max_memory_mb = self
+            .tests()
+            .map(|test| test.memory_limit_mb.unwrap_or(self.memory_limit_mb))
+            .max()
+            .unwrap_or(self.memory_limit_mb);
+
+        let mut limits = limits.clone();
+        limits.wall_time_secs = limits
+            .wall_time_secs
+            .max(COMPILATION_WALL_TIME_SECS + total_time_ms.div_ceil(1000));
+        limits.memory_mb = limits.memory_mb.max(max_memory_mb as i64);
+
+        limits
+    }
+
+    /// Scores every group for 'results' of the ticket with 'verdict'. Tests
+    /// missing from 'results' did not pass, they get verdict of the ticket.
+    pub fn score(&self, results: &[TestResult], verdict: Verdict) -> Vec<SubtaskResult> {
+        let results: HashMap<&str, &TestResult> = results
+            .iter()
+            .map(|result| (result.name.as_str(), result))
+            .collect();
+        let missing_verdict = match verdict {
+            // Testing program finished without reporting every test.
+            Verdict::Ok => Verdict::InternalError,
+            verdict => verdict,
+        };
+
+        self.groups
+            .iter()
+            .map(|group| {
+                let tests: Vec<Option<&TestResult>> = group
+                    .tests
+                    .iter()
+                    .map(|test| results.get(test.name.as_str()).copied())
+                    .collect();
+
+                let group_verdict = tests
+                    .iter()
+                    .map(|result| result.map_or(missing_verdict, |result| result.verdict))
+                    .find(|verdict| *verdict != Verdict::Ok)
+                    .unwrap_or(Verdict::Ok);
+
+                let fraction = match group.scoring {
+                    GroupScoring::All if group_verdict == Verdict::Ok => 1.0,
+                    GroupScoring::All => 0.0,
+                    GroupScoring::Min => tests
+                        .iter()
+                        .map(|result| result.map_or(0.0, TestResult::score_fraction))
+                        .fold(1.0, f64::min),
+                };
+
+                SubtaskResult {
+                    name: group.name.clone(),
+                    verdict: group_verdict,
+                    score: round_score(group.points as f64 * fraction),
+                    max_score: group.points as i64,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"{
+        "time_limit_ms": 1000,
+        "memory_limit_mb": 256,
+        "groups": [
+            {"name": "samples", "points": 0, "tests": ["sample1"]},
+            {"name": "small", "points": 40, "tests": ["small1", {"name": "small2", "time_limit_ms": 2000}]},
+            {"name": "large", "points": 60, "scoring": "min", "tests": ["large1", {"name": "large2", "memory_limit_mb": 512}]}
+        ]
+    }"#;
+
+    fn parse(json: &str) -> Manifest {
+        Manifest::parse(json.as_bytes()).expect("Manifest should parse.")
+    }
+
+    fn tests(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| String::from(*name)).collect()
+    }
+
+    fn package_tests() -> HashSet<String> {
+        tests(&["sample1", "small1", "small2", "large1", "large2"])
+    }
+
+    #[test]
+    fn manifest_is_parsed() {
+        let manifest = parse(MANIFEST);
+
+        assert_eq!(manifest.groups.len(), 3);
+        assert_eq!(manifest.groups[0].scoring, GroupScoring::All);
+        assert_eq!(manifest.groups[2].scoring, GroupScoring::Min);
+        assert_eq!(manifest.groups[1].tests[1].name, "small2");
+        assert_eq!(manifest.groups[1].tests[1].time_limit_ms, Some(2000));
+        assert_eq!(manifest.groups[1].tests[0].time_limit_ms, None);
+        assert_eq!(manifest.test_count(), 5);
+        assert!(manifest.validate(&package_tests()).is_ok());
+    }
+
+    #[test]
+    fn missing_limits_take_defaults() {
+        let manifest = parse(r#"{"groups": [{"name": "all", "tests": ["a"]}]}"#);
+
+        assert_eq!(manifest.time_limit_ms, DEFAULT_TIME_LIMIT_MS);
+        assert_eq!(manifest.memory_limit_mb, DEFAULT_MEMORY_LIMIT_MB);
+        assert_eq!(manifest.groups[0].points, 0);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let result = Manifest::parse(br#"{"groups": [], "time_limit": 5}"#);
+
+        assert!(matches!(result, Err(ManifestError::Format(_))));
+    }
+
+    #[test]
+    fn default_manifest_has_group_for_every_test() {
+        let manifest = Manifest::for_tests(&tests(&["b", "a"]));
+
+        let groups: Vec<(&str, u32)> = manifest
+            .groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.points))
+            .collect();
+        assert_eq!(groups, [("a", 1), ("b", 1)]);
+        assert!(manifest.validate(&tests(&["a", "b"])).is_ok());
+    }
+
+    #[test]
+    fn unknown_and_unlisted_tests_are_rejected() {
+        let manifest = parse(MANIFEST);
+
+        let mut extra = package_tests();
+        extra.insert(String::from("large3"));
+        assert!(matches!(
+            manifest.validate(&extra),
+            Err(ManifestError::UnlistedTest(test)) if test == "large3"
+        ));
+
+        let mut missing = package_tests();
+        missing.remove("small1");
+        assert!(matches!(
+            manifest.validate(&missing),
+            Err(ManifestError::UnknownTest(test)) if test == "small1"
+        ));
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let manifest =
+            parse(r#"{"groups": [{"name": "a", "tests": ["a"]}, {"name": "b", "tests": ["a"]}]}"#);
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::DuplicateTest(test)) if test == "a"
+        ));
+
+        let manifest =
+            parse(r#"{"groups": [{"name": "a", "tests": ["a"]}, {"name": "a", "tests": ["b"]}]}"#);
+        assert!(matches!(
+            manifest.validate(&tests(&["a", "b"])),
+            Err(ManifestError::DuplicateGroup(group)) if group == "a"
+        ));
+    }
+
+    #[test]
+    fn empty_groups_are_rejected() {
+        let result = parse(r#"{"groups": []}"#).validate(&tests(&["a"]));
+        assert!(matches!(result, Err(ManifestError::NoGroups)));
+
+        let manifest =
+            parse(r#"{"groups": [{"name": "a", "tests": ["a"]}, {"name": "b", "tests": []}]}"#);
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::EmptyGroup(group)) if group == "b"
+        ));
+    }
+
+    #[test]
+    fn zero_limits_are_rejected() {
+        let manifest =
+            parse(r#"{"groups": [{"name": "a", "tests": [{"name": "a", "time_limit_ms": 0}]}]}"#);
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::InvalidLimit(test)) if test == "a"
+        ));
+
+        let manifest =
+            parse(r#"{"memory_limit_mb": 0, "groups": [{"name": "a", "tests": ["a"]}]}"#);
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::InvalidLimit(_))
+        ));
+    }
+
+    #[test]
+    fn resolved_manifest_has_limits_of_every_test() {
+        let resolved = parse(MANIFEST).resolved();
+
+        for test in resolved.tests() {
+            assert!(test.time_limit_ms.is_some() && test.memory_limit_mb.is_some());
+        }
+        assert_eq!(resolved.groups[1].tests[0].time_limit_ms, Some(1000));
+        assert_eq!(resolved.groups[1].tests[1].time_limit_ms, Some(2000));
+        assert_eq!(resolved.groups[2].tests[1].memory_limit_mb, Some(512));
+    }
+
+    #[test]
+    fn limits_of_test_fall_back_to_exercise() {
+        let manifest = parse(MANIFEST);
+
+        assert_eq!(manifest.limits_of("small2"), (2000, 256));
+        assert_eq!(manifest.limits_of("large2"), (1000, 512));
+        assert_eq!(manifest.limits_of("sample1"), (1000, 256));
+    }
+
+    #[test]
+    fn container_limits_cover_all_tests() {
+        let limits = ExerciseLimits {
+            memory_mb: 256,
+            wall_time_secs: 10,
+            ..ExerciseLimits::default()
+        };
+
+        let container_limits = parse(MANIFEST).container_limits(&limits);
+
+        // 4 tests of 1 s and one of 2 s, after compilation.
+        assert_eq!(
+            container_limits.wall_time_secs,
+            COMPILATION_WALL_TIME_SECS + 6
+        );
+        assert_eq!(container_limits.memory_mb, 512);
+        assert_eq!(container_limits.pids, limits.pids);
+    }
+
+    #[test]
+    fn container_limits_are_never_lowered() {
+        let limits = ExerciseLimits {
+            memory_mb: 2048,
+            wall_time_secs: 600,
+            ..ExerciseLimits::default()
+        };
+
+        let container_limits = parse(MANIFEST).container_limits(&limits);
+
+        assert_eq!(container_limits.wall_time_secs, 600);
+        assert_eq!(container_limits.memory_mb, 2048);
+    }
+
+    fn result(name: &str, verdict: Verdict, score: Option<f64>) -> TestResult {
+        TestResult {
+            name: String::from(name),
+            verdict,
+            runtime_ms: None,
+            peak_memory_kb: None,
+            exit_code: None,
+            score,
+        }
+    }
+
+    fn scores(subtasks: &[SubtaskResult]) -> Vec<(Verdict, f64, i64)> {
+        subtasks
+            .iter()
+            .map(|subtask| (subtask.verdict, subtask.score, subtask.max_score))
+            .collect()
+    }
+
+    #[test]
+    fn passed_tests_score_all_points() {
+        let results: Vec<TestResult> = package_tests()
+            .iter()
+            .map(|test| result(test, Verdict::Ok, None))
+            .collect();
+
+        let subtasks = parse(MANIFEST).score(&results, Verdict::Ok);
+
+        let names: Vec<&str> = subtasks
+            .iter()
+            .map(|subtask| subtask.name.as_str())
+            .collect();
+        assert_eq!(names, ["samples", "small", "large"]);
+        assert_eq!(
+            scores(&subtasks),
+            [
+                (Verdict::Ok, 0.0, 0),
+                (Verdict::Ok, 40.0, 40),
+                (Verdict::Ok, 60.0, 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn all_scoring_gives_nothing_for_partial_results() {
+        let results = [
+            result("sample1", Verdict::Ok, None),
+            result("small1", Verdict::Ok, None),
+            result("small2", Verdict::WrongAnswer, Some(0.9)),
+            result("large1", Verdict::Ok, None),
+            result("large2", Verdict::Ok, None),
+        ];
+
+        let subtasks = parse(MANIFEST).score(&results, Verdict::WrongAnswer);
+
+        assert_eq!(scores(&subtasks)[1], (Verdict::WrongAnswer, 0.0, 40));
+    }
+
+    #[test]
+    fn min_scoring_takes_lowest_test_score() {
+        let results = [
+            result("sample1", Verdict::Ok, None),
+            result("small1", Verdict::Ok, None),
+            result("small2", Verdict::Ok, None),
+            result("large1", Verdict::Ok, Some(0.75)),
+            result("large2", Verdict::WrongAnswer, Some(1.0 / 3.0)),
+        ];
+
+        let subtasks = parse(MANIFEST).score(&results, Verdict::WrongAnswer);
+
+        assert_eq!(scores(&subtasks)[2], (Verdict::WrongAnswer, 20.0, 60));
+    }
+
+    #[test]
+    fn min_scoring_gives_nothing_for_failed_test() {
+        let results = [
+            result("large1", Verdict::Ok, None),
+            result("large2", Verdict::TimeLimitExceeded, Some(0.5)),
+        ];
+
+        let subtasks = parse(MANIFEST).score(&results, Verdict::TimeLimitExceeded);
+
+        assert_eq!(scores(&subtasks)[2], (Verdict::TimeLimitExceeded, 0.0, 60));
+    }
+
+    #[test]
+    fn missing_tests_get_verdict_of_the_ticket() {
+        let results = [result("sample1", Verdict::Ok, None)];
+
+        let subtasks = parse(MANIFEST).score(&results, Verdict::TimeLimitExceeded);
+        assert_eq!(
+            scores(&subtasks),
+            [
+                (Verdict::Ok, 0.0, 0),
+                (Verdict::TimeLimitExceeded, 0.0, 40),
+                (Verdict::TimeLimitExceeded, 0.0, 60)
+            ]
+        );
+
+        // Testing program did not report every test of accepted ticket.
+        let subtasks = parse(MANIFEST).score(&results, Verdict::Ok);
+        assert_eq!(subtasks[1].verdict, Verdict::InternalError);
+    }
+
+    #[test]
+    fn partial_scores_are_rounded() {
+        let manifest =
+            parse(r#"{"groups": [{"name": "a", "points": 10, "scoring": "min", "tests": ["a"]}]}"#);
+        let results = [result("a", Verdict::WrongAnswer, Some(1.0 / 3.0))];
+
+        let subtasks = manifest.score(&results, Verdict::WrongAnswer);
+
+        assert_eq!(subtasks[0].score, 3.33);
+    }
+
+    fn checker(checker: &str) -> Result<Manifest, ManifestError> {
+        Manifest::parse(
+            format!(r#"{{"checker": {checker}, "groups": [{{"name": "a", "tests": ["a"]}}]}}"#)
+                .as_bytes(),
+        )
+    }
+
+    #[test]
+    fn checker_is_exact_by_default() {
+        assert_eq!(parse(MANIFEST).checker, CheckerKind::Exact);
+    }
+
+    #[test]
+    fn checker_is_given_by_name() {
+        assert_eq!(checker(r#""tokens""#).unwrap().checker, CheckerKind::Tokens);
+        assert_eq!(
+            checker(r#""whitespace""#).unwrap().checker,
+            CheckerKind::Whitespace
+        );
+        assert_eq!(
+            checker(r#""float""#).unwrap().checker,
+            CheckerKind::Float {
+                epsilon: DEFAULT_EPSILON
+            }
+        );
+    }
+
+    #[test]
+    fn checker_is_given_as_object() {
+        assert_eq!(
+            checker(r#"{"kind": "float", "epsilon": 0.01}"#)
+                .unwrap()
+                .checker,
+            CheckerKind::Float { epsilon: 0.01 }
+        );
+        assert_eq!(
+            checker(r#"{"kind": "custom", "source": "check.rs", "language": "Rust"}"#)
+                .unwrap()
+                .checker,
+            CheckerKind::Custom {
+                source: String::from("check.rs"),
+                language: Language::Rust
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_checker_is_rejected() {
+        assert!(matches!(
+            checker(r#""diff""#),
+            Err(ManifestError::Format(_))
+        ));
+        assert!(matches!(
+            checker(r#"{"kind": "custom", "source": "check.rs"}"#),
+            Err(ManifestError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_checker_is_rejected() {
+        let manifest = checker(r#"{"kind": "float", "epsilon": -1}"#).unwrap();
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::InvalidChecker(_))
+        ));
+
+        let manifest =
+            checker(r#"{"kind": "custom", "source": "check.cpp", "language": "Rust"}"#).unwrap();
+        assert!(matches!(
+            manifest.validate(&tests(&["a"])),
+            Err(ManifestError::InvalidChecker(_))
+        ));
+    }
+
+    #[test]
+    fn resolved_checker_is_written_as_object() {
+        let manifest = checker(r#""tokens""#).unwrap().resolved();
+
+        let json = serde_json::to_value(&manifest).unwrap();
+
+        assert_eq!(json["checker"], serde_json::json!({"kind": "tokens"}));
+    }
+
+    #[test]
+    fn custom_checker_does_not_run_in_container() {
+        let manifest =
+            checker(r#"{"kind": "custom", "source": "check.c", "language": "C"}"#).unwrap();
+        let limits = ExerciseLimits {
+            wall_time_secs: 1,
+            ..ExerciseLimits::default()
+        };
+
+        let container_limits = manifest.container_limits(&limits);
+
+        assert_eq!(
+            container_limits.wall_time_secs,
+            COMPILATION_WALL_TIME_SECS + 1
+        );
+    }
+}
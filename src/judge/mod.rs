@@ -1,65 +1,246 @@
-use crate::ticket::{Language, TicketId};
+//! ## Exercise data
+//! Every exercise has its own directory `{tests_path}/{exercise_id}/` containing:
+//!
+//! * `tests.tar` - package with tests, uploaded to `/tests` in testing container.
+//!   It is sent through `PUT /exercise/{exercise_id}/tests`, see
+//!   [crate::exercise::package] for its layout. Hash of the package is stored
+//!   with every judged ticket.
+//!
+//! Resource limits of testing container are set for the exercise through
+//! `/exercise` endpoints, for example
+//! `{"memory_mb": 256, "cpus": 0.5, "pids": 32, "work_dir_mb": 64, "wall_time_secs": 30}`.
+//! Missing values are replaced with `judge.default_limits` from configuration.
+//! Memory and wall time are raised when [manifest] of the package needs more.
+//!
+//! ## Sandboxes
+//! Submissions are judged either in Docker containers created from testing image
+//! (`judge.sandbox` set to "docker") or as local processes ("local"), see
+//! [crate::config] for keys configuring local backend. Containers are removed
+//! once output is collected; containers labeled `alsit.managed` which are left behind
+//! (for example after a crash) are removed by a background task once lease of
+//! their ticket's job expires.
+use crate::config::Config;
+use crate::ticket::{ExerciseId, TicketId};
+use deadpool_postgres::Pool;
+use std::fmt::Display;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_postgres::Transaction;
 
+mod checker;
+pub mod events;
+mod limits;
+pub mod manifest;
+mod queue;
+mod retry;
+mod sandbox;
 mod virtualization;
 
+pub use limits::{ExerciseLimits, LimitOverrides};
+pub use sandbox::SandboxConfig;
+
+use events::{TicketEvent, TicketEvents, TicketProgress};
+use queue::{JobState, WorkerId};
+
+#[derive(Debug)]
 pub enum JudgeError {
-    MismatchedLanguage {
-        judge_lang: Language,
-        ticket_lang: Language,
-    },
     InternalError,
-    PoisonedJudge,
+    DatabaseError,
+    /// Docker daemon failed and retries did not help.
+    DockerError,
+    /// Tests of the exercise could not be read.
+    TestsUnavailable {
+        exercise_id: ExerciseId,
+    },
+    QueueFull,
 }
 
-struct Judge {}
-
-impl Default for Judge {
-    fn default() -> Self {
-        Self {}
+impl Display for JudgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JudgeError::InternalError => write!(f, "Internal error of the judge."),
+            JudgeError::DatabaseError => write!(f, "Database is unavailable."),
+            JudgeError::DockerError => write!(f, "Docker daemon is unavailable."),
+            JudgeError::TestsUnavailable { exercise_id } => {
+                write!(f, "Tests of exercise {exercise_id} cannot be read.")
+            }
+            JudgeError::QueueFull => write!(f, "Judging queue is full."),
+        }
     }
 }
 
+/// Time after which idle judge checks the queue, even if it was not notified.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Interval at which judge renews lease of the job it is working on.
+const LEASE_RENEWAL_INTERVAL: Duration = Duration::from_secs(20);
+
+struct Judge {
+    db: Pool,
+    config: Arc<Config>,
+    sandbox_config: SandboxConfig,
+    worker_id: WorkerId,
+    job_notifier: Arc<Notify>,
+    events: TicketEvents,
+}
+
 impl Judge {
-    fn judge(&self, ticket_id: TicketId) {}
+    fn new(
+        db: Pool,
+        config: Arc<Config>,
+        sandbox_config: SandboxConfig,
+        worker_id: WorkerId,
+        job_notifier: Arc<Notify>,
+        events: TicketEvents,
+    ) -> Judge {
+        Judge {
+            db,
+            config,
+            sandbox_config,
+            worker_id,
+            job_notifier,
+            events,
+        }
+    }
+
+    /// Main loop of the judge. Claims jobs from the queue one by one and waits
+    /// for notification (or poll interval) when there is nothing to do.
+    async fn run(self) {
+        loop {
+            match queue::claim(&self.db, self.worker_id).await {
+                Ok(Some(ticket_id)) => {
+                    self.judge(ticket_id).await;
+                }
+                Ok(None) => {
+                    if let Ok(failed) = queue::fail_exhausted(&self.db).await {
+                        for ticket_id in failed {
+                            warn!(
+                                "Abandoned job ran out of attempts. Marking ticket as system error. TicketId = {}",
+                                ticket_id
+                            );
+                            virtualization::fail_ticket(ticket_id, &self.db, &self.events).await;
+                        }
+                    }
+
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.job_notifier.notified()).await;
+                }
+                Err(_) => {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Runs submission from the ticket against exercise tests and stores the outcome.
+    async fn judge(&self, ticket_id: TicketId) {
+        info!("Judging ticket. TicketId = {}", ticket_id);
+
+        let _ = queue::set_state(&self.db, ticket_id, self.worker_id, JobState::Running).await;
+
+        let judging = virtualization::test_program(
+            ticket_id,
+            &self.db,
+            &self.config,
+            &self.sandbox_config,
+            &self.events,
+        );
+        tokio::pin!(judging);
+
+        let final_state;
+
+        let mut lease_renewal = tokio::time::interval(LEASE_RENEWAL_INTERVAL);
+        lease_renewal.tick().await;
+
+        loop {
+            tokio::select! {
+                judging_result = &mut judging => {
+                    final_state = match judging_result {
+                        Ok(()) => JobState::Done,
+                        Err(_) => JobState::Failed,
+                    };
+                    break;
+                }
+                _ = lease_renewal.tick() => {
+                    let _ = queue::renew_lease(&self.db, ticket_id, self.worker_id).await;
+                }
+            }
+        }
+
+        let _ = queue::set_state(&self.db, ticket_id, self.worker_id, final_state).await;
+
+        info!("Finished judging ticket. TicketId = {}", ticket_id);
+    }
 }
 
+/// Handle used to put tickets into the judging queue.
 #[derive(Clone)]
 pub struct JudgeDispatcher {
-    judges_list: Arc<Vec<Arc<Mutex<Judge>>>>,
+    job_notifier: Arc<Notify>,
+    max_queued_jobs: i64,
+    events: TicketEvents,
 }
 
-unsafe impl Sync for JudgeDispatcher {}
-
 impl JudgeDispatcher {
-    fn new(number_of_judges: usize) -> JudgeDispatcher {
-        let judges_vec = Arc::new(Vec::new());
+    /// Registers the server instance, recovers jobs interrupted by previous shutdown
+    /// and spawns `judge.number_of_judges` judges.
+    pub async fn start(db: Pool, config: Arc<Config>) -> Result<JudgeDispatcher, JudgeError> {
+        let instance_id = queue::register_instance(&db).await?;
+        let recovered = queue::recover(&db).await?;
 
-        for _i in 0..number_of_judges {
-            judges_vec.push(Arc::new(Mutex::new(Judge::default())));
+        if recovered > 0 {
+            info!(
+                "Recovered {} jobs interrupted by previous shutdown.",
+                recovered
+            );
         }
 
-        JudgeDispatcher {
-            judges_list: judges_vec,
+        let sandbox_config = SandboxConfig::from_config(&config);
+
+        if let SandboxConfig::Docker { .. } = sandbox_config {
+            sandbox::spawn_container_reaper(db.clone());
         }
-    }
 
-    pub fn queue_judging(&self, ticket_id: TicketId) -> Result<(), JudgeError> {
-        use rand::prelude::*;
+        let job_notifier = Arc::new(Notify::new());
+        let events = TicketEvents::new();
+
+        for judge_index in 0..config.judge.number_of_judges {
+            let judge = Judge::new(
+                db.clone(),
+                config.clone(),
+                sandbox_config.clone(),
+                queue::worker_id(instance_id, judge_index as u32),
+                job_notifier.clone(),
+                events.clone(),
+            );
+            tokio::task::spawn(judge.run());
+        }
 
-        let mut rng = thread_rng();
+        Ok(JudgeDispatcher {
+            job_notifier,
+            max_queued_jobs: config.judge.max_queued_jobs,
+            events,
+        })
+    }
 
-        let judge = self
-            .judges_list
-            .get(rng.gen::<usize>() % self.judges_list.len())
-            .unwrap()
-            .clone();
+    /// Adds ticket to the judging queue as a part of 'client' transaction.
+    /// After the transaction is committed, judges should be woken up with
+    /// [JudgeDispatcher::notify_judges].
+    pub async fn queue_judging(
+        &self,
+        client: &Transaction<'_>,
+        ticket_id: TicketId,
+    ) -> Result<(), JudgeError> {
+        queue::enqueue(client, ticket_id, self.max_queued_jobs).await
+    }
 
-        tokio::task::spawn(async move {
-            judge.lock().await.judge(ticket_id);
-        });
+    /// Wakes up one of idle judges and tells subscribers that the ticket is queued.
+    pub fn notify_judges(&self, ticket_id: TicketId) {
+        self.events.publish(ticket_id, TicketProgress::Queued);
+        self.job_notifier.notify_one();
+    }
 
-        Ok(())
+    /// Receiver of status transitions of all tickets.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<TicketEvent> {
+        self.events.subscribe()
     }
 }
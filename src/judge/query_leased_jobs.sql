@@ -0,0 +1,3 @@
+SELECT ticket_id
+FROM ticket_data.judge_queue
+WHERE job_state IN ('Claimed', 'Running') AND lease_expires >= now();
\ No newline at end of file
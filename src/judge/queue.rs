@@ -0,0 +1,536 @@
+//! Persistent judging queue stored in `ticket_data.judge_queue`.
+//!
+//! Every submitted ticket gets a job in state `Queued`. Judges claim jobs with
+//! `SELECT ... FOR UPDATE SKIP LOCKED`, so one job is never taken by two judges,
+//! and hold a lease which is renewed for as long as judging lasts. When a judge
+//! dies, its lease expires and the job is claimed again, until it runs out of
+//! attempts and is marked as `Failed`.
+//!
+//! Every server instance holds a Postgres advisory lock identifying it for as long
+//! as it runs, and ids of its judges start with id of the instance. At startup,
+//! jobs of judges whose instance holds no lock are put back into the queue right
+//! away, without waiting for their leases to expire.
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::time::Duration;
+
+use deadpool_postgres::{Object, Pool};
+use tokio_postgres::Transaction;
+
+use super::JudgeError;
+use crate::ticket::TicketId;
+
+/// Id of the judge, id of its server instance is in the upper 32 bits.
+pub type WorkerId = i64;
+/// Id of the server instance, second key of its advisory lock.
+pub type InstanceId = i32;
+
+/// First key of advisory locks held by server instances.
+const INSTANCE_LOCK_NAMESPACE: i32 = 0x616c_7374;
+/// Interval at which connection holding the instance lock is checked.
+const INSTANCE_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time after which job claimed by a judge can be taken over by another one.
+const LEASE_DURATION_SECS: f64 = 60.0;
+/// Number of times job is claimed before it is marked as failed.
+const MAX_JUDGE_ATTEMPTS: i32 = 3;
+
+/// State of the job set by the judge holding it. Jobs are `Queued` and `Claimed`
+/// only by queries of this module.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+}
+
+impl Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_description = match self {
+            JobState::Running => "Running",
+            JobState::Done => "Done",
+            JobState::Failed => "Failed",
+        };
+
+        write!(f, "{string_description}")
+    }
+}
+
+async fn get_client(db: &Pool) -> Result<Object, JudgeError> {
+    match db.get().await {
+        Ok(client) => Ok(client),
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Id of the judge number 'judge_index' of the instance.
+pub fn worker_id(instance_id: InstanceId, judge_index: u32) -> WorkerId {
+    (i64::from(instance_id) << 32) | i64::from(judge_index)
+}
+
+/// Tries to take advisory lock of 'instance_id' on 'client'. The lock is held
+/// until the connection is closed.
+async fn try_lock_instance(client: &Object, instance_id: InstanceId) -> Result<bool, JudgeError> {
+    let lock_stmt = include_str!("lock_instance.sql");
+
+    match client
+        .query_one(lock_stmt, &[&INSTANCE_LOCK_NAMESPACE, &instance_id])
+        .await
+    {
+        Ok(row) => Ok(row.get(0)),
+        Err(error) => {
+            error!(
+                "Error occured while locking server instance. ERROR = {:?}",
+                error
+            );
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Registers the running server instance and returns its id. Connection holding
+/// the instance lock is kept by a background task, which takes the lock again
+/// when the connection is lost.
+pub async fn register_instance(db: &Pool) -> Result<InstanceId, JudgeError> {
+    use rand::prelude::*;
+
+    let mut client = get_client(db).await?;
+
+    let instance_id = loop {
+        // Non-negative id is read back from `pg_locks` without change of sign.
+        let instance_id: InstanceId = thread_rng().gen_range(0..=InstanceId::MAX);
+
+        if try_lock_instance(&client, instance_id).await? {
+            break instance_id;
+        }
+    };
+
+    let db = db.clone();
+
+    tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(INSTANCE_LOCK_CHECK_INTERVAL).await;
+
+            if !client.is_closed() {
+                continue;
+            }
+
+            warn!("Connection holding lock of server instance was lost. Locking it again.");
+
+            // Until the lock is taken again, jobs of the judges are kept by their leases.
+            if let Ok(new_client) = get_client(&db).await {
+                if let Ok(true) = try_lock_instance(&new_client, instance_id).await {
+                    client = new_client;
+                }
+            }
+        }
+    });
+
+    Ok(instance_id)
+}
+
+/// Puts jobs whose leases expired or whose instances are not running back into
+/// the queue. Leases of such jobs which ran out of attempts are expired, so they
+/// are failed by [fail_exhausted]. Returns number of recovered jobs.
+pub async fn recover(db: &Pool) -> Result<u64, JudgeError> {
+    let update_stmt = include_str!("recover_jobs.sql");
+    let client = get_client(db).await?;
+
+    match client
+        .execute(
+            update_stmt,
+            &[&i64::from(INSTANCE_LOCK_NAMESPACE), &MAX_JUDGE_ATTEMPTS],
+        )
+        .await
+    {
+        Ok(recovered) => Ok(recovered),
+        Err(error) => {
+            error!("Error occured while recovering jobs. ERROR = {:?}", error);
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Adds job for the ticket to the queue. It is done inside of 'client' transaction,
+/// so the job becomes visible to judges together with the ticket. Ticket is rejected
+/// when there are already 'max_queued_jobs' waiting jobs.
+pub async fn enqueue(
+    client: &Transaction<'_>,
+    ticket_id: TicketId,
+    max_queued_jobs: i64,
+) -> Result<(), JudgeError> {
+    let count_stmt = include_str!("count_queued.sql");
+    let insert_stmt = include_str!("enqueue_job.sql");
+
+    let queued_jobs: i64 = match client.query_one(count_stmt, &[]).await {
+        Ok(row) => row.get(0),
+        Err(error) => {
+            error!(
+                "Error occured while counting queued jobs. ERROR = {:?}",
+                error
+            );
+            return Err(JudgeError::DatabaseError);
+        }
+    };
+
+    if queued_jobs >= max_queued_jobs {
+        warn!(
+            "Judging queue is full. Rejecting ticket. TicketId = {}",
+            ticket_id
+        );
+        return Err(JudgeError::QueueFull);
+    }
+
+    if let Err(error) = client.execute(insert_stmt, &[&ticket_id]).await {
+        error!(
+            "Error occured while inserting job into the queue. ERROR = {:?}",
+            error
+        );
+        return Err(JudgeError::DatabaseError);
+    }
+
+    Ok(())
+}
+
+/// Claims the oldest job waiting in the queue (or abandoned by a crashed judge).
+pub async fn claim(db: &Pool, worker_id: WorkerId) -> Result<Option<TicketId>, JudgeError> {
+    let claim_stmt = include_str!("claim_job.sql");
+    let client = get_client(db).await?;
+
+    match client
+        .query_opt(
+            claim_stmt,
+            &[&worker_id, &LEASE_DURATION_SECS, &MAX_JUDGE_ATTEMPTS],
+        )
+        .await
+    {
+        Ok(row) => Ok(row.map(|row| row.get(0))),
+        Err(error) => {
+            error!("Error occured while claiming a job. ERROR = {:?}", error);
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Extends lease of the job held by 'worker_id'.
+pub async fn renew_lease(
+    db: &Pool,
+    ticket_id: TicketId,
+    worker_id: WorkerId,
+) -> Result<(), JudgeError> {
+    let renew_stmt = include_str!("renew_lease.sql");
+    let client = get_client(db).await?;
+
+    match client
+        .execute(renew_stmt, &[&ticket_id, &worker_id, &LEASE_DURATION_SECS])
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while renewing lease. TicketId = {}, ERROR = {:?}",
+                ticket_id, error
+            );
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+pub async fn set_state(
+    db: &Pool,
+    ticket_id: TicketId,
+    worker_id: WorkerId,
+    state: JobState,
+) -> Result<(), JudgeError> {
+    let update_stmt = include_str!("set_job_state.sql");
+    let client = get_client(db).await?;
+
+    match client
+        .execute(update_stmt, &[&ticket_id, &worker_id, &state.to_string()])
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while setting job state. TicketId = {}, ERROR = {:?}",
+                ticket_id, error
+            );
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Tickets whose jobs are held by judges with valid leases.
+pub async fn leased_tickets(db: &Pool) -> Result<HashSet<TicketId>, JudgeError> {
+    let select_stmt = include_str!("query_leased_jobs.sql");
+    let client = get_client(db).await?;
+
+    match client.query(select_stmt, &[]).await {
+        Ok(rows) => Ok(rows.iter().map(|row| row.get(0)).collect()),
+        Err(error) => {
+            error!(
+                "Error occured while querying leased jobs. ERROR = {:?}",
+                error
+            );
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+/// Marks jobs with expired leases which ran out of attempts as failed. Returns
+/// ids of their tickets, which have to be marked as system errors by the caller.
+pub async fn fail_exhausted(db: &Pool) -> Result<Vec<TicketId>, JudgeError> {
+    let update_stmt = include_str!("fail_exhausted_jobs.sql");
+    let client = get_client(db).await?;
+
+    match client.query(update_stmt, &[&MAX_JUDGE_ATTEMPTS]).await {
+        Ok(rows) => Ok(rows.iter().map(|row| row.get(0)).collect()),
+        Err(error) => {
+            error!(
+                "Error occured while failing exhausted jobs. ERROR = {:?}",
+                error
+            );
+            Err(JudgeError::DatabaseError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_database;
+
+    /// Tickets of queue tests, which are removed before every test.
+    const FIRST_TEST_TICKET: TicketId = 900_000_000;
+    const LAST_TEST_TICKET: TicketId = FIRST_TEST_TICKET + 99;
+
+    /// Queue is shared, so tests which use it run one at a time.
+    static QUEUE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Empties the queue and inserts tickets with 'count' jobs in order of ids.
+    async fn fill_queue(db: &Pool, count: i64) -> Vec<TicketId> {
+        let mut client = db.get().await.unwrap();
+        client
+            .batch_execute(&format!(
+                "DELETE FROM ticket_data.judge_queue;
+                DELETE FROM ticket_data.tickets
+                WHERE id BETWEEN {FIRST_TEST_TICKET} AND {LAST_TEST_TICKET}"
+            ))
+            .await
+            .unwrap();
+
+        let mut tickets = Vec::new();
+
+        for ticket_id in FIRST_TEST_TICKET..FIRST_TEST_TICKET + count {
+            let transaction = client.transaction().await.unwrap();
+            transaction
+                .execute(
+                    "INSERT INTO ticket_data.tickets (id, owner_id, lang, content, exercise_id)
+                    VALUES ($1, 1, 'C', '', 1)",
+                    &[&ticket_id],
+                )
+                .await
+                .unwrap();
+            enqueue(&transaction, ticket_id, i64::MAX).await.unwrap();
+            transaction.commit().await.unwrap();
+
+            tickets.push(ticket_id);
+        }
+
+        tickets
+    }
+
+    /// State, attempts and worker of the job.
+    async fn job(db: &Pool, ticket_id: TicketId) -> (String, i32, Option<WorkerId>) {
+        let row = db
+            .get()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT job_state, attempts, worker_id FROM ticket_data.judge_queue
+                WHERE ticket_id = $1",
+                &[&ticket_id],
+            )
+            .await
+            .unwrap();
+
+        (row.get(0), row.get(1), row.get(2))
+    }
+
+    async fn expire_lease(db: &Pool, ticket_id: TicketId) {
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "UPDATE ticket_data.judge_queue SET lease_expires = now() - interval '1 second'
+                WHERE ticket_id = $1",
+                &[&ticket_id],
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn worker_id_starts_with_instance_id() {
+        let worker_id = worker_id(InstanceId::MAX, 3);
+
+        // The same shift is used to find instance of the worker in `recover_jobs.sql`.
+        assert_eq!(worker_id >> 32, i64::from(InstanceId::MAX));
+        assert_eq!(worker_id & 0xffff_ffff, 3);
+        assert_ne!(worker_id, super::worker_id(InstanceId::MAX, 4));
+    }
+
+    #[test]
+    fn job_states_match_database_values() {
+        assert_eq!(JobState::Running.to_string(), "Running");
+        assert_eq!(JobState::Done.to_string(), "Done");
+        assert_eq!(JobState::Failed.to_string(), "Failed");
+    }
+
+    #[actix_web::test]
+    async fn jobs_are_claimed_once_in_order() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        let tickets = fill_queue(&db, 2).await;
+
+        assert_eq!(claim(&db, 1).await.unwrap(), Some(tickets[0]));
+        assert_eq!(claim(&db, 2).await.unwrap(), Some(tickets[1]));
+        assert_eq!(claim(&db, 1).await.unwrap(), None);
+        assert_eq!(
+            job(&db, tickets[1]).await,
+            (String::from("Claimed"), 1, Some(2))
+        );
+    }
+
+    #[actix_web::test]
+    async fn full_queue_rejects_jobs() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        fill_queue(&db, 1).await;
+        let mut client = db.get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+
+        let result = enqueue(&transaction, FIRST_TEST_TICKET + 1, 1).await;
+
+        assert!(matches!(result, Err(JudgeError::QueueFull)));
+    }
+
+    #[actix_web::test]
+    async fn only_holder_of_the_job_changes_it() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        let tickets = fill_queue(&db, 1).await;
+        claim(&db, 1).await.unwrap();
+
+        set_state(&db, tickets[0], 2, JobState::Done).await.unwrap();
+        assert_eq!(job(&db, tickets[0]).await.0, "Claimed");
+
+        set_state(&db, tickets[0], 1, JobState::Running)
+            .await
+            .unwrap();
+        assert_eq!(job(&db, tickets[0]).await.0, "Running");
+    }
+
+    #[actix_web::test]
+    async fn abandoned_job_is_claimed_again_until_attempts_run_out() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        let tickets = fill_queue(&db, 1).await;
+
+        for attempt in 1..=MAX_JUDGE_ATTEMPTS {
+            let worker_id = WorkerId::from(attempt);
+
+            assert_eq!(claim(&db, worker_id).await.unwrap(), Some(tickets[0]));
+            assert_eq!(job(&db, tickets[0]).await.1, attempt);
+            assert!(fail_exhausted(&db).await.unwrap().is_empty());
+            expire_lease(&db, tickets[0]).await;
+        }
+
+        assert_eq!(claim(&db, 10).await.unwrap(), None);
+        assert_eq!(fail_exhausted(&db).await.unwrap(), tickets);
+        assert_eq!(job(&db, tickets[0]).await.0, "Failed");
+    }
+
+    #[actix_web::test]
+    async fn jobs_of_dead_instances_are_recovered() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        let tickets = fill_queue(&db, 4).await;
+        let live_instance = register_instance(&db).await.unwrap();
+        let live_worker = worker_id(live_instance, 0);
+        // Locks of instances are non-negative, so this one is never held.
+        let dead_worker = worker_id(-1, 0);
+
+        for (ticket_id, worker_id) in tickets.iter().zip([live_worker, dead_worker, live_worker]) {
+            assert_eq!(claim(&db, worker_id).await.unwrap(), Some(*ticket_id));
+        }
+        expire_lease(&db, tickets[2]).await;
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "UPDATE ticket_data.judge_queue SET job_state = 'Running', attempts = $2, worker_id = $3,
+                    lease_expires = now() + interval '1 minute'
+                WHERE ticket_id = $1",
+                &[&tickets[3], &MAX_JUDGE_ATTEMPTS, &dead_worker],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(recover(&db).await.unwrap(), 3);
+
+        assert_eq!(
+            job(&db, tickets[0]).await,
+            (String::from("Claimed"), 1, Some(live_worker))
+        );
+        assert_eq!(
+            job(&db, tickets[1]).await,
+            (String::from("Queued"), 1, None)
+        );
+        assert_eq!(
+            job(&db, tickets[2]).await,
+            (String::from("Queued"), 1, None)
+        );
+        // Exhausted job is not requeued, its lease is expired to fail it.
+        assert_eq!(fail_exhausted(&db).await.unwrap(), [tickets[3]]);
+    }
+
+    #[actix_web::test]
+    async fn only_jobs_with_valid_leases_are_leased() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let _queue = QUEUE.lock().await;
+        let tickets = fill_queue(&db, 4).await;
+        for worker_id in 1..=3 {
+            claim(&db, worker_id).await.unwrap();
+        }
+        set_state(&db, tickets[1], 2, JobState::Running)
+            .await
+            .unwrap();
+        expire_lease(&db, tickets[2]).await;
+
+        let leased = leased_tickets(&db).await.unwrap();
+
+        assert_eq!(leased, HashSet::from([tickets[0], tickets[1]]));
+
+        set_state(&db, tickets[0], 1, JobState::Done).await.unwrap();
+        assert_eq!(
+            leased_tickets(&db).await.unwrap(),
+            HashSet::from([tickets[1]])
+        );
+    }
+}
@@ -0,0 +1,14 @@
+UPDATE ticket_data.judge_queue
+SET job_state = CASE WHEN attempts < $2 THEN 'Queued' ELSE job_state END,
+    worker_id = CASE WHEN attempts < $2 THEN NULL ELSE worker_id END,
+    lease_expires = CASE WHEN attempts < $2 THEN NULL ELSE LEAST(lease_expires, now()) END
+WHERE job_state IN ('Claimed', 'Running')
+    AND (lease_expires < now() OR NOT EXISTS (
+        SELECT 1
+        FROM pg_locks
+        WHERE locktype = 'advisory'
+            AND granted
+            AND classid::bigint = $1
+            AND objid::bigint = judge_queue.worker_id >> 32
+            AND objsubid = 2
+    ));
\ No newline at end of file
@@ -0,0 +1,3 @@
+UPDATE ticket_data.judge_queue
+SET lease_expires = now() + make_interval(secs => $3)
+WHERE ticket_id = $1 AND worker_id = $2;
\ No newline at end of file
@@ -0,0 +1,166 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::ticket::TicketId;
+
+/// Policy of retrying operations which may fail temporarily (Docker daemon or
+/// database being unavailable). Delay between attempts grows exponentially from
+/// 'initial_backoff' up to 'max_backoff', with random jitter added to spread
+/// retries of many judges in time.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Fraction of the backoff by which delay may randomly differ, from range [0, 1].
+    pub jitter: f64,
+}
+
+/// Policy of retrying Docker, filesystem and database operations of judges.
+pub const RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(200),
+    max_backoff: Duration::from_secs(10),
+    jitter: 0.2,
+};
+
+impl RetryPolicy {
+    /// Delay before attempt number 'attempt' (counting from 1) is retried.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        use rand::prelude::*;
+
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        let jitter_factor = 1.0 + self.jitter * thread_rng().gen_range(-1.0..=1.0);
+
+        backoff.mul_f64(jitter_factor.max(0.0))
+    }
+
+    /// Runs 'operation' until it succeeds or runs out of attempts. Error of the
+    /// last attempt is returned.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        operation_name: &str,
+        ticket_id: TicketId,
+        mut operation: F,
+    ) -> Result<T, E>
+    where
+        E: Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match operation().await {
+                Ok(result) => return Ok(result),
+                Err(error) if attempt >= self.max_attempts => {
+                    error!(
+                        "Error occured while {}. Giving up after {} attempts. TicketId = {}, ERROR = {:?}",
+                        operation_name, attempt, ticket_id, error
+                    );
+                    return Err(error);
+                }
+                Err(error) => {
+                    warn!(
+                        "Error occured while {}. Trying again. Attempt = {}/{}, TicketId = {}, ERROR = {:?}",
+                        operation_name, attempt, self.max_attempts, ticket_id, error
+                    );
+                }
+            }
+
+            tokio::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+        jitter: 0.0,
+    };
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        assert_eq!(POLICY.backoff(1), Duration::from_millis(100));
+        assert_eq!(POLICY.backoff(2), Duration::from_millis(200));
+        assert_eq!(POLICY.backoff(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(POLICY.backoff(5), Duration::from_secs(1));
+        assert_eq!(POLICY.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_in_range() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..POLICY
+        };
+
+        for _ in 0..100 {
+            let backoff = policy.backoff(2);
+            assert!(backoff >= Duration::from_millis(100));
+            assert!(backoff <= Duration::from_millis(300));
+        }
+    }
+
+    #[tokio::test]
+    async fn run_gives_up_after_max_attempts() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::ZERO,
+            ..POLICY
+        };
+        let mut attempts = 0;
+
+        let result: Result<(), u32> = policy
+            .run("testing", 1, || {
+                attempts += 1;
+                let attempt = attempts;
+                async move { Err(attempt) }
+            })
+            .await;
+
+        assert_eq!(result, Err(3));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn run_returns_first_success() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::ZERO,
+            ..POLICY
+        };
+        let mut attempts = 0;
+
+        let result: Result<u32, ()> = policy
+            .run("testing", 1, || {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    if attempt < 2 {
+                        Err(())
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok(2));
+    }
+}
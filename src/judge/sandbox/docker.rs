@@ -0,0 +1,611 @@
+use bollard::{
+    self,
+    container::{
+        CreateContainerOptions, DownloadFromContainerOptions, ListContainersOptions, LogOutput,
+        LogsOptions, RemoveContainerOptions, StartContainerOptions, UploadToContainerOptions,
+        WaitContainerOptions,
+    },
+    models::{ContainerSummary, HostConfig},
+};
+use deadpool_postgres::Pool;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_stream::StreamExt;
+
+use super::{output_test_name, COMPILER_OUTPUT_FILE, OUTPUTS_DIR, REPORT_FILE};
+use super::{ProgressReporter, RunOutcome, Sandbox, SandboxOutput};
+use crate::judge::limits::ExerciseLimits;
+use crate::judge::queue;
+use crate::judge::retry::RETRY_POLICY;
+use crate::judge::JudgeError;
+use crate::ticket::{Language, TicketId};
+
+/// User (uid:gid) which runs testing program inside of the container.
+const TESTING_USER: &str = "1000:1000";
+
+const PROGRAM_PATH: &str = "/program";
+const TESTS_PATH: &str = "/tests";
+/// Directory in testing container where report of judging is written.
+const OUTPUT_PATH: &str = "/output";
+/// Writable directory for compilation and running of the submission.
+const WORK_DIR: &str = "/work";
+
+/// Label marking containers created by alsit, used to find orphaned ones.
+const MANAGED_LABEL: &str = "alsit.managed";
+/// Label with id of the ticket judged in the container.
+const TICKET_LABEL: &str = "alsit.ticket_id";
+
+/// Containers younger than this are never removed, so that container is not taken
+/// for orphaned while the job of its ticket is being claimed.
+const MIN_ORPHAN_CONTAINER_AGE: Duration = Duration::from_secs(5 * 60);
+/// Interval between searches for orphaned containers.
+const REAPER_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Sandbox running testing program in a container created from testing image.
+pub struct DockerSandbox {
+    ticket_id: TicketId,
+    image_name: String,
+    container_name: String,
+}
+
+impl DockerSandbox {
+    pub fn new(image_name: &str, ticket_id: TicketId) -> DockerSandbox {
+        DockerSandbox {
+            ticket_id,
+            image_name: String::from(image_name),
+            container_name: format!("{}{}", image_name, ticket_id),
+        }
+    }
+
+    async fn upload(
+        &self,
+        path: &str,
+        tar: Vec<u8>,
+        operation_name: &str,
+    ) -> Result<(), JudgeError> {
+        RETRY_POLICY
+            .run(operation_name, self.ticket_id, || {
+                let upload_options = UploadToContainerOptions {
+                    path,
+                    ..Default::default()
+                };
+
+                crate::DOCKER.upload_to_container(
+                    &self.container_name,
+                    Some(upload_options),
+                    tar.clone().into(),
+                )
+            })
+            .await
+            .map_err(|_| JudgeError::DockerError)
+    }
+
+    /// Waits until container stops and returns its exit code.
+    async fn wait(&self) -> Option<i64> {
+        let wait_container_options: WaitContainerOptions<&str> = WaitContainerOptions::default();
+
+        match crate::DOCKER
+            .wait_container(&self.container_name, Some(wait_container_options))
+            .next()
+            .await
+        {
+            Some(Ok(response)) => Some(response.status_code),
+            Some(Err(error)) => {
+                error!(
+                    "Error occured while waiting for container. TicketId = {}, ERROR = {}",
+                    self.ticket_id, error
+                );
+                None
+            }
+            None => {
+                error!(
+                    "Container stopped without reporting exit code. TicketId = {}",
+                    self.ticket_id
+                );
+                None
+            }
+        }
+    }
+
+    /// Feeds stdout of the container into 'progress'. It never returns, so it has to
+    /// be cancelled once container stops.
+    async fn follow_progress(&self, progress: &mut ProgressReporter) {
+        let logs_options = LogsOptions::<String> {
+            follow: true,
+            stdout: true,
+            ..Default::default()
+        };
+
+        let logs = crate::DOCKER.logs(&self.container_name, Some(logs_options));
+        tokio::pin!(logs);
+
+        while let Some(output) = logs.next().await {
+            match output {
+                Ok(LogOutput::StdOut { message }) => progress.feed(&message),
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(
+                        "Error occured while reading container output. TicketId = {}, ERROR = {}",
+                        self.ticket_id, error
+                    );
+                    break;
+                }
+            }
+        }
+
+        std::future::pending::<()>().await;
+    }
+
+    /// Downloads output directory of the container as a tar archive.
+    async fn download_output(&self) -> Result<Vec<u8>, bollard::errors::Error> {
+        let options = DownloadFromContainerOptions { path: OUTPUT_PATH };
+        let mut output_stream =
+            crate::DOCKER.download_from_container(&self.container_name, Some(options));
+        let mut output_tar = Vec::new();
+
+        while let Some(chunk) = output_stream.next().await {
+            output_tar.extend_from_slice(&chunk?);
+        }
+
+        Ok(output_tar)
+    }
+}
+
+/// Removes containers created by alsit whose tickets are not held by any judge with
+/// a valid lease. They are left behind when server is stopped during judging or
+/// cleanup fails. Containers of tickets being judged are kept however long they run.
+async fn reap_orphaned_containers(db: &Pool) -> Result<usize, JudgeError> {
+    let label_filter = format!("{}=true", MANAGED_LABEL);
+    let list_options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([("label", vec![label_filter.as_str()])]),
+        ..Default::default()
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let max_created = now - MIN_ORPHAN_CONTAINER_AGE.as_secs() as i64;
+
+    let remove_options = RemoveContainerOptions {
+        force: true,
+        v: true,
+        ..Default::default()
+    };
+
+    let containers = match crate::DOCKER.list_containers(Some(list_options)).await {
+        Ok(containers) => containers,
+        Err(error) => {
+            error!(
+                "Error occured while searching for orphaned containers. ERROR = {}",
+                error
+            );
+            return Err(JudgeError::DockerError);
+        }
+    };
+    // Leases are read after containers are listed, so job of every listed
+    // container being judged is already claimed.
+    let leased_tickets = queue::leased_tickets(db).await?;
+
+    let mut removed = 0;
+
+    for container in containers {
+        if !is_orphaned(&container, &leased_tickets, max_created) {
+            continue;
+        }
+
+        let id = match container.id {
+            Some(id) => id,
+            None => continue,
+        };
+
+        match crate::DOCKER
+            .remove_container(&id, Some(remove_options))
+            .await
+        {
+            Ok(()) => removed += 1,
+            Err(error) => {
+                error!(
+                    "Error occured while removing orphaned container. ContainerId = {}, ERROR = {}",
+                    id, error
+                );
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Whether listed container created no later than 'max_created' belongs to no
+/// ticket in 'leased_tickets'. Containers without ticket label are orphaned too.
+fn is_orphaned(
+    container: &ContainerSummary,
+    leased_tickets: &HashSet<TicketId>,
+    max_created: i64,
+) -> bool {
+    match container.created {
+        Some(created) if created <= max_created => {}
+        _ => return false,
+    }
+
+    let ticket_id = container
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(TICKET_LABEL))
+        .and_then(|ticket_id| ticket_id.parse::<TicketId>().ok());
+
+    !matches!(ticket_id, Some(ticket_id) if leased_tickets.contains(&ticket_id))
+}
+
+/// Spawns task which removes orphaned containers at startup and then periodically.
+pub fn spawn_container_reaper(db: Pool) {
+    tokio::task::spawn(async move {
+        loop {
+            if let Ok(removed) = reap_orphaned_containers(&db).await {
+                if removed > 0 {
+                    info!("Removed {} orphaned testing containers.", removed);
+                }
+            }
+
+            tokio::time::sleep(REAPER_INTERVAL).await;
+        }
+    });
+}
+
+/// Configuration of the testing container. Submission gets no network, no capabilities,
+/// read-only root filesystem and runs as unprivileged user. Only directories used for
+/// exchanging data with the server (volumes) and work directory (tmpfs) are writable.
+fn host_config(limits: &ExerciseLimits) -> HostConfig {
+    let tmpfs_options = format!("rw,exec,nosuid,size={}m", limits.work_dir_mb);
+
+    HostConfig {
+        memory: Some(limits.memory_bytes()),
+        memory_swap: Some(limits.memory_bytes()),
+        nano_cpus: Some(limits.nano_cpus()),
+        pids_limit: Some(limits.pids),
+        network_mode: Some(String::from("none")),
+        readonly_rootfs: Some(true),
+        tmpfs: Some(HashMap::from([
+            (String::from(WORK_DIR), tmpfs_options.clone()),
+            (String::from("/tmp"), tmpfs_options),
+        ])),
+        cap_drop: Some(vec![String::from("ALL")]),
+        security_opt: Some(vec![String::from("no-new-privileges")]),
+        ..Default::default()
+    }
+}
+
+/// Extracts judging report, compiler output and outputs of the submission from
+/// the tar with output directory.
+async fn untar_output(output_tar: &[u8]) -> std::io::Result<SandboxOutput> {
+    use futures::io::AsyncReadExt;
+
+    let archive = async_tar::Archive::new(output_tar);
+    let mut entries = archive.entries()?;
+
+    let mut output = SandboxOutput::default();
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let in_outputs = path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .is_some_and(|parent| parent == OUTPUTS_DIR);
+
+        let mut content = Vec::new();
+
+        if in_outputs {
+            if let Some(test) = output_test_name(&file_name) {
+                entry.read_to_end(&mut content).await?;
+                output.outputs.insert(String::from(test), content);
+            }
+            continue;
+        }
+
+        let target = match file_name.as_str() {
+            REPORT_FILE => &mut output.report,
+            COMPILER_OUTPUT_FILE => &mut output.compiler_output,
+            _ => continue,
+        };
+
+        entry.read_to_end(&mut content).await?;
+        *target = Some(content);
+    }
+
+    Ok(output)
+}
+
+impl Sandbox for DockerSandbox {
+    async fn prepare(
+        &mut self,
+        lang: &Language,
+        limits: &ExerciseLimits,
+    ) -> Result<(), JudgeError> {
+        let container_name_config = CreateContainerOptions {
+            name: self.container_name.as_str(),
+        };
+
+        let env = [
+            format!("TEST_LANGUAGE={}", lang),
+            format!("PROGRAM_DIR={}", PROGRAM_PATH),
+            format!("TESTS_DIR={}", TESTS_PATH),
+            format!("OUTPUT_DIR={}", OUTPUT_PATH),
+            format!("WORK_DIR={}", WORK_DIR),
+        ];
+
+        // Volumes are needed, because files cannot be uploaded into read-only root filesystem.
+        let volumes = HashMap::from([
+            (PROGRAM_PATH, HashMap::new()),
+            (TESTS_PATH, HashMap::new()),
+            (OUTPUT_PATH, HashMap::new()),
+        ]);
+
+        let ticket_id = self.ticket_id.to_string();
+        let labels = HashMap::from([(MANAGED_LABEL, "true"), (TICKET_LABEL, ticket_id.as_str())]);
+
+        let config = bollard::container::Config {
+            image: Some(self.image_name.as_str()),
+            labels: Some(labels),
+            env: Some(env.iter().map(String::as_str).collect()),
+            user: Some(TESTING_USER),
+            volumes: Some(volumes),
+            network_disabled: Some(true),
+            host_config: Some(host_config(limits)),
+            ..Default::default()
+        };
+
+        let remove_options = RemoveContainerOptions {
+            force: true,
+            v: true,
+            ..Default::default()
+        };
+
+        let container_name = self.container_name.as_str();
+        let container_name_config = &container_name_config;
+        let config = &config;
+
+        RETRY_POLICY
+            .run("creating container", self.ticket_id, move || async move {
+                // Container may be left over from previous judging of the same ticket.
+                let _ = crate::DOCKER
+                    .remove_container(container_name, Some(remove_options))
+                    .await;
+
+                crate::DOCKER
+                    .create_container(Some(container_name_config.clone()), config.clone())
+                    .await
+            })
+            .await
+            .map_err(|_| JudgeError::DockerError)?;
+
+        Ok(())
+    }
+
+    async fn upload_program(&mut self, program_tar: Vec<u8>) -> Result<(), JudgeError> {
+        self.upload(PROGRAM_PATH, program_tar, "uploading program to container")
+            .await
+    }
+
+    async fn upload_tests(&mut self, tests_tar: Vec<u8>) -> Result<(), JudgeError> {
+        self.upload(TESTS_PATH, tests_tar, "uploading tests to container")
+            .await
+    }
+
+    async fn run(
+        &mut self,
+        wall_time_limit: Duration,
+        progress: &mut ProgressReporter,
+    ) -> Result<RunOutcome, JudgeError> {
+        RETRY_POLICY
+            .run("starting container", self.ticket_id, || {
+                crate::DOCKER
+                    .start_container(&self.container_name, None::<StartContainerOptions<String>>)
+            })
+            .await
+            .map_err(|_| JudgeError::DockerError)?;
+
+        tokio::select! {
+            waiting_result = tokio::time::timeout(wall_time_limit, self.wait()) => {
+                match waiting_result {
+                    Ok(exit_code) => Ok(RunOutcome::Finished { exit_code }),
+                    Err(_) => {
+                        // Container is killed during cleanup.
+                        Ok(RunOutcome::TimedOut)
+                    }
+                }
+            }
+            _ = self.follow_progress(progress) => unreachable!(),
+        }
+    }
+
+    async fn collect_output(&mut self) -> Result<SandboxOutput, JudgeError> {
+        let output_tar = RETRY_POLICY
+            .run("downloading output from container", self.ticket_id, || {
+                self.download_output()
+            })
+            .await
+            .map_err(|_| JudgeError::DockerError)?;
+
+        untar_output(&output_tar).await.map_err(|error| {
+            error!(
+                "Error occured while unpacking container output. TicketId = {}, ERROR = {}",
+                self.ticket_id, error
+            );
+            JudgeError::InternalError
+        })
+    }
+
+    /// Removes container together with its volumes, killing it if it is still running.
+    async fn cleanup(&mut self) {
+        let remove_options = RemoveContainerOptions {
+            force: true,
+            v: true,
+            ..Default::default()
+        };
+
+        if let Err(error) = crate::DOCKER
+            .remove_container(&self.container_name, Some(remove_options))
+            .await
+        {
+            error!(
+                "Error occured while removing container. TicketId = {}, ERROR = {}",
+                self.ticket_id, error
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tar as downloaded from the container, with output directory as its root.
+    async fn output_tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        let mut builder = async_tar::Builder::new(&mut tar);
+
+        for (path, content) in files {
+            let mut header = async_tar::Header::new_gnu();
+            header.set_path(format!("output/{path}")).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *content).await.unwrap();
+        }
+
+        builder.into_inner().await.unwrap();
+        tar
+    }
+
+    #[tokio::test]
+    async fn output_files_are_extracted() {
+        let tar = output_tar(&[
+            (REPORT_FILE, b"{}"),
+            (COMPILER_OUTPUT_FILE, b"warning"),
+            ("outputs/a.out", b"1"),
+            ("outputs/b.out", b""),
+            ("outputs/c.txt", b"3"),
+            ("a.out", b"4"),
+            ("other.txt", b"5"),
+        ])
+        .await;
+
+        let output = untar_output(&tar).await.unwrap();
+
+        assert_eq!(output.report.as_deref(), Some(&b"{}"[..]));
+        assert_eq!(output.compiler_output.as_deref(), Some(&b"warning"[..]));
+        assert_eq!(
+            output.outputs,
+            HashMap::from([
+                (String::from("a"), b"1".to_vec()),
+                (String::from("b"), Vec::new())
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_output_directory_has_no_files() {
+        let output = untar_output(&output_tar(&[]).await).await.unwrap();
+
+        assert!(output.report.is_none() && output.compiler_output.is_none());
+        assert!(output.outputs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn broken_tar_is_error() {
+        let mut tar = output_tar(&[(REPORT_FILE, b"{}")]).await;
+        tar[148] ^= 1;
+
+        assert!(untar_output(&tar).await.is_err());
+    }
+
+    #[test]
+    fn container_gets_limits_of_exercise() {
+        let limits = ExerciseLimits {
+            memory_mb: 256,
+            cpus: 1.5,
+            pids: 32,
+            work_dir_mb: 16,
+            wall_time_secs: 10,
+        };
+
+        let config = host_config(&limits);
+
+        assert_eq!(config.memory, Some(256 * 1024 * 1024));
+        // Swap limit includes memory, so the container cannot swap.
+        assert_eq!(config.memory_swap, config.memory);
+        assert_eq!(config.nano_cpus, Some(1_500_000_000));
+        assert_eq!(config.pids_limit, Some(32));
+
+        let tmpfs = config.tmpfs.unwrap();
+        assert_eq!(tmpfs[WORK_DIR], "rw,exec,nosuid,size=16m");
+        assert_eq!(tmpfs["/tmp"], "rw,exec,nosuid,size=16m");
+    }
+
+    #[test]
+    fn container_is_isolated() {
+        let config = host_config(&ExerciseLimits::default());
+
+        assert_eq!(config.network_mode.as_deref(), Some("none"));
+        assert_eq!(config.readonly_rootfs, Some(true));
+        assert_eq!(config.cap_drop, Some(vec![String::from("ALL")]));
+        assert_eq!(
+            config.security_opt,
+            Some(vec![String::from("no-new-privileges")])
+        );
+        assert_eq!(config.privileged, None);
+    }
+
+    fn container(created: i64, ticket_id: Option<&str>) -> ContainerSummary {
+        let mut labels = HashMap::from([(String::from(MANAGED_LABEL), String::from("true"))]);
+
+        if let Some(ticket_id) = ticket_id {
+            labels.insert(String::from(TICKET_LABEL), String::from(ticket_id));
+        }
+
+        ContainerSummary {
+            id: Some(String::from("container")),
+            created: Some(created),
+            labels: Some(labels),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn containers_of_leased_tickets_are_kept() {
+        let leased = HashSet::from([1]);
+
+        assert!(!is_orphaned(&container(100, Some("1")), &leased, 100));
+        assert!(is_orphaned(&container(100, Some("2")), &leased, 100));
+    }
+
+    #[test]
+    fn young_containers_are_kept() {
+        let leased = HashSet::new();
+
+        assert!(!is_orphaned(&container(101, Some("2")), &leased, 100));
+        assert!(!is_orphaned(
+            &ContainerSummary {
+                created: None,
+                ..container(0, Some("2"))
+            },
+            &leased,
+            100
+        ));
+    }
+
+    #[test]
+    fn containers_without_ticket_are_orphaned() {
+        let leased = HashSet::from([1]);
+
+        assert!(is_orphaned(&container(0, None), &leased, 100));
+        assert!(is_orphaned(&container(0, Some("x")), &leased, 100));
+    }
+}
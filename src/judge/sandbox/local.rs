@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use super::{output_test_name, COMPILER_OUTPUT_FILE, OUTPUTS_DIR, REPORT_FILE};
+use super::{LocalSandboxConfig, ProgressReporter, RunOutcome, Sandbox, SandboxOutput};
+use crate::judge::limits::ExerciseLimits;
+use crate::judge::JudgeError;
+use crate::ticket::{Language, TicketId};
+
+/// Number of times removal of busy cgroup is retried during cleanup.
+const CGROUP_REMOVAL_ATTEMPTS: usize = 10;
+const CGROUP_REMOVAL_DELAY: Duration = Duration::from_millis(100);
+
+/// Sandbox running testing program as a process on the judging machine. It has its
+/// own working directory and resource limits; optionally it is also moved into
+/// separate namespaces, so it has no network access. Testing program leads its own
+/// process group, which is killed as a whole on timeout.
+///
+/// Limits are weaker than in [super::DockerSandbox]:
+///
+/// * number of processes and total memory are limited only with cgroup created in
+///   `judge.local_cgroup_dir` (`RLIMIT_NPROC` would count all processes of the
+///   server's user). Without memory controller in that cgroup, every process,
+///   compiler included, gets its own `RLIMIT_AS` of `memory_mb`,
+/// * size of working directory is not limited, `work_dir_mb` is the maximal size
+///   of a single file (`RLIMIT_FSIZE`),
+/// * CPU time of every process is limited to the wall time limit (`RLIMIT_CPU`),
+///   `cpus` is not applied.
+///
+/// It is meant for machines without Docker, such as developement ones.
+pub struct LocalSandbox {
+    config: LocalSandboxConfig,
+    ticket_id: TicketId,
+    sandbox_dir: PathBuf,
+    lang: Option<Language>,
+    limits: ExerciseLimits,
+}
+
+impl LocalSandbox {
+    pub fn new(config: LocalSandboxConfig, ticket_id: TicketId) -> LocalSandbox {
+        let sandbox_dir = config.root_dir.join(ticket_id.to_string());
+
+        LocalSandbox {
+            config,
+            ticket_id,
+            sandbox_dir,
+            lang: None,
+            limits: ExerciseLimits::default(),
+        }
+    }
+
+    fn program_dir(&self) -> PathBuf {
+        self.sandbox_dir.join("program")
+    }
+
+    fn tests_dir(&self) -> PathBuf {
+        self.sandbox_dir.join("tests")
+    }
+
+    fn output_dir(&self) -> PathBuf {
+        self.sandbox_dir.join("output")
+    }
+
+    fn work_dir(&self) -> PathBuf {
+        self.sandbox_dir.join("work")
+    }
+
+    fn cgroup(&self) -> Option<PathBuf> {
+        self.config
+            .cgroup_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("alsit-{}", self.ticket_id)))
+    }
+
+    /// Creates cgroup limiting number of processes and, if memory controller is
+    /// enabled, total memory of the sandbox.
+    async fn create_cgroup(
+        &self,
+        cgroup: &Path,
+        limits: &ExerciseLimits,
+    ) -> std::io::Result<CgroupSetup> {
+        tokio::fs::create_dir_all(cgroup).await?;
+        tokio::fs::write(cgroup.join("pids.max"), limits.pids.to_string()).await?;
+
+        let limits_memory =
+            match tokio::fs::write(cgroup.join("memory.max"), limits.memory_bytes().to_string())
+                .await
+            {
+                Ok(()) => {
+                    // Swap is missing when the machine has none.
+                    let _ = tokio::fs::write(cgroup.join("memory.swap.max"), "0").await;
+                    true
+                }
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::PermissionDenied
+                        || error.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    false
+                }
+                Err(error) => return Err(error),
+            };
+
+        let procs_path = CString::new(cgroup.join("cgroup.procs").into_os_string().into_vec())
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+
+        Ok(CgroupSetup {
+            procs_path,
+            limits_memory,
+        })
+    }
+
+    fn internal_error(&self, operation_name: &str, error: std::io::Error) -> JudgeError {
+        error!(
+            "Error occured while {} in local sandbox. TicketId = {}, ERROR = {}",
+            operation_name, self.ticket_id, error
+        );
+        JudgeError::InternalError
+    }
+
+    async fn unpack(&self, tar: Vec<u8>, target: PathBuf) -> std::io::Result<()> {
+        async_tar::Archive::new(tar.as_slice()).unpack(target).await
+    }
+
+    /// Reads outputs of the submission, there are none if testing program did not
+    /// create their directory.
+    async fn read_outputs(&self) -> std::io::Result<HashMap<String, Vec<u8>>> {
+        let mut outputs = HashMap::new();
+
+        let mut entries = match tokio::fs::read_dir(self.output_dir().join(OUTPUTS_DIR)).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(outputs),
+            Err(error) => return Err(error),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+
+            if let Some(test) = file_name.to_str().and_then(output_test_name) {
+                outputs.insert(String::from(test), tokio::fs::read(entry.path()).await?);
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    async fn read_output_file(&self, file_name: &str) -> std::io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.output_dir().join(file_name)).await {
+            Ok(content) => Ok(Some(content)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Cgroup of the sandbox, into which testing program moves itself.
+struct CgroupSetup {
+    /// Path of its `cgroup.procs`.
+    procs_path: CString,
+    /// Whether `memory.max` is set, so address space of processes is not limited.
+    limits_memory: bool,
+}
+
+#[cfg(target_env = "gnu")]
+pub type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+pub type RlimitResource = libc::c_int;
+
+/// Moves the current process into cgroup with 'procs_path'.
+fn join_cgroup(procs_path: &CString) -> std::io::Result<()> {
+    // SAFETY: 'procs_path' is a valid C string, the buffer lives for the whole write.
+    unsafe {
+        let fd = libc::open(procs_path.as_ptr(), libc::O_WRONLY);
+
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+        libc::close(fd);
+
+        if written != 1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets resource limit of the current process.
+pub fn set_limit(resource: RlimitResource, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+
+    // SAFETY: 'limit' is a valid rlimit structure living for the whole call.
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+impl Sandbox for LocalSandbox {
+    async fn prepare(
+        &mut self,
+        lang: &Language,
+        limits: &ExerciseLimits,
+    ) -> Result<(), JudgeError> {
+        // Directory may be left over from previous judging of the same ticket.
+        let _ = tokio::fs::remove_dir_all(&self.sandbox_dir).await;
+
+        for dir in [
+            self.program_dir(),
+            self.tests_dir(),
+            self.output_dir(),
+            self.work_dir(),
+        ] {
+            if let Err(error) = tokio::fs::create_dir_all(&dir).await {
+                return Err(self.internal_error("creating directories", error));
+            }
+        }
+
+        self.lang = Some(lang.clone());
+        self.limits = limits.clone();
+
+        Ok(())
+    }
+
+    async fn upload_program(&mut self, program_tar: Vec<u8>) -> Result<(), JudgeError> {
+        self.unpack(program_tar, self.program_dir())
+            .await
+            .map_err(|error| self.internal_error("unpacking program", error))
+    }
+
+    async fn upload_tests(&mut self, tests_tar: Vec<u8>) -> Result<(), JudgeError> {
+        self.unpack(tests_tar, self.tests_dir())
+            .await
+            .map_err(|error| self.internal_error("unpacking tests", error))
+    }
+
+    async fn run(
+        &mut self,
+        wall_time_limit: Duration,
+        progress: &mut ProgressReporter,
+    ) -> Result<RunOutcome, JudgeError> {
+        let lang = match &self.lang {
+            Some(lang) => lang.to_string(),
+            None => {
+                error!(
+                    "Local sandbox was run before being prepared. TicketId = {}",
+                    self.ticket_id
+                );
+                return Err(JudgeError::InternalError);
+            }
+        };
+
+        let mut command = Command::new(&self.config.tester_path);
+        command
+            .current_dir(self.work_dir())
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .env("TEST_LANGUAGE", lang)
+            .env("PROGRAM_DIR", self.program_dir())
+            .env("TESTS_DIR", self.tests_dir())
+            .env("OUTPUT_DIR", self.output_dir())
+            .env("WORK_DIR", self.work_dir())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true);
+
+        let cgroup = match self.cgroup() {
+            Some(cgroup) => Some(
+                self.create_cgroup(&cgroup, &self.limits)
+                    .await
+                    .map_err(|error| self.internal_error("creating cgroup", error))?,
+            ),
+            None => None,
+        };
+
+        let limits_memory = matches!(&cgroup, Some(cgroup) if cgroup.limits_memory);
+        let cgroup_procs = cgroup.map(|cgroup| cgroup.procs_path);
+        let memory_bytes = self.limits.memory_bytes() as u64;
+        let file_size_bytes = self.limits.work_dir_mb as u64 * 1024 * 1024;
+        let cpu_secs = wall_time_limit.as_secs().max(1);
+        let unshare = self.config.unshare;
+
+        // SAFETY: closure runs in the child process between fork and exec, and calls
+        // only async-signal-safe functions (setpgid, open, write, setrlimit, unshare).
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                if let Some(procs_path) = &cgroup_procs {
+                    join_cgroup(procs_path)?;
+                }
+
+                if !limits_memory {
+                    set_limit(libc::RLIMIT_AS, memory_bytes)?;
+                }
+                set_limit(libc::RLIMIT_FSIZE, file_size_bytes)?;
+                set_limit(libc::RLIMIT_CPU, cpu_secs)?;
+                set_limit(libc::RLIMIT_CORE, 0)?;
+
+                if unshare {
+                    let flags = libc::CLONE_NEWUSER
+                        | libc::CLONE_NEWNET
+                        | libc::CLONE_NEWIPC
+                        | libc::CLONE_NEWUTS;
+
+                    if libc::unshare(flags) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|error| self.internal_error("starting testing program", error))?;
+
+        let stdout = child.stdout.take();
+        let process_group = child.id().map(|pid| pid as libc::pid_t);
+
+        let follow_progress = async move {
+            use tokio::io::AsyncReadExt;
+
+            if let Some(mut stdout) = stdout {
+                let mut chunk = [0u8; 4096];
+
+                while let Ok(read) = stdout.read(&mut chunk).await {
+                    if read == 0 {
+                        break;
+                    }
+                    progress.feed(&chunk[..read]);
+                }
+            }
+
+            // Testing program could close stdout before exiting.
+            std::future::pending::<()>().await;
+        };
+
+        let waiting_result = tokio::select! {
+            waiting_result = tokio::time::timeout(wall_time_limit, child.wait()) => waiting_result,
+            _ = follow_progress => unreachable!(),
+        };
+
+        match waiting_result {
+            Ok(Ok(status)) => Ok(RunOutcome::Finished {
+                exit_code: status.code().map(i64::from),
+            }),
+            Ok(Err(error)) => Err(self.internal_error("waiting for testing program", error)),
+            Err(_) => {
+                if let Some(process_group) = process_group {
+                    // SAFETY: killpg has no memory safety requirements. Testing program
+                    // was not waited for, so its process group still exists.
+                    unsafe {
+                        libc::killpg(process_group, libc::SIGKILL);
+                    }
+                }
+
+                let _ = child.kill().await;
+                Ok(RunOutcome::TimedOut)
+            }
+        }
+    }
+
+    async fn collect_output(&mut self) -> Result<SandboxOutput, JudgeError> {
+        let report = self
+            .read_output_file(REPORT_FILE)
+            .await
+            .map_err(|error| self.internal_error("reading report", error))?;
+
+        let compiler_output = self
+            .read_output_file(COMPILER_OUTPUT_FILE)
+            .await
+            .map_err(|error| self.internal_error("reading compiler output", error))?;
+
+        let outputs = self
+            .read_outputs()
+            .await
+            .map_err(|error| self.internal_error("reading outputs", error))?;
+
+        Ok(SandboxOutput {
+            report,
+            compiler_output,
+            outputs,
+        })
+    }
+
+    async fn cleanup(&mut self) {
+        if let Some(cgroup) = self.cgroup() {
+            // Kills processes which outlived testing program, so cgroup can be removed.
+            let _ = tokio::fs::write(cgroup.join("cgroup.kill"), "1").await;
+
+            let mut removal = tokio::fs::remove_dir(&cgroup).await;
+
+            // Killed processes leave cgroup asynchronously.
+            for _ in 0..CGROUP_REMOVAL_ATTEMPTS {
+                match &removal {
+                    Err(error) if error.raw_os_error() == Some(libc::EBUSY) => {
+                        tokio::time::sleep(CGROUP_REMOVAL_DELAY).await;
+                        removal = tokio::fs::remove_dir(&cgroup).await;
+                    }
+                    _ => break,
+                }
+            }
+
+            if let Err(error) = removal {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    warn!(
+                        "Error occured while removing cgroup of local sandbox. TicketId = {}, ERROR = {}",
+                        self.ticket_id, error
+                    );
+                }
+            }
+        }
+
+        if let Err(error) = tokio::fs::remove_dir_all(&self.sandbox_dir).await {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                error!(
+                    "Error occured while removing local sandbox directory. TicketId = {}, ERROR = {}",
+                    self.ticket_id, error
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::events::TicketEvents;
+
+    /// Sandbox running shell script 'script' as testing program. Every test uses
+    /// its own 'name', so their directories do not collide.
+    async fn sandbox(name: &str, script: &str) -> LocalSandbox {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root_dir =
+            std::env::temp_dir().join(format!("alsit-local-{}-{}", std::process::id(), name));
+        tokio::fs::create_dir_all(&root_dir).await.unwrap();
+
+        let tester_path = root_dir.join("tester.sh");
+        tokio::fs::write(&tester_path, format!("#!/bin/sh\n{script}\n"))
+            .await
+            .unwrap();
+        tokio::fs::set_permissions(&tester_path, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        let config = LocalSandboxConfig {
+            tester_path,
+            root_dir,
+            unshare: false,
+            cgroup_dir: None,
+        };
+
+        LocalSandbox::new(config, 7)
+    }
+
+    fn progress() -> ProgressReporter {
+        let mut config = deadpool_postgres::Config::new();
+        config.dbname = Some(String::from("alsit_test"));
+
+        let pool = config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap();
+
+        ProgressReporter::new(TicketEvents::new(), pool, 7)
+    }
+
+    async fn tar_file(path: &str, content: &[u8]) -> Vec<u8> {
+        let mut header = async_tar::Header::new_gnu();
+        header.set_path(path).unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        let mut tar = Vec::new();
+        let mut builder = async_tar::Builder::new(&mut tar);
+        builder.append(&header, content).await.unwrap();
+        builder.into_inner().await.unwrap();
+
+        tar
+    }
+
+    async fn remove(sandbox: &mut LocalSandbox) {
+        sandbox.cleanup().await;
+        let _ = tokio::fs::remove_dir_all(&sandbox.config.root_dir).await;
+    }
+
+    #[tokio::test]
+    async fn testing_program_reads_uploads_and_writes_output() {
+        let mut sandbox = sandbox(
+            "output",
+            r#"cat "$PROGRAM_DIR/main.c" "$TESTS_DIR/a.in" > "$OUTPUT_DIR/report.json"
+echo "$TEST_LANGUAGE $PWD" > "$OUTPUT_DIR/compile_stderr.txt"
+echo "ALSIT_PROGRESS test 1/1"
+exit 3"#,
+        )
+        .await;
+        let limits = ExerciseLimits::default();
+
+        sandbox.prepare(&Language::C, &limits).await.unwrap();
+        sandbox
+            .upload_program(tar_file("main.c", b"program ").await)
+            .await
+            .unwrap();
+        sandbox
+            .upload_tests(tar_file("a.in", b"input").await)
+            .await
+            .unwrap();
+
+        let outcome = sandbox
+            .run(Duration::from_secs(10), &mut progress())
+            .await
+            .unwrap();
+        let output = sandbox.collect_output().await.unwrap();
+
+        assert_eq!(outcome, RunOutcome::Finished { exit_code: Some(3) });
+        assert_eq!(output.report.as_deref(), Some(b"program input".as_slice()));
+        assert_eq!(
+            output.compiler_output,
+            Some(format!("C {}\n", sandbox.work_dir().display()).into_bytes())
+        );
+
+        remove(&mut sandbox).await;
+        assert!(!sandbox.sandbox_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn missing_output_is_not_an_error() {
+        let mut sandbox = sandbox("no-output", "exit 0").await;
+
+        sandbox
+            .prepare(&Language::Rust, &ExerciseLimits::default())
+            .await
+            .unwrap();
+        sandbox
+            .run(Duration::from_secs(10), &mut progress())
+            .await
+            .unwrap();
+        let output = sandbox.collect_output().await.unwrap();
+
+        assert!(output.report.is_none() && output.compiler_output.is_none());
+        assert!(output.outputs.is_empty());
+
+        remove(&mut sandbox).await;
+    }
+
+    #[tokio::test]
+    async fn outputs_of_tests_are_collected() {
+        let mut sandbox = sandbox(
+            "outputs",
+            r#"mkdir "$OUTPUT_DIR/outputs"
+echo 1 > "$OUTPUT_DIR/outputs/a.out"
+echo 2 > "$OUTPUT_DIR/outputs/b.txt""#,
+        )
+        .await;
+
+        sandbox
+            .prepare(&Language::C, &ExerciseLimits::default())
+            .await
+            .unwrap();
+        sandbox
+            .run(Duration::from_secs(10), &mut progress())
+            .await
+            .unwrap();
+        let output = sandbox.collect_output().await.unwrap();
+
+        assert_eq!(output.outputs.len(), 1);
+        assert_eq!(output.outputs["a"], b"1\n");
+
+        remove(&mut sandbox).await;
+    }
+
+    #[tokio::test]
+    async fn timeout_kills_whole_process_group() {
+        let mut sandbox = sandbox(
+            "timeout",
+            r#"(sleep 2; touch "$OUTPUT_DIR/report.json") &
+sleep 10"#,
+        )
+        .await;
+
+        sandbox
+            .prepare(&Language::C, &ExerciseLimits::default())
+            .await
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let outcome = sandbox
+            .run(Duration::from_millis(300), &mut progress())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RunOutcome::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Background process would have written the report by now.
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let output = sandbox.collect_output().await.unwrap();
+        assert!(output.report.is_none());
+
+        remove(&mut sandbox).await;
+    }
+
+    #[tokio::test]
+    async fn files_are_limited_to_work_dir_size() {
+        let mut sandbox = sandbox(
+            "file-size",
+            r#"head -c 2097152 /dev/zero > "$WORK_DIR/big" || exit 1
+head -c 1024 /dev/zero > "$WORK_DIR/small" || exit 2"#,
+        )
+        .await;
+        let limits = ExerciseLimits {
+            work_dir_mb: 1,
+            ..ExerciseLimits::default()
+        };
+
+        sandbox.prepare(&Language::C, &limits).await.unwrap();
+        let outcome = sandbox
+            .run(Duration::from_secs(10), &mut progress())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RunOutcome::Finished { exit_code: Some(1) });
+
+        remove(&mut sandbox).await;
+    }
+}
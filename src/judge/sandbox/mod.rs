@@ -0,0 +1,318 @@
+//! Environments in which submissions are compiled and tested.
+//!
+//! Judging goes through the same steps for every backend: sandbox is prepared,
+//! program and tests are uploaded, testing program is run (and killed after wall
+//! time limit), output directory is collected and finally sandbox is cleaned up.
+//! Testing program finds its directories in `PROGRAM_DIR`, `TESTS_DIR`,
+//! `OUTPUT_DIR` and `WORK_DIR` environment variables, and language of the
+//! submission in `TEST_LANGUAGE`.
+//!
+//! Testing program reports its progress by writing lines
+//! `ALSIT_PROGRESS test <n>/<total>` to stdout before running each test; they are
+//! forwarded to subscribers of ticket events. Other output is ignored.
+//!
+//! Testing program only runs the submission. For every test it writes what the
+//! submission printed to `outputs/<test>.out` in output directory and reports the
+//! test as `OK` when the submission finished within limits; such outputs are
+//! compared with expected ones by the judge, see [super::checker].
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use deadpool_postgres::Pool;
+
+use super::events::{TicketEvents, TicketProgress};
+use super::limits::ExerciseLimits;
+use super::JudgeError;
+use crate::config::{Config, SandboxKind};
+use crate::ticket::{self, Language, TicketId};
+
+mod docker;
+mod local;
+
+pub use docker::{spawn_container_reaper, DockerSandbox};
+pub(super) use local::set_limit;
+pub use local::LocalSandbox;
+
+/// File with judging report, format is described in [crate::ticket::results].
+pub const REPORT_FILE: &str = "report.json";
+/// File with everything compiler wrote to stderr.
+pub const COMPILER_OUTPUT_FILE: &str = "compile_stderr.txt";
+/// Directory with outputs of the submission on tests, named `<test>.out`.
+pub const OUTPUTS_DIR: &str = "outputs";
+
+/// Prefix of progress lines written by testing program.
+const PROGRESS_PREFIX: &str = "ALSIT_PROGRESS ";
+/// Longer lines are not progress lines, so they are not buffered whole.
+const MAX_PROGRESS_LINE_LEN: usize = 256;
+
+/// Finds progress lines in stdout of testing program and publishes them as
+/// ticket events. First test marks the ticket as running in the database.
+pub struct ProgressReporter {
+    events: TicketEvents,
+    db: Pool,
+    ticket_id: TicketId,
+    line: Vec<u8>,
+    line_too_long: bool,
+    running: bool,
+}
+
+impl ProgressReporter {
+    pub fn new(events: TicketEvents, db: Pool, ticket_id: TicketId) -> ProgressReporter {
+        ProgressReporter {
+            events,
+            db,
+            ticket_id,
+            line: Vec::new(),
+            line_too_long: false,
+            running: false,
+        }
+    }
+
+    pub fn publish(&self, progress: TicketProgress) {
+        self.events.publish(self.ticket_id, progress);
+    }
+
+    /// Consumes next chunk of stdout, which does not have to end at line boundary.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        for byte in chunk {
+            if *byte == b'\n' {
+                if !self.line_too_long {
+                    self.report_line();
+                }
+                self.line.clear();
+                self.line_too_long = false;
+            } else if self.line.len() < MAX_PROGRESS_LINE_LEN {
+                self.line.push(*byte);
+            } else {
+                self.line_too_long = true;
+            }
+        }
+    }
+
+    fn report_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line);
+        let progress = match parse_progress(line.trim_end()) {
+            Some(progress) => progress,
+            None => return,
+        };
+
+        if !self.running {
+            self.running = true;
+
+            // Reading stdout is not held up by the database.
+            let db = self.db.clone();
+            let ticket_id = self.ticket_id;
+            tokio::spawn(async move {
+                let _ = ticket::set_running(ticket_id, &db).await;
+            });
+        }
+
+        self.publish(progress);
+    }
+}
+
+/// Name of the test whose output is in file 'file_name' of [OUTPUTS_DIR].
+fn output_test_name(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_suffix(".out")
+        .filter(|test| !test.is_empty())
+}
+
+/// Parses `ALSIT_PROGRESS test <n>/<total>` line.
+fn parse_progress(line: &str) -> Option<TicketProgress> {
+    let progress = line.strip_prefix(PROGRESS_PREFIX)?;
+    let (test, total) = progress.strip_prefix("test ")?.split_once('/')?;
+
+    Some(TicketProgress::Running {
+        test: test.trim().parse().ok()?,
+        total: total.trim().parse().ok()?,
+    })
+}
+
+/// How testing program finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// Program exited by itself, exit code is `None` when it is unknown.
+    Finished { exit_code: Option<i64> },
+    /// Program exceeded wall time limit and was killed.
+    TimedOut,
+}
+
+/// Files read from output directory of the sandbox.
+#[derive(Debug, Default)]
+pub struct SandboxOutput {
+    pub report: Option<Vec<u8>>,
+    pub compiler_output: Option<Vec<u8>>,
+    /// Outputs of the submission by names of tests.
+    pub outputs: HashMap<String, Vec<u8>>,
+}
+
+pub trait Sandbox {
+    /// Creates environment for judging submission in 'lang' within 'limits'.
+    async fn prepare(&mut self, lang: &Language, limits: &ExerciseLimits)
+        -> Result<(), JudgeError>;
+
+    /// Uploads tar with the submission into program directory.
+    async fn upload_program(&mut self, program_tar: Vec<u8>) -> Result<(), JudgeError>;
+
+    /// Uploads tar with tests of the exercise into tests directory.
+    async fn upload_tests(&mut self, tests_tar: Vec<u8>) -> Result<(), JudgeError>;
+
+    /// Runs testing program and waits at most 'wall_time_limit' for it to finish.
+    /// Its stdout is fed into 'progress'.
+    async fn run(
+        &mut self,
+        wall_time_limit: Duration,
+        progress: &mut ProgressReporter,
+    ) -> Result<RunOutcome, JudgeError>;
+
+    /// Reads report and compiler output from output directory.
+    async fn collect_output(&mut self) -> Result<SandboxOutput, JudgeError>;
+
+    /// Removes everything created by the sandbox. It is safe to call it after
+    /// failure at any of previous steps.
+    async fn cleanup(&mut self);
+}
+
+/// Backend used for judging, chosen with `judge.sandbox` configuration key.
+#[derive(Clone, Debug)]
+pub enum SandboxConfig {
+    /// Testing program runs in a Docker container created from 'image_name' (default).
+    Docker { image_name: String },
+    /// Testing program runs as a local process.
+    Local(LocalSandboxConfig),
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalSandboxConfig {
+    /// Path to testing program binary.
+    pub tester_path: PathBuf,
+    /// Directory in which working directories of tickets are created
+    /// (by default `alsit` in temporary directory).
+    pub root_dir: PathBuf,
+    /// Whether testing program is put into new user, network, IPC and UTS namespaces.
+    pub unshare: bool,
+    /// Cgroup v2 directory in which cgroups of sandboxes are created.
+    pub cgroup_dir: Option<PathBuf>,
+}
+
+impl SandboxConfig {
+    pub fn from_config(config: &Config) -> SandboxConfig {
+        let judge = &config.judge;
+
+        match judge.sandbox {
+            SandboxKind::Docker => SandboxConfig::Docker {
+                image_name: config.testing_image_name.clone(),
+            },
+            SandboxKind::Local => {
+                if judge.local_cgroup_dir.is_none() {
+                    warn!("Local sandbox has no cgroup directory. Number of processes of submissions is not limited.");
+                }
+
+                SandboxConfig::Local(LocalSandboxConfig {
+                    // Presence of the path is checked when configuration is loaded.
+                    tester_path: judge.local_tester_path.clone().unwrap_or_default(),
+                    root_dir: judge
+                        .local_sandbox_dir
+                        .clone()
+                        .unwrap_or_else(|| std::env::temp_dir().join("alsit")),
+                    unshare: judge.local_unshare,
+                    cgroup_dir: judge.local_cgroup_dir.clone(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running(test: u32, total: u32) -> Option<TicketProgress> {
+        Some(TicketProgress::Running { test, total })
+    }
+
+    #[test]
+    fn progress_line_is_parsed() {
+        assert_eq!(parse_progress("ALSIT_PROGRESS test 3/10"), running(3, 10));
+        assert_eq!(parse_progress("ALSIT_PROGRESS test 1 / 2"), running(1, 2));
+    }
+
+    #[test]
+    fn other_lines_are_ignored() {
+        assert_eq!(parse_progress("test 3/10"), None);
+        assert_eq!(parse_progress("ALSIT_PROGRESS compile"), None);
+        assert_eq!(parse_progress("ALSIT_PROGRESS test 3"), None);
+        assert_eq!(parse_progress("ALSIT_PROGRESS test x/10"), None);
+        assert_eq!(parse_progress("ALSIT_PROGRESS test -1/10"), None);
+    }
+
+    /// Reporter with database pool which is never connected to successfully;
+    /// marking the ticket as running fails silently.
+    fn reporter(events: &TicketEvents) -> ProgressReporter {
+        let mut config = deadpool_postgres::Config::new();
+        config.dbname = Some(String::from("alsit_test"));
+
+        let pool = config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap();
+
+        ProgressReporter::new(events.clone(), pool, 7)
+    }
+
+    fn received(
+        receiver: &mut tokio::sync::broadcast::Receiver<super::super::events::TicketEvent>,
+    ) -> Vec<TicketProgress> {
+        let mut progress = Vec::new();
+
+        while let Ok(event) = receiver.try_recv() {
+            assert_eq!(event.ticket_id, 7);
+            progress.push(event.progress);
+        }
+
+        progress
+    }
+
+    #[tokio::test]
+    async fn progress_lines_split_between_chunks_are_reported() {
+        let events = TicketEvents::new();
+        let mut receiver = events.subscribe();
+        let mut reporter = reporter(&events);
+
+        reporter.feed(b"compiling...\nALSIT_PROG");
+        reporter.feed(b"RESS test 1/2\r\nALSIT_PROGRESS test 2/2");
+        assert_eq!(
+            received(&mut receiver),
+            vec![TicketProgress::Running { test: 1, total: 2 }]
+        );
+
+        reporter.feed(b"\n");
+        assert_eq!(
+            received(&mut receiver),
+            vec![TicketProgress::Running { test: 2, total: 2 }]
+        );
+    }
+
+    #[tokio::test]
+    async fn too_long_lines_are_skipped() {
+        let events = TicketEvents::new();
+        let mut receiver = events.subscribe();
+        let mut reporter = reporter(&events);
+
+        let mut long_line = b"ALSIT_PROGRESS test 1/2".to_vec();
+        long_line.resize(MAX_PROGRESS_LINE_LEN + 10, b'0');
+        long_line.push(b'\n');
+
+        reporter.feed(&long_line);
+        reporter.feed(b"ALSIT_PROGRESS test 2/2\n");
+
+        assert_eq!(
+            received(&mut receiver),
+            vec![TicketProgress::Running { test: 2, total: 2 }]
+        );
+    }
+}
@@ -1,180 +1,741 @@
-use bollard::{
-    self,
-    container::{CreateContainerOptions, StartContainerOptions, UploadToContainerOptions, WaitContainerOptions},
-};
 use deadpool_postgres::Pool;
+use std::path::Path;
+use std::time::Duration;
 
-use crate::ticket::{self, ExerciseId, Language, TicketError, TicketId};
+use super::checker;
+use super::events::{TicketEvents, TicketProgress};
+use super::limits::{self, LimitOverrides};
+use super::manifest::{Manifest, MANIFEST_FILE};
+use super::retry::RETRY_POLICY;
+use super::sandbox::{
+    DockerSandbox, LocalSandbox, ProgressReporter, RunOutcome, Sandbox, SandboxConfig,
+    SandboxOutput,
+};
+use super::JudgeError;
 
-const TESTING_IMAGE_NAME: &str = env!("TESTING_IMAGE_NAME");
-const TESTS_PATH: &str = env!("TESTS_PATH");
+use crate::config::Config;
+use crate::exercise::{self, package, ExerciseError};
+use crate::ticket::results::{self, TestReport, TicketResults, Verdict};
+use crate::ticket::{self, ExerciseId, Language, TicketError, TicketId, TicketStatus};
 
-use tokio_stream::StreamExt;
+/// Maximal number of bytes of compiler output kept in the database.
+const MAX_COMPILER_OUTPUT_LEN: usize = 64 * 1024;
 
-pub async fn test_program(ticket_id: TicketId, exercise_id: ExerciseId, db: Pool) {
-    let (content, lang);
+/// Judges the ticket, publishing its progress to 'events'. When judging fails
+/// because of a problem on the server side, the ticket is marked as a system error.
+pub async fn test_program(
+    ticket_id: TicketId,
+    db: &Pool,
+    config: &Config,
+    sandbox_config: &SandboxConfig,
+    events: &TicketEvents,
+) -> Result<(), JudgeError> {
+    let judging_result = match sandbox_config {
+        SandboxConfig::Docker { image_name } => {
+            let sandbox = DockerSandbox::new(image_name, ticket_id);
+            judge_ticket(sandbox, ticket_id, db, config, events).await
+        }
+        SandboxConfig::Local(local_config) => {
+            let sandbox = LocalSandbox::new(local_config.clone(), ticket_id);
+            judge_ticket(sandbox, ticket_id, db, config, events).await
+        }
+    };
 
-    loop {
-        match ticket::get_content(ticket_id, &db).await {
-            Ok(res) => {
-                (content, lang) = res;
-                break;
-            }
-            Err(TicketError::WrongTicketId) => {
-                return;
+    if let Err(error) = &judging_result {
+        error!(
+            "Unable to judge ticket. Marking it as system error. TicketId = {}, ERROR = {}",
+            ticket_id, error
+        );
+
+        fail_ticket(ticket_id, db, events).await;
+    }
+
+    judging_result
+}
+
+/// Marks the ticket as system error with internal error as its results and
+/// tells subscribers that judging is over.
+pub async fn fail_ticket(ticket_id: TicketId, db: &Pool, events: &TicketEvents) {
+    let results = TicketResults::internal_error();
+    let _ = RETRY_POLICY
+        .run("storing results", ticket_id, || {
+            store_results_of(ticket_id, &results, db)
+        })
+        .await;
+    let _ = RETRY_POLICY
+        .run("marking ticket as system error", ticket_id, || {
+            ticket::set_system_error(ticket_id, db)
+        })
+        .await;
+
+    events.publish(ticket_id, TicketProgress::SystemError);
+}
+
+async fn store_results_of(
+    ticket_id: TicketId,
+    results: &TicketResults,
+    db: &Pool,
+) -> Result<(), TicketError> {
+    match results::store_results(ticket_id, results, db).await {
+        Ok(_) | Err(TicketError::WrongTicketId) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+async fn judge_ticket<S: Sandbox>(
+    mut sandbox: S,
+    ticket_id: TicketId,
+    db: &Pool,
+    config: &Config,
+    events: &TicketEvents,
+) -> Result<(), JudgeError> {
+    let content = RETRY_POLICY
+        .run("reading ticket content", ticket_id, || async {
+            match ticket::get_content(ticket_id, db).await {
+                Ok(content) => Ok(Some(content)),
+                Err(TicketError::WrongTicketId) => Ok(None),
+                Err(error) => Err(error),
             }
-            Err(TicketError::DatabaseError) => {
-                error!("Error occured at test_program() at point get_content(). Trying again. TicketId = {}", ticket_id);
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?;
+
+    let (content, lang, exercise_id) = match content {
+        Some(content) => content,
+        None => {
+            warn!("Ticket to judge does not exist. TicketId = {}", ticket_id);
+            return Ok(());
+        }
+    };
+
+    let exercise_dir = config.tests_path.join(exercise_id.to_string());
+    let limits = RETRY_POLICY
+        .run("reading exercise limits", ticket_id, || async {
+            match exercise::get_limits(exercise_id, db).await {
+                Ok(limits) => Ok(limits),
+                // Exercise of the ticket was checked at submission, so it should not happen.
+                Err(ExerciseError::WrongExerciseId) => Ok(LimitOverrides::default()),
+                Err(error) => Err(error),
             }
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?
+        .apply(&config.judge.default_limits);
+
+    RETRY_POLICY
+        .run("marking ticket as started", ticket_id, || {
+            ticket::set_started(ticket_id, db)
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?;
+
+    let tar_tests = tarize_tests(&exercise_dir, exercise_id, ticket_id).await?;
+    let tests_hash = package::content_hash(&tar_tests);
+    RETRY_POLICY
+        .run("storing tests hash", ticket_id, || {
+            ticket::set_tests_hash(ticket_id, &tests_hash, db)
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?;
+
+    let manifest = match package::validate(&tar_tests).await {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            error!(
+                "Test package of exercise is invalid. ExerciseId = {}, ERROR = {}",
+                exercise_id, error
+            );
+            return Err(JudgeError::TestsUnavailable { exercise_id });
         }
+    };
+    let setup = TestSetup {
+        limits: manifest.container_limits(&limits),
+        tests: tar_tests,
+        manifest,
+    };
+
+    let mut progress = ProgressReporter::new(events.clone(), db.clone(), ticket_id);
+
+    let run_result = run_in_sandbox(
+        &mut sandbox,
+        content,
+        lang,
+        &setup,
+        ticket_id,
+        &mut progress,
+    )
+    .await;
+    sandbox.cleanup().await;
+
+    let (exit_code, mut results) = results_of_run(run_result?, &setup, ticket_id).await;
+
+    if results.verdict != Verdict::InternalError {
+        results.set_subtasks(setup.manifest.score(&results.tests, results.verdict));
     }
 
-    let container_name = invoke_container(content, lang, ticket_id, exercise_id).await;
-    let wait_container_options: WaitContainerOptions<&str> = WaitContainerOptions::default();
+    RETRY_POLICY
+        .run("storing results", ticket_id, || {
+            store_results_of(ticket_id, &results, db)
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?;
 
-    //TODO: Need to check how exactly it works.
-    let _exit_code = crate::DOCKER.wait_container(&container_name, Some(wait_container_options)).next().await;
-    
-}
+    let status = match results.verdict {
+        Verdict::CompilationError => TicketStatus::CompileError,
+        _ => TicketStatus::Judged,
+    };
 
-async fn tarize_tests(exercise_id: ExerciseId) -> Vec<u8> {
-    let tests;
-    loop {
-        match tokio::fs::read(format!("{}{}/tests.tar", TESTS_PATH, exercise_id)).await {
-            Ok(content) => {
-                tests = content;
-                break;
+    RETRY_POLICY
+        .run("marking ticket as judged", ticket_id, || async {
+            match ticket::set_judged(ticket_id, status, exit_code, db).await {
+                Ok(()) | Err(TicketError::WrongTicketId) => Ok(()),
+                Err(error) => Err(error),
             }
+        })
+        .await
+        .map_err(|_| JudgeError::DatabaseError)?;
+
+    events.publish(
+        ticket_id,
+        TicketProgress::Finished {
+            verdict: results.verdict,
+        },
+    );
+
+    Ok(())
+}
+
+/// Test package of the exercise with limits of the sandbox it is run in.
+struct TestSetup {
+    /// Tar with the package, it is kept for checking outputs of tests.
+    tests: Vec<u8>,
+    manifest: Manifest,
+    limits: limits::ExerciseLimits,
+}
+
+/// Goes through all steps of judging in the sandbox, except of cleanup. Output is
+/// collected only if testing program finished by itself.
+async fn run_in_sandbox<S: Sandbox>(
+    sandbox: &mut S,
+    content: String,
+    lang: Language,
+    setup: &TestSetup,
+    ticket_id: TicketId,
+    progress: &mut ProgressReporter,
+) -> Result<(RunOutcome, Option<SandboxOutput>), JudgeError> {
+    sandbox.prepare(&lang, &setup.limits).await?;
+
+    let tar_program = tarize_program(content, lang, ticket_id).await?;
+    sandbox.upload_program(tar_program).await?;
+
+    sandbox.upload_tests(setup.tests.clone()).await?;
+    sandbox
+        .upload_tests(tarize_manifest(&setup.manifest, ticket_id).await?)
+        .await?;
+
+    // Testing program compiles submission first, then it reports tests on its own.
+    progress.publish(TicketProgress::Compiling);
+
+    let outcome = sandbox
+        .run(Duration::from_secs(setup.limits.wall_time_secs), progress)
+        .await?;
+
+    match outcome {
+        RunOutcome::Finished { .. } => match sandbox.collect_output().await {
+            Ok(output) => Ok((outcome, Some(output))),
             Err(error) => {
                 error!(
-                    "Error occured while reading tar with tests. Trying again. ERROR = {}.",
-                    error
+                    "Unable to collect output of testing program. TicketId = {}, ERROR = {:?}",
+                    ticket_id, error
                 );
+                Ok((outcome, None))
             }
-        }
+        },
+        RunOutcome::TimedOut => Ok((outcome, None)),
     }
-
-    tests
 }
 
-async fn tarize_program(content: String, lang: Language) -> Vec<u8> {
-    let program: Vec<u8> = content.into();
-    let mut tar_program_header = async_tar::Header::new_gnu();
-    let _ = tar_program_header.set_path(format!("main{}", lang.extension()));
-    let _ = tar_program_header.set_size(program.len().try_into().unwrap());
-    let _ = tar_program_header.set_cksum();
+/// Exit code of testing program and results of the ticket, depending on how
+/// testing program finished.
+async fn results_of_run(
+    run: (RunOutcome, Option<SandboxOutput>),
+    setup: &TestSetup,
+    ticket_id: TicketId,
+) -> (Option<i64>, TicketResults) {
+    match run {
+        (RunOutcome::Finished { exit_code }, Some(output)) => {
+            let results = match parse_output(output, setup, ticket_id).await {
+                Ok(results) => results,
+                Err(()) => TicketResults::internal_error(),
+            };
 
-    let mut tar_content: Vec<u8> = Vec::new();
+            (exit_code, results)
+        }
+        (RunOutcome::Finished { exit_code }, None) => (exit_code, TicketResults::internal_error()),
+        (RunOutcome::TimedOut, _) => {
+            warn!(
+                "Testing program exceeded wall time limit of {} seconds and was killed. TicketId = {}",
+                setup.limits.wall_time_secs, ticket_id
+            );
 
-    let mut tar_builder = async_tar::Builder::new(&mut tar_content);
-    tar_builder
-        .append(&tar_program_header, program.as_slice())
+            (None, TicketResults::timed_out(setup.limits.wall_time_secs))
+        }
+    }
+}
+
+/// Turns files written by testing program into results of the ticket, with
+/// outputs of passed tests checked.
+async fn parse_output(
+    output: SandboxOutput,
+    setup: &TestSetup,
+    ticket_id: TicketId,
+) -> Result<TicketResults, ()> {
+    let mut report: TestReport = match output.report.map(|report| serde_json::from_slice(&report)) {
+        Some(Ok(report)) => report,
+        Some(Err(error)) => {
+            error!(
+                "Judging report has wrong format. TicketId = {}, ERROR = {}",
+                ticket_id, error
+            );
+            return Err(());
+        }
+        None => {
+            error!(
+                "Testing program did not write judging report. TicketId = {}",
+                ticket_id
+            );
+            return Err(());
+        }
+    };
+
+    if report.compiled {
+        checker::check_outputs(
+            &setup.manifest,
+            &setup.tests,
+            &mut report.tests,
+            &output.outputs,
+            ticket_id,
+        )
         .await;
+    }
+
+    let mut results = TicketResults::from_report(report);
 
-    // TODO: Can error occur here?
-    let _ = tar_builder.into_inner().await;
+    results.compiler_output = output.compiler_output.map(|mut output| {
+        output.truncate(MAX_COMPILER_OUTPUT_LEN);
+        String::from_utf8_lossy(&output).into_owned()
+    });
 
-    tar_content
+    Ok(results)
 }
 
-async fn invoke_container(
+async fn tarize_tests(
+    exercise_dir: &Path,
+    exercise_id: ExerciseId,
+    ticket_id: TicketId,
+) -> Result<Vec<u8>, JudgeError> {
+    let tests_path = exercise_dir.join(package::PACKAGE_FILE);
+
+    RETRY_POLICY
+        .run("reading tar with tests", ticket_id, || {
+            tokio::fs::read(&tests_path)
+        })
+        .await
+        .map_err(|_| JudgeError::TestsUnavailable { exercise_id })
+}
+
+/// Packs manifest with limits of every test filled in. It is uploaded after the
+/// package, so it replaces manifest from the package.
+async fn tarize_manifest(manifest: &Manifest, ticket_id: TicketId) -> Result<Vec<u8>, JudgeError> {
+    let content = serde_json::to_vec_pretty(&manifest.resolved()).map_err(|error| {
+        error!(
+            "Error occured while serializing manifest. TicketId = {}, ERROR = {:?}",
+            ticket_id, error
+        );
+        JudgeError::InternalError
+    })?;
+
+    tarize_file(MANIFEST_FILE, &content).await.map_err(|error| {
+        error!(
+            "Error occured while packing manifest. TicketId = {}, ERROR = {:?}",
+            ticket_id, error
+        );
+        JudgeError::InternalError
+    })
+}
+
+async fn tarize_program(
     content: String,
     lang: Language,
     ticket_id: TicketId,
-    exercise_id: ExerciseId,
-) -> String {
-    let container_name = format!("{}{}", TESTING_IMAGE_NAME, ticket_id);
-    let container_name_config = CreateContainerOptions {
-        name: &container_name,
-    };
+) -> Result<Vec<u8>, JudgeError> {
+    let path = format!("main{}", lang.extension());
 
-    let test_language_env = format!("TEST_LANGUAGE={}", lang.to_string());
-    let config = bollard::container::Config {
-        image: Some(TESTING_IMAGE_NAME),
-        env: Some(vec![test_language_env.as_str()]),
-        ..Default::default()
-    };
+    tarize_file(&path, content.as_bytes())
+        .await
+        .map_err(|error| {
+            error!(
+                "Error occured while packing program. TicketId = {}, ERROR = {:?}",
+                ticket_id, error
+            );
+            JudgeError::InternalError
+        })
+}
+
+/// Packs single file readable by everyone, so testing program can read it
+/// regardless of the user it runs as.
+async fn tarize_file(path: &str, content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut header = async_tar::Header::new_gnu();
+    header.set_path(path)?;
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let mut tar_content: Vec<u8> = Vec::new();
+    let mut tar_builder = async_tar::Builder::new(&mut tar_content);
 
-    let created_container;
+    tar_builder.append(&header, content).await?;
+    tar_builder.into_inner().await?;
 
-    loop {
-        let _ = crate::DOCKER.remove_container(&container_name, None).await;
+    Ok(tar_content)
+}
 
-        match crate::DOCKER
-            .create_container(Some(container_name_config.clone()), config.clone())
+#[cfg(test)]
+mod tests {
+    use futures::{AsyncReadExt, StreamExt};
+
+    use super::*;
+
+    /// Reads the only file of 'tar' with its path and mode.
+    async fn single_file(tar: &[u8]) -> (String, u32, Vec<u8>) {
+        let mut entries = async_tar::Archive::new(tar).entries().unwrap();
+        let mut entry = entries.next().await.unwrap().unwrap();
+
+        let path = entry.path().unwrap().to_string_lossy().into_owned();
+        let mode = entry.header().mode().unwrap();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).await.unwrap();
+
+        assert!(entries.next().await.is_none());
+        (path, mode, content)
+    }
+
+    #[tokio::test]
+    async fn program_is_packed_readable() {
+        let tar = tarize_program(String::from("int main() {}"), Language::C, 1)
             .await
-        {
-            Ok(res) => {
-                created_container = res;
-                break;
-            }
-            Err(error) => {
-                error!(
-                    "Error occured while creating container. Trying again. ERROR = {}.",
-                    error
-                );
+            .unwrap();
+
+        let (path, mode, content) = single_file(&tar).await;
+
+        assert_eq!(path, "main.c");
+        assert_eq!(mode, 0o644);
+        assert_eq!(content, b"int main() {}");
+    }
+
+    #[tokio::test]
+    async fn manifest_is_packed_with_resolved_limits() {
+        let tests = ["a", "b"].into_iter().map(String::from).collect();
+        let manifest = Manifest::for_tests(&tests);
+
+        let tar = tarize_manifest(&manifest, 1).await.unwrap();
+
+        let (path, mode, content) = single_file(&tar).await;
+        let packed: serde_json::Value = serde_json::from_slice(&content).unwrap();
+
+        assert_eq!(path, MANIFEST_FILE);
+        assert_eq!(mode, 0o644);
+        assert_eq!(packed["groups"][1]["tests"][0]["time_limit_ms"], 1000);
+    }
+
+    #[tokio::test]
+    async fn invalid_path_is_not_packed() {
+        assert!(tarize_file("", b"").await.is_err());
+    }
+
+    /// Sandbox recording steps of judging, its testing program finishes with 'outcome'.
+    struct FakeSandbox {
+        steps: Vec<&'static str>,
+        uploaded_tests: Vec<Vec<u8>>,
+        outcome: RunOutcome,
+        fail_prepare: bool,
+        fail_collect: bool,
+    }
+
+    impl FakeSandbox {
+        fn new(outcome: RunOutcome) -> FakeSandbox {
+            FakeSandbox {
+                steps: Vec::new(),
+                uploaded_tests: Vec::new(),
+                outcome,
+                fail_prepare: false,
+                fail_collect: false,
             }
         }
     }
 
-    let tar_program = tarize_program(content, lang).await;
-    let tar_tests = tarize_tests(exercise_id).await;
+    impl Sandbox for FakeSandbox {
+        async fn prepare(
+            &mut self,
+            _lang: &Language,
+            _limits: &limits::ExerciseLimits,
+        ) -> Result<(), JudgeError> {
+            self.steps.push("prepare");
 
-    let program_upload = UploadToContainerOptions {
-        path: "/program",
-        ..Default::default()
-    };
+            if self.fail_prepare {
+                return Err(JudgeError::DockerError);
+            }
 
-    let tests_upload = UploadToContainerOptions {
-        path: "/tests",
-        ..Default::default()
-    };
+            Ok(())
+        }
 
-    loop {
-        match crate::DOCKER
-            .upload_to_container(
-                &container_name,
-                Some(program_upload.clone()),
-                tar_program.clone().into(),
-            )
-            .await
-        {
-            Ok(_) => {
-                break;
-            }
-            Err(error) => {
-                error!("Error occured while uploading program to container. Trying again. TicketId = {}, Error = {}", ticket_id, error);
+        async fn upload_program(&mut self, _program_tar: Vec<u8>) -> Result<(), JudgeError> {
+            self.steps.push("program");
+            Ok(())
+        }
+
+        async fn upload_tests(&mut self, tests_tar: Vec<u8>) -> Result<(), JudgeError> {
+            self.steps.push("tests");
+            self.uploaded_tests.push(tests_tar);
+            Ok(())
+        }
+
+        async fn run(
+            &mut self,
+            _wall_time_limit: Duration,
+            _progress: &mut ProgressReporter,
+        ) -> Result<RunOutcome, JudgeError> {
+            self.steps.push("run");
+            Ok(self.outcome)
+        }
+
+        async fn collect_output(&mut self) -> Result<SandboxOutput, JudgeError> {
+            self.steps.push("collect");
+
+            if self.fail_collect {
+                return Err(JudgeError::DockerError);
             }
+
+            Ok(SandboxOutput {
+                report: Some(b"{}".to_vec()),
+                ..SandboxOutput::default()
+            })
+        }
+
+        async fn cleanup(&mut self) {
+            self.steps.push("cleanup");
+        }
+    }
+
+    fn setup() -> TestSetup {
+        let tests = ["a"].into_iter().map(String::from).collect();
+        let manifest = Manifest::for_tests(&tests);
+
+        TestSetup {
+            limits: manifest.container_limits(&limits::ExerciseLimits::default()),
+            tests: b"package".to_vec(),
+            manifest,
         }
     }
 
-    loop {
-        match crate::DOCKER
-            .upload_to_container(
-                &container_name,
-                Some(tests_upload.clone()),
-                tar_tests.clone().into(),
+    /// Runs judging in 'sandbox'; marking the ticket as running fails silently,
+    /// as database pool is never connected to.
+    async fn run(
+        sandbox: &mut FakeSandbox,
+        setup: &TestSetup,
+    ) -> Result<(RunOutcome, Option<SandboxOutput>), JudgeError> {
+        let mut config = deadpool_postgres::Config::new();
+        config.dbname = Some(String::from("alsit_test"));
+        let pool = config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
             )
-            .await
-        {
-            Ok(_) => {
-                break;
-            }
-            Err(error) => {
-                error!("Error occured while uploading tests to container. Trying again. TicketId = {}, Error = {}", ticket_id, error);
-            }
+            .unwrap();
+        let events = TicketEvents::new();
+        let mut progress = ProgressReporter::new(events.clone(), pool, 1);
+
+        run_in_sandbox(
+            sandbox,
+            String::from("int main() {}"),
+            Language::C,
+            setup,
+            1,
+            &mut progress,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn finished_program_goes_through_all_steps() {
+        let outcome = RunOutcome::Finished { exit_code: Some(0) };
+        let mut sandbox = FakeSandbox::new(outcome);
+        let setup = setup();
+
+        let (run_outcome, output) = run(&mut sandbox, &setup).await.unwrap();
+
+        assert_eq!(run_outcome, outcome);
+        assert_eq!(output.unwrap().report.unwrap(), b"{}");
+        assert_eq!(
+            sandbox.steps,
+            ["prepare", "program", "tests", "tests", "run", "collect"]
+        );
+        // Package is kept for checking outputs.
+        assert_eq!(sandbox.uploaded_tests[0], setup.tests);
+        assert_eq!(
+            single_file(&sandbox.uploaded_tests[1]).await.0,
+            MANIFEST_FILE
+        );
+    }
+
+    #[tokio::test]
+    async fn output_of_timed_out_program_is_not_collected() {
+        let mut sandbox = FakeSandbox::new(RunOutcome::TimedOut);
+
+        let (outcome, output) = run(&mut sandbox, &setup()).await.unwrap();
+
+        assert_eq!(outcome, RunOutcome::TimedOut);
+        assert!(output.is_none());
+        assert_eq!(sandbox.steps.last(), Some(&"run"));
+    }
+
+    #[tokio::test]
+    async fn failed_collection_leaves_no_output() {
+        let outcome = RunOutcome::Finished { exit_code: None };
+        let mut sandbox = FakeSandbox::new(outcome);
+        sandbox.fail_collect = true;
+
+        let (run_outcome, output) = run(&mut sandbox, &setup()).await.unwrap();
+
+        assert_eq!(run_outcome, outcome);
+        assert!(output.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_preparation_stops_judging() {
+        let mut sandbox = FakeSandbox::new(RunOutcome::TimedOut);
+        sandbox.fail_prepare = true;
+
+        let result = run(&mut sandbox, &setup()).await;
+
+        assert!(matches!(result, Err(JudgeError::DockerError)));
+        assert_eq!(sandbox.steps, ["prepare"]);
+    }
+
+    fn output(report: Option<&str>, compiler_output: Option<Vec<u8>>) -> SandboxOutput {
+        SandboxOutput {
+            report: report.map(|report| report.as_bytes().to_vec()),
+            compiler_output,
+            ..SandboxOutput::default()
         }
     }
 
-    while let Err(error) = crate::DOCKER
-        .start_container(&container_name, None::<StartContainerOptions<String>>)
+    #[tokio::test]
+    async fn missing_or_invalid_report_is_error() {
+        assert!(parse_output(output(None, None), &setup(), 1).await.is_err());
+        assert!(parse_output(output(Some("{"), None), &setup(), 1)
+            .await
+            .is_err());
+        assert!(
+            parse_output(output(Some(r#"{"tests": []}"#), None), &setup(), 1)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn compiler_output_is_truncated() {
+        let compiler_output = vec![b'e'; MAX_COMPILER_OUTPUT_LEN + 1];
+
+        let results = parse_output(
+            output(Some(r#"{"compiled": false}"#), Some(compiler_output)),
+            &setup(),
+            1,
+        )
         .await
-    {
-        error!(
-            "Error occured while starting container. Trying again. TicketId = {}, Error = {}",
-            ticket_id, error
+        .unwrap();
+
+        assert_eq!(results.verdict, Verdict::CompilationError);
+        assert_eq!(
+            results.compiler_output.unwrap().len(),
+            MAX_COMPILER_OUTPUT_LEN
         );
     }
 
-    container_name
+    /// Package with test 'a' whose expected output is "1".
+    async fn checker_package() -> Vec<u8> {
+        let mut tar = Vec::new();
+        let mut builder = async_tar::Builder::new(&mut tar);
+
+        for (path, content) in [("a.in", ""), ("a.out", "1")] {
+            let mut header = async_tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).await.unwrap();
+        }
+
+        builder.into_inner().await.unwrap();
+        tar
+    }
+
+    #[tokio::test]
+    async fn passed_tests_are_checked() {
+        let mut sandbox_output = output(
+            Some(
+                r#"{"compiled": true, "tests": [
+                    {"name": "a", "verdict": "OK", "runtime_ms": 1, "peak_memory_kb": 1, "exit_code": 0}
+                ]}"#,
+            ),
+            None,
+        );
+        sandbox_output
+            .outputs
+            .insert(String::from("a"), b"2".to_vec());
+        let mut setup = setup();
+        setup.tests = checker_package().await;
+
+        let results = parse_output(sandbox_output, &setup, 1).await.unwrap();
+
+        assert_eq!(results.verdict, Verdict::WrongAnswer);
+    }
+
+    #[tokio::test]
+    async fn timed_out_program_exceeds_time_limit() {
+        let setup = setup();
+
+        let (exit_code, results) =
+            results_of_run((RunOutcome::TimedOut, Some(output(None, None))), &setup, 1).await;
+
+        assert_eq!(exit_code, None);
+        assert_eq!(results.verdict, Verdict::TimeLimitExceeded);
+        assert_eq!(
+            results.timeout_secs,
+            Some(setup.limits.wall_time_secs as i64)
+        );
+        assert!(results.tests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn program_without_output_is_internal_error() {
+        let finished = RunOutcome::Finished { exit_code: Some(1) };
+
+        let (exit_code, results) = results_of_run((finished, None), &setup(), 1).await;
+
+        assert_eq!(exit_code, Some(1));
+        assert_eq!(results.verdict, Verdict::InternalError);
+        assert_eq!(results.timeout_secs, None);
+    }
+
+    #[tokio::test]
+    async fn finished_program_is_judged_by_its_report() {
+        let finished = RunOutcome::Finished { exit_code: Some(0) };
+        let output = output(Some(r#"{"compiled": false}"#), None);
+
+        let (exit_code, results) = results_of_run((finished, Some(output)), &setup(), 1).await;
+
+        assert_eq!(exit_code, Some(0));
+        assert_eq!(results.verdict, Verdict::CompilationError);
+    }
 }
@@ -1,27 +1,42 @@
-//! ## Environment variables
-//! Some variables must be set in order to run this server:
-//! 
-//! * TESTING_IMAGE_NAME defines name of docker images used to conduct
-//!   testing. It is read by default from 'config.json'
-//! * TESTS_PATH defines path (absolute) at the machine, where tests data
-//!   is stored. It is read by default from 'config.json'. More info about
-//!   tests format is in module [alsit::judge].
-//! * ALSIT_ADDRESS defines IP address at which server should start. Example:
-//!   "127.0.0.1:8080".
-//! 
-//! [alsit::judge]: crate::judge
+//! ## Configuration
+//! Server is configured with 'config.json' file (path can be changed with
+//! ALSIT_CONFIG env variable). It covers server address, database connection,
+//! testing image, path to exercises data and judges. Every key can be
+//! overridden with env variable, see module [alsit::config] for the list.
+//!
+//! Keys used for encryption and hashing are configured separately, see
+//! [alsit::crypto::Keys]. New keys are generated with `alsit keygen [path]`,
+//! encryption key is rotated with `alsit rotate-key <path>` and data is moved to
+//! the current key with `alsit reencrypt`.
+//!
+//! Database schema is migrated at startup, or with `alsit migrate` when
+//! `database.auto_migrate` is turned off, see [alsit::migrations].
+//!
+//! Every new account is a student. Role of the first admin is set with
+//! `alsit set-role <username> Admin`, later roles are managed through
+//! `/account/users` endpoints.
+//!
+//! [alsit::config]: crate::config
+//! [alsit::crypto::Keys]: crate::crypto::Keys
+//! [alsit::migrations]: crate::migrations
 extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 
 mod account;
+mod config;
 mod crypto;
+mod exercise;
 mod judge;
+mod migrations;
 mod ticket;
 
 use actix_web::{web, App, HttpServer};
 use bollard::Docker;
 use lazy_static::lazy_static;
+use std::sync::Arc;
+
+use config::Config;
 
 const HASH_LENGTH_BYTES: usize = 32;
 const MAX_USERNAME_LENGTH: usize = 40;
@@ -32,28 +47,105 @@ lazy_static! {
     static ref DOCKER: Docker = Docker::connect_with_socket_defaults().unwrap();
 }
 
-/// Retriving key used for encryption.
-fn encryption_seed() -> [u8; 32] {
-    // Perfectly to restore it from something.
-    [
-        35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63, 21, 23,
-        35, 23, 12, 63, 35, 23, 12, 63, 2, 3,
-    ]
+/// Handles `alsit keygen [path]` command. New keys are written into 'path', or
+/// printed to stdout when path is not given.
+fn keygen(path: Option<String>) -> std::io::Result<()> {
+    let keys = crypto::Keys::generate();
+
+    match path {
+        Some(path) => {
+            if let Err(error) = keys.write_key_file(std::path::Path::new(&path)) {
+                error!("Unable to generate keys. {}", error);
+                std::process::exit(1);
+            }
+
+            info!("New keys were written to '{}'.", path);
+        }
+        None => print!("{}", keys.to_json()),
+    }
+
+    Ok(())
+}
+
+/// Handles `alsit rotate-key <path>` command. New encryption key is added to key
+/// file at 'path' and becomes current one.
+fn rotate_key(path: Option<String>) -> std::io::Result<()> {
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => {
+            error!("Path to key file is required: alsit rotate-key <path>.");
+            std::process::exit(1);
+        }
+    };
+
+    let rotation = crypto::Keys::read_key_file(&path).and_then(|mut keys| {
+        let new_id = keys.rotate()?;
+        keys.replace_key_file(&path)?;
+        Ok(new_id)
+    });
+
+    match rotation {
+        Ok(new_id) => {
+            info!(
+                "Added encryption key {}. Run 'alsit reencrypt' after restarting the server.",
+                new_id
+            );
+            Ok(())
+        }
+        Err(error) => {
+            error!("Unable to rotate encryption key. {}", error);
+            std::process::exit(1);
+        }
+    }
 }
 
-/// Retriving key which serves as base for hashing.
-fn hashing_seed() -> [u8; 64] {
-    [
-        35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63, 21, 23,
-        92, 2, 1, 62, 173, 12, 162, 36, 232, 15, 35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12,
-        162, 36, 232, 15, 35, 23, 12, 63, 21, 23, 92, 2, 1, 62, 173, 12, 162, 36, 232, 15,
-    ]
+/// Handles `alsit set-role <username> <role>` command, used to create the first admin.
+async fn set_role(
+    pool: &deadpool_postgres::Pool,
+    username: Option<String>,
+    role: Option<String>,
+) -> std::io::Result<()> {
+    let (username, role) = match (username, role.map(|role| role.parse())) {
+        (Some(username), Some(Ok(role))) => (username, role),
+        _ => {
+            error!("Usage: alsit set-role <username> <Student|Teacher|Admin>.");
+            std::process::exit(1);
+        }
+    };
+
+    match account::set_role_by_username(pool, &username, role).await {
+        Ok(true) => {
+            info!("Role of '{}' was set to {}.", username, role);
+            Ok(())
+        }
+        Ok(false) => {
+            error!("User '{}' does not exist.", username);
+            std::process::exit(1);
+        }
+        Err(()) => std::process::exit(1),
+    }
 }
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     pretty_env_logger::init_timed();
 
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+
+    match command.as_deref() {
+        None | Some("migrate") | Some("reencrypt") | Some("set-role") => {}
+        Some("keygen") => return keygen(args.next()),
+        Some("rotate-key") => return rotate_key(args.next()),
+        Some(command) => {
+            error!(
+                "Unknown command '{}'. Available commands: keygen, rotate-key, migrate, reencrypt, set-role.",
+                command
+            );
+            std::process::exit(1);
+        }
+    }
+
     if cfg!(debug_assertions) {
         warn!("You are running the application in debug mode! It should only be used for developement.");
 
@@ -61,24 +153,88 @@ async fn main() -> std::io::Result<()> {
         dotenv::from_filename("sql/db.env").expect("Unable to find file ../sql/db.env");
     }
 
-    let server_address =
-        std::env::var("ALSIT_ADDRESS").expect("Unable to find ALSIT__ADDRESS env variable.");
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(error) => {
+            error!("Invalid configuration. {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    info!("Configuration was loaded successfully.");
+
+    let keys = match crypto::Keys::load(&config) {
+        Ok(keys) => keys,
+        Err(error) => {
+            error!("Unable to load keys. {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let hashing_key = Box::leak(Box::new(keys.hashing_key));
 
-    let password_hash = Box::leak(Box::new(hashing_seed()));
+    let encryptor = crypto::Encryptor::new(&keys.encryption_keys, keys.current_key_id);
 
-    let encryptor = crypto::Encryptor::new(&encryption_seed());
+    let hasher = crypto::Hasher::new(hashing_key);
+
+    let pool = crypto::init_database_pool(&config.database).await;
+
+    if command.as_deref() == Some("migrate") || config.database.auto_migrate {
+        match migrations::migrate(&pool).await {
+            Ok(0) => info!("Database schema is up to date."),
+            Ok(applied) => info!("Applied {} database migrations.", applied),
+            Err(error) => {
+                error!("Unable to migrate database. {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else if let Err(error) = migrations::check(&pool).await {
+        error!("Database schema does not match. {}", error);
+        std::process::exit(1);
+    }
+
+    if command.as_deref() == Some("migrate") {
+        return Ok(());
+    }
+
+    if command.as_deref() == Some("set-role") {
+        return set_role(&pool, args.next(), args.next()).await;
+    }
+
+    if command.as_deref() == Some("reencrypt") {
+        return match account::reencrypt::reencrypt_emails(&pool, &encryptor).await {
+            Ok(updated) => {
+                info!("Re-encrypted emails of {} users.", updated);
+                Ok(())
+            }
+            Err(error) => {
+                error!("Re-encryption failed. ERROR = {:?}", error);
+                std::process::exit(1);
+            }
+        };
+    }
 
-    let hasher = crypto::Hasher::new(password_hash);
+    let dispatcher = match judge::JudgeDispatcher::start(pool.clone(), config.clone()).await {
+        Ok(dispatcher) => dispatcher,
+        Err(error) => {
+            error!("Unable to start judges. {}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let pool = crypto::init_database_pool().await;
+    let server_address = config.server_address.clone();
+    let config_data = web::Data::from(config);
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(encryptor.clone()))
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(hasher.clone()))
+            .app_data(web::Data::new(dispatcher.clone()))
+            .app_data(config_data.clone())
             .service(web::scope("/account").configure(account::account_handler))
             .service(web::scope("/ticket").configure(ticket::ticket_handler))
+            .service(web::scope("/exercise").configure(exercise::exercise_handler))
     })
     .bind(server_address)?
     .run()
@@ -0,0 +1,72 @@
+CREATE SCHEMA user_data;
+CREATE SCHEMA ticket_data;
+
+CREATE TABLE user_data.users (
+    id BIGINT UNIQUE NOT NULL PRIMARY KEY,
+    username VARCHAR(40) UNIQUE NOT NULL,
+    password_hash BYTEA NOT NULL,
+    user_salt BYTEA NOT NULL,
+    email BYTEA NOT NULL,
+    role VARCHAR NOT NULL DEFAULT 'Student'
+);
+
+CREATE TABLE user_data.sessions (
+    token_hash BYTEA UNIQUE NOT NULL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES user_data.users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX sessions_user_idx ON user_data.sessions (user_id);
+
+CREATE TYPE ticket_data.ticket_status AS ENUM (
+    'Pending', 'Queued', 'Compiling', 'Running', 'Judged', 'CompileError', 'SystemError', 'Cancelled'
+);
+
+CREATE TABLE ticket_data.tickets (
+    id BIGINT UNIQUE NOT NULL PRIMARY KEY,
+    owner_id BIGINT NOT NULL,
+    lang VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    exercise_id BIGINT NOT NULL,
+    ticket_status ticket_data.ticket_status NOT NULL DEFAULT 'Pending',
+    exit_code BIGINT,
+    results_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX tickets_owner_idx ON ticket_data.tickets (owner_id, created_at);
+
+CREATE TABLE ticket_data.judge_queue (
+    ticket_id BIGINT UNIQUE NOT NULL PRIMARY KEY REFERENCES ticket_data.tickets (id),
+    job_state VARCHAR NOT NULL,
+    attempts INTEGER NOT NULL,
+    worker_id BIGINT,
+    lease_expires TIMESTAMPTZ,
+    queued_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX judge_queue_state_idx ON ticket_data.judge_queue (job_state, queued_at);
+
+CREATE TABLE ticket_data.results (
+    id BIGINT UNIQUE NOT NULL PRIMARY KEY,
+    ticket_id BIGINT UNIQUE NOT NULL REFERENCES ticket_data.tickets (id),
+    verdict VARCHAR NOT NULL,
+    compiler_output VARCHAR,
+    timeout_secs BIGINT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE ticket_data.test_results (
+    results_id BIGINT NOT NULL REFERENCES ticket_data.results (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    test_name VARCHAR NOT NULL,
+    verdict VARCHAR NOT NULL,
+    runtime_ms BIGINT,
+    peak_memory_kb BIGINT,
+    exit_code INTEGER,
+    PRIMARY KEY (results_id, position)
+);
@@ -0,0 +1,13 @@
+CREATE SCHEMA exercise_data;
+
+CREATE TABLE exercise_data.exercises (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    title VARCHAR NOT NULL,
+    statement VARCHAR NOT NULL,
+    limits JSONB NOT NULL DEFAULT '{}',
+    languages VARCHAR[] NOT NULL,
+    visible BOOLEAN NOT NULL DEFAULT false,
+    author_id BIGINT REFERENCES user_data.users (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+ALTER TABLE exercise_data.exercises
+    ADD COLUMN tests_hash BYTEA,
+    ADD COLUMN tests_uploaded_at TIMESTAMPTZ;
+
+ALTER TABLE ticket_data.tickets
+    ADD COLUMN tests_hash BYTEA;
@@ -0,0 +1,3 @@
+ALTER TABLE ticket_data.results
+    ADD COLUMN score DOUBLE PRECISION,
+    ADD COLUMN max_score BIGINT;
@@ -0,0 +1,12 @@
+ALTER TABLE ticket_data.test_results
+    ADD COLUMN score DOUBLE PRECISION;
+
+CREATE TABLE ticket_data.subtask_results (
+    results_id BIGINT NOT NULL REFERENCES ticket_data.results (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name VARCHAR NOT NULL,
+    verdict VARCHAR NOT NULL,
+    score DOUBLE PRECISION NOT NULL,
+    max_score BIGINT NOT NULL,
+    PRIMARY KEY (results_id, position)
+);
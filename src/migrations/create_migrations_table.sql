@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS public.schema_migrations (
+    version INTEGER UNIQUE NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+INSERT INTO public.schema_migrations (version, name, applied_at)
+VALUES ($1, $2, now());
\ No newline at end of file
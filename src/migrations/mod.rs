@@ -0,0 +1,391 @@
+//! Versioned schema migrations embedded in the binary.
+//!
+//! Every migration is SQL file `NNNN_name.sql` in this directory, listed in
+//! [MIGRATIONS] in order of versions. Applied migrations are recorded in
+//! `public.schema_migrations`; migrations which are not recorded there are
+//! applied in one transaction, so failed migration leaves the schema untouched.
+//! Applied migrations must never be edited, schema is changed by adding new ones.
+//!
+//! Database created with the old `sql/structure.sql` script has no bookkeeping
+//! table. Its schema is upgraded to the one of the first migration, which is then
+//! recorded as applied, so existing data is kept.
+use deadpool_postgres::{GenericClient, Object, Pool, Transaction};
+
+/// Key of the advisory lock held while migrating, so two servers starting at
+/// the same time do not apply migrations twice.
+const MIGRATION_LOCK_KEY: i64 = 0x61_6c73_6974;
+
+struct Migration {
+    version: i32,
+    name: &'static str,
+    sql: &'static str,
+}
+
+/// All migrations known to this binary, ordered by version.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "exercises",
+        sql: include_str!("0002_exercises.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "test_packages",
+        sql: include_str!("0003_test_packages.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "scores",
+        sql: include_str!("0004_scores.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "subtasks",
+        sql: include_str!("0005_subtasks.sql"),
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    DatabaseError,
+    /// Database was migrated by newer binary, its schema is unknown to this one.
+    DatabaseNewer {
+        database: i32,
+        binary: i32,
+    },
+    /// Migrations are not applied and automatic migration is turned off.
+    Pending {
+        database: i32,
+        binary: i32,
+    },
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::DatabaseError => write!(f, "Database error occured while migrating."),
+            MigrationError::DatabaseNewer { database, binary } => write!(
+                f,
+                "Database schema version {database} is newer than version {binary} supported by this binary."
+            ),
+            MigrationError::Pending { database, binary } => write!(
+                f,
+                "Database schema version {database} is older than version {binary}. Run 'alsit migrate'."
+            ),
+        }
+    }
+}
+
+/// Version of the newest migration known to this binary.
+fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Migrations which have to be applied to database with schema 'version'.
+fn pending_migrations(version: i32) -> Result<Vec<&'static Migration>, MigrationError> {
+    if version > latest_version() {
+        return Err(MigrationError::DatabaseNewer {
+            database: version,
+            binary: latest_version(),
+        });
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+        .collect())
+}
+
+/// Checks that schema 'database' is exactly the one this binary expects.
+fn check_version(database: i32) -> Result<(), MigrationError> {
+    let binary = latest_version();
+
+    if database > binary {
+        Err(MigrationError::DatabaseNewer { database, binary })
+    } else if database < binary {
+        Err(MigrationError::Pending { database, binary })
+    } else {
+        Ok(())
+    }
+}
+
+async fn get_client(db: &Pool) -> Result<Object, MigrationError> {
+    match db.get().await {
+        Ok(client) => Ok(client),
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            Err(MigrationError::DatabaseError)
+        }
+    }
+}
+
+/// Reads version of the newest applied migration, 0 for empty database.
+async fn database_version(client: &impl GenericClient) -> Result<i32, MigrationError> {
+    let table_stmt = include_str!("query_migrations_table.sql");
+    let select_stmt = include_str!("query_version.sql");
+
+    let query = async {
+        let table_exists: bool = client.query_one(table_stmt, &[]).await?.get(0);
+
+        if !table_exists {
+            return Ok(0);
+        }
+
+        client
+            .query_one(select_stmt, &[])
+            .await
+            .map(|row| row.get(0))
+    };
+
+    match query.await {
+        Ok(version) => Ok(version),
+        Err(error) => {
+            error!(
+                "Error occured while reading schema version. ERROR = {:?}",
+                error
+            );
+            Err(MigrationError::DatabaseError)
+        }
+    }
+}
+
+/// Brings schema created by the old `sql/structure.sql` script to the version of
+/// the first migration. Returns version of the schema, 0 if it is not legacy.
+async fn upgrade_legacy_schema(transaction: &Transaction<'_>) -> Result<i32, MigrationError> {
+    let legacy_stmt = include_str!("query_legacy_schema.sql");
+    let upgrade_stmt = include_str!("upgrade_legacy.sql");
+    let insert_stmt = include_str!("insert_migration.sql");
+
+    let is_legacy: bool = match transaction.query_one(legacy_stmt, &[]).await {
+        Ok(row) => row.get(0),
+        Err(error) => {
+            error!(
+                "Error occured while looking for legacy schema. ERROR = {:?}",
+                error
+            );
+            return Err(MigrationError::DatabaseError);
+        }
+    };
+
+    if !is_legacy {
+        return Ok(0);
+    }
+
+    let initial = &MIGRATIONS[0];
+    warn!(
+        "Found schema created without migrations. Upgrading it to migration {} ({}).",
+        initial.version, initial.name
+    );
+
+    let upgrade = async {
+        transaction.batch_execute(upgrade_stmt).await?;
+        transaction
+            .execute(insert_stmt, &[&initial.version, &initial.name])
+            .await
+    };
+
+    match upgrade.await {
+        Ok(_) => Ok(initial.version),
+        Err(error) => {
+            error!(
+                "Error occured while upgrading legacy schema. ERROR = {:?}",
+                error
+            );
+            Err(MigrationError::DatabaseError)
+        }
+    }
+}
+
+/// Applies migrations missing in the database. Returns number of applied ones.
+pub async fn migrate(db: &Pool) -> Result<usize, MigrationError> {
+    let lock_stmt = include_str!("lock_migrations.sql");
+    let create_stmt = include_str!("create_migrations_table.sql");
+    let insert_stmt = include_str!("insert_migration.sql");
+
+    let mut client = get_client(db).await?;
+
+    let transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            error!("Unable to start transaction. ERROR = {:?}", error);
+            return Err(MigrationError::DatabaseError);
+        }
+    };
+
+    let prepare = async {
+        transaction
+            .execute(lock_stmt, &[&MIGRATION_LOCK_KEY])
+            .await?;
+        transaction.batch_execute(create_stmt).await
+    };
+
+    if let Err(error) = prepare.await {
+        error!(
+            "Error occured while preparing migrations table. ERROR = {:?}",
+            error
+        );
+        return Err(MigrationError::DatabaseError);
+    }
+
+    let mut version = database_version(&transaction).await?;
+
+    if version == 0 {
+        version = upgrade_legacy_schema(&transaction).await?;
+    }
+
+    let pending = pending_migrations(version)?;
+
+    for migration in &pending {
+        info!(
+            "Applying migration {} ({}).",
+            migration.version, migration.name
+        );
+
+        let apply = async {
+            transaction.batch_execute(migration.sql).await?;
+            transaction
+                .execute(insert_stmt, &[&migration.version, &migration.name])
+                .await
+        };
+
+        if let Err(error) = apply.await {
+            error!(
+                "Error occured while applying migration {}. ERROR = {:?}",
+                migration.version, error
+            );
+            return Err(MigrationError::DatabaseError);
+        }
+    }
+
+    if let Err(error) = transaction.commit().await {
+        error!(
+            "Error occured while committing migrations. ERROR = {:?}",
+            error
+        );
+        return Err(MigrationError::DatabaseError);
+    }
+
+    Ok(pending.len())
+}
+
+/// Checks that schema of the database is exactly the one this binary expects.
+pub async fn check(db: &Pool) -> Result<(), MigrationError> {
+    let client = get_client(db).await?;
+
+    check_version(database_version(&client).await?)
+}
+
+/// Database for tests of SQL statements, given by connection string in
+/// `ALSIT_TEST_DATABASE`, with all migrations applied. Tests which need it do
+/// nothing when the variable is not set.
+#[cfg(test)]
+pub async fn test_database() -> Option<Pool> {
+    let url = match std::env::var("ALSIT_TEST_DATABASE") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("ALSIT_TEST_DATABASE is not set, skipping test which needs database.");
+            return None;
+        }
+    };
+
+    let config: tokio_postgres::Config = url
+        .parse()
+        .expect("ALSIT_TEST_DATABASE should be a connection string.");
+    let manager = deadpool_postgres::Manager::new(config, tokio_postgres::NoTls);
+    let pool = Pool::builder(manager)
+        .max_size(4)
+        .build()
+        .expect("Test database pool should be created.");
+
+    migrate(&pool)
+        .await
+        .expect("Test database should be migrated.");
+
+    Some(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(migrations: &[&Migration]) -> Vec<i32> {
+        migrations
+            .iter()
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    #[test]
+    fn migrations_are_numbered_from_one_without_gaps() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i32 + 1, "{}", migration.name);
+            assert!(!migration.sql.trim().is_empty(), "{}", migration.name);
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as i32);
+    }
+
+    #[test]
+    fn migration_names_are_unique() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert!(MIGRATIONS[..index]
+                .iter()
+                .all(|other| other.name != migration.name));
+        }
+    }
+
+    #[test]
+    fn only_newer_migrations_are_pending() {
+        let latest = latest_version();
+
+        assert_eq!(
+            versions(&pending_migrations(0).unwrap()),
+            (1..=latest).collect::<Vec<_>>()
+        );
+        assert_eq!(versions(&pending_migrations(latest - 1).unwrap()), [latest]);
+        assert!(pending_migrations(latest).unwrap().is_empty());
+    }
+
+    #[test]
+    fn newer_database_is_not_migrated() {
+        let latest = latest_version();
+
+        assert!(matches!(
+            pending_migrations(latest + 1),
+            Err(MigrationError::DatabaseNewer { database, binary }) if database == latest + 1 && binary == latest
+        ));
+    }
+
+    #[test]
+    fn version_check_detects_pending_and_newer_schema() {
+        let latest = latest_version();
+
+        assert!(check_version(latest).is_ok());
+        assert!(matches!(
+            check_version(latest - 1),
+            Err(MigrationError::Pending { database, binary }) if database == latest - 1 && binary == latest
+        ));
+        assert!(matches!(
+            check_version(0),
+            Err(MigrationError::Pending { database: 0, .. })
+        ));
+        assert!(matches!(
+            check_version(latest + 1),
+            Err(MigrationError::DatabaseNewer { .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn migrated_database_has_latest_version() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+
+        assert_eq!(migrate(&db).await.unwrap(), 0);
+        assert!(check(&db).await.is_ok());
+    }
+}
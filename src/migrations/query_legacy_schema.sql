@@ -0,0 +1 @@
+SELECT to_regclass('user_data.users') IS NOT NULL;
\ No newline at end of file
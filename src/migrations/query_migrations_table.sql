@@ -0,0 +1 @@
+SELECT to_regclass('public.schema_migrations') IS NOT NULL;
\ No newline at end of file
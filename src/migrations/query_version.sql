@@ -0,0 +1,2 @@
+SELECT COALESCE(MAX(version), 0)
+FROM public.schema_migrations;
\ No newline at end of file
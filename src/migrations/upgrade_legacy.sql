@@ -0,0 +1,71 @@
+ALTER TABLE user_data.users
+    ADD COLUMN IF NOT EXISTS role VARCHAR NOT NULL DEFAULT 'Student';
+
+CREATE TABLE IF NOT EXISTS user_data.sessions (
+    token_hash BYTEA UNIQUE NOT NULL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES user_data.users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_idx ON user_data.sessions (user_id);
+
+DO $$
+BEGIN
+    IF to_regtype('ticket_data.ticket_status') IS NULL THEN
+        CREATE TYPE ticket_data.ticket_status AS ENUM (
+            'Pending', 'Queued', 'Compiling', 'Running', 'Judged', 'CompileError', 'SystemError', 'Cancelled'
+        );
+    END IF;
+END
+$$;
+
+-- Old script stored whether the ticket was judged as 'true' or 'false'.
+ALTER TABLE ticket_data.tickets
+    ALTER COLUMN ticket_status DROP DEFAULT,
+    ALTER COLUMN ticket_status TYPE ticket_data.ticket_status USING (
+        CASE ticket_status::text
+            WHEN 'true' THEN 'Judged'
+            WHEN 'false' THEN 'Pending'
+            ELSE ticket_status::text
+        END
+    )::ticket_data.ticket_status,
+    ALTER COLUMN ticket_status SET DEFAULT 'Pending',
+    ADD COLUMN IF NOT EXISTS exit_code BIGINT,
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS started_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS finished_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS tickets_owner_idx ON ticket_data.tickets (owner_id, created_at);
+
+CREATE TABLE IF NOT EXISTS ticket_data.judge_queue (
+    ticket_id BIGINT UNIQUE NOT NULL PRIMARY KEY REFERENCES ticket_data.tickets (id),
+    job_state VARCHAR NOT NULL,
+    attempts INTEGER NOT NULL,
+    worker_id BIGINT,
+    lease_expires TIMESTAMPTZ,
+    queued_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS judge_queue_state_idx ON ticket_data.judge_queue (job_state, queued_at);
+
+CREATE TABLE IF NOT EXISTS ticket_data.results (
+    id BIGINT UNIQUE NOT NULL PRIMARY KEY,
+    ticket_id BIGINT UNIQUE NOT NULL REFERENCES ticket_data.tickets (id),
+    verdict VARCHAR NOT NULL,
+    compiler_output VARCHAR,
+    timeout_secs BIGINT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ticket_data.test_results (
+    results_id BIGINT NOT NULL REFERENCES ticket_data.results (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    test_name VARCHAR NOT NULL,
+    verdict VARCHAR NOT NULL,
+    runtime_ms BIGINT,
+    peak_memory_kb BIGINT,
+    exit_code INTEGER,
+    PRIMARY KEY (results_id, position)
+);
@@ -0,0 +1,2 @@
+DELETE FROM ticket_data.results
+WHERE ticket_id = $1;
\ No newline at end of file
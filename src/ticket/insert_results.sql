@@ -0,0 +1,2 @@
+INSERT INTO ticket_data.results (id, ticket_id, verdict, compiler_output, timeout_secs, score, max_score, created_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7, now());
\ No newline at end of file
@@ -0,0 +1,2 @@
+INSERT INTO ticket_data.subtask_results (results_id, position, name, verdict, score, max_score)
+VALUES ($1, $2, $3, $4, $5, $6);
\ No newline at end of file
@@ -0,0 +1,2 @@
+INSERT INTO ticket_data.test_results (results_id, position, test_name, verdict, runtime_ms, peak_memory_kb, exit_code, score)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8);
\ No newline at end of file
@@ -1,3 +1,3 @@
-INSERT INTO ticket_data.tickets (id, owner_id, lang, content, exercise_id, ticket_status)
-VALUES ($1, $2, $3, $4, $5, $6)
+INSERT INTO ticket_data.tickets (id, owner_id, lang, content, exercise_id, ticket_status, created_at)
+VALUES ($1, $2, $3, $4, $5, $6, now())
 RETURNING id;
\ No newline at end of file
@@ -1,14 +1,24 @@
 use std::{fmt::Display, str::FromStr};
 
-use crate::account::UserId;
+use crate::account::{AuthenticatedUser, Role, RoleGuard, UserId};
+use crate::exercise::{self, ExerciseError};
+use crate::judge::{JudgeDispatcher, JudgeError};
 use actix_web::{web, HttpResponse, Result};
 use deadpool_postgres::{Object, Pool};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Transaction;
+
+pub mod results;
+mod status;
+mod stream;
+mod view;
+
+pub use status::TicketStatus;
 
 pub type TicketId = i64;
 pub type ExerciseId = i64;
 
-#[derive(Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Language {
     C = 0,
     Cpp,
@@ -17,10 +27,10 @@ pub enum Language {
 
 impl Display for Language {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string_description = match &self {
-            &Language::C => "C",
-            &Language::Cpp => "Cpp",
-            &Language::Rust => "Rust",
+        let string_description = match self {
+            Language::C => "C",
+            Language::Cpp => "Cpp",
+            Language::Rust => "Rust",
         };
 
         write!(f, "{string_description}")
@@ -29,14 +39,15 @@ impl Display for Language {
 
 impl Language {
     pub fn extension(&self) -> &'static str {
-        match &self {
-            &Language::C => ".c",
-            &Language::Cpp => ".cpp",
-            &Language::Rust => ".rs",
+        match self {
+            Language::C => ".c",
+            Language::Cpp => ".cpp",
+            Language::Rust => ".rs",
         }
     }
 }
 
+#[derive(Debug)]
 pub enum TicketError {
     DatabaseError,
     WrongTicketId,
@@ -63,10 +74,13 @@ pub struct Ticket {
     content: String,
     exercise_id: ExerciseId,
 
-    judged: bool,
+    status: TicketStatus,
     ticket_id: TicketId,
+}
 
-    results_id: Option<i64>,
+#[derive(Serialize)]
+struct CreatedTicket {
+    id: TicketId,
 }
 
 #[derive(Deserialize)]
@@ -100,15 +114,186 @@ async fn generate_id(client: &Object) -> Result<TicketId, ()> {
     }
 }
 
+/// Function retrieves source code, language and exercise of the ticket.
 pub async fn get_content(
     ticket_id: TicketId,
     db: &Pool,
-) -> Result<(String, Language), TicketError> {
-    todo!()
+) -> Result<(String, Language, ExerciseId), TicketError> {
+    let select_stmt = include_str!("query_content.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let row = match client.query_opt(select_stmt, &[&ticket_id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err(TicketError::WrongTicketId),
+        Err(error) => {
+            error!(
+                "Error occured while querying ticket content. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let content: String = row.get(0);
+    let lang: String = row.get(1);
+    let exercise_id: ExerciseId = row.get(2);
+
+    match Language::from_str(&lang) {
+        Ok(lang) => Ok((content, lang, exercise_id)),
+        Err(_) => {
+            error!(
+                "Ticket has unknown language stored. TicketId = {}, Language = {}",
+                ticket_id, lang
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
 }
 
-pub async fn set_judged(ticket_id: TicketId, db: &Pool) -> Result<(), TicketError> {
-    todo!()
+/// Function marks ticket as judging started, its submission is compiled first.
+pub async fn set_started(ticket_id: TicketId, db: &Pool) -> Result<(), TicketError> {
+    let update_stmt = include_str!("update_started.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    match client.execute(update_stmt, &[&ticket_id]).await {
+        Ok(0) => Err(TicketError::WrongTicketId),
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while marking ticket as started. ERROR = {:?}",
+                error
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
+}
+
+/// Function marks ticket as running tests. Only compiling tickets are changed,
+/// so late call does not overwrite final status.
+pub async fn set_running(ticket_id: TicketId, db: &Pool) -> Result<(), TicketError> {
+    let update_stmt = include_str!("update_running.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    match client.execute(update_stmt, &[&ticket_id]).await {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while marking ticket as running. ERROR = {:?}",
+                error
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
+}
+
+/// Function records hash of the test package the ticket is judged against.
+pub async fn set_tests_hash(
+    ticket_id: TicketId,
+    tests_hash: &[u8],
+    db: &Pool,
+) -> Result<(), TicketError> {
+    let update_stmt = include_str!("update_tests_hash.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    match client
+        .execute(update_stmt, &[&ticket_id, &tests_hash])
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while storing tests hash of ticket. ERROR = {:?}",
+                error
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
+}
+
+/// Function sets final 'status' of the judged ticket and stores exit code of the
+/// testing container (`None` if container was killed or its exit code is unknown).
+pub async fn set_judged(
+    ticket_id: TicketId,
+    status: TicketStatus,
+    exit_code: Option<i64>,
+    db: &Pool,
+) -> Result<(), TicketError> {
+    let update_stmt = include_str!("update_judged.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    match client
+        .execute(update_stmt, &[&ticket_id, &status, &exit_code])
+        .await
+    {
+        Ok(0) => Err(TicketError::WrongTicketId),
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while marking ticket as judged. ERROR = {:?}",
+                error
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
+}
+
+/// Function marks ticket as impossible to judge because of a problem on the server side.
+pub async fn set_system_error(ticket_id: TicketId, db: &Pool) -> Result<(), TicketError> {
+    let update_stmt = include_str!("update_system_error.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    match client.execute(update_stmt, &[&ticket_id]).await {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!(
+                "Error occured while marking ticket as system error. ERROR = {:?}",
+                error
+            );
+            Err(TicketError::DatabaseError)
+        }
+    }
 }
 
 impl Ticket {
@@ -118,23 +303,14 @@ impl Ticket {
             language: form.language,
             content: form.content,
             exercise_id: form.exercise_id,
-            judged: false,
+            // Ticket is inserted together with its judging job.
+            status: TicketStatus::Queued,
             ticket_id,
-            results_id: None,
         }
     }
-
-    pub fn language(&self) -> Language {
-        self.language.clone()
-    }
-
-    pub fn id(&self) -> TicketId {
-        self.ticket_id
-    }
 }
 
-// TODO: Make field in ticket table boolean not ticketstatus!
-async fn insert_ticket(ticket: Ticket, client: &Object) -> HttpResponse {
+async fn insert_ticket(ticket: Ticket, client: &Transaction<'_>) -> HttpResponse {
     let insert_stmt = include_str!("insert_ticket.sql");
 
     let query_result = client
@@ -146,7 +322,7 @@ async fn insert_ticket(ticket: Ticket, client: &Object) -> HttpResponse {
                 &ticket.language.to_string(),
                 &ticket.content,
                 &ticket.exercise_id,
-                &ticket.judged,
+                &ticket.status,
             ],
         )
         .await;
@@ -159,15 +335,43 @@ async fn insert_ticket(ticket: Ticket, client: &Object) -> HttpResponse {
     }
 }
 
-// TODO: UserID
-async fn create_ticket(form: web::Json<TicketForm>, db: web::Data<Pool>) -> HttpResponse {
-    let client = match db.get().await {
+/// Submits ticket for judging. Possible responses:
+///     HTTP 201 => Ticket was queued, its id is in JSON body: `{"id": 123}`.
+///     HTTP 422 => Exercise does not exist, is hidden, has no tests or does not allow the language.
+///     HTTP 503 => Server problem or full judging queue, try again later.
+async fn create_ticket(
+    user: AuthenticatedUser,
+    form: web::Json<TicketForm>,
+    db: web::Data<Pool>,
+    dispatcher: web::Data<JudgeDispatcher>,
+) -> HttpResponse {
+    let mut client = match db.get().await {
         Ok(client) => client,
         Err(_) => {
             return HttpResponse::ServiceUnavailable().finish();
         }
     };
 
+    // Hidden exercises are not revealed, they look as if they did not exist.
+    match exercise::get_submission_rules(&client, form.exercise_id).await {
+        Ok(rules) if rules.visible => {
+            if !rules.languages.contains(&form.language) {
+                return HttpResponse::UnprocessableEntity()
+                    .body("Language is not allowed for this exercise.");
+            }
+
+            if !rules.has_tests {
+                return HttpResponse::UnprocessableEntity().body("Exercise has no tests yet.");
+            }
+        }
+        Ok(_) | Err(ExerciseError::WrongExerciseId) => {
+            return HttpResponse::UnprocessableEntity().body("Exercise does not exist.");
+        }
+        Err(ExerciseError::DatabaseError) => {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    }
+
     let ticket_id = match generate_id(&client).await {
         Ok(id) => id,
         Err(_) => {
@@ -175,13 +379,53 @@ async fn create_ticket(form: web::Json<TicketForm>, db: web::Data<Pool>) -> Http
         }
     };
 
-    let user_id = 0;
+    let ticket = Ticket::create(form.into_inner(), user.user_id, ticket_id).await;
+
+    // Ticket and its judging job are inserted together, so no ticket is left unjudged.
+    let transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            error!("Unable to start transaction. ERROR = {:?}", error);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let response = insert_ticket(ticket, &transaction).await;
+
+    if !response.status().is_success() {
+        return response;
+    }
+
+    match dispatcher.queue_judging(&transaction, ticket_id).await {
+        Ok(()) => {}
+        Err(JudgeError::QueueFull) => {
+            return HttpResponse::ServiceUnavailable()
+                .body("Judging queue is full. Try again later.");
+        }
+        Err(_) => {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    }
+
+    if let Err(error) = transaction.commit().await {
+        error!("Error occured while committing ticket. ERROR = {:?}", error);
+        return HttpResponse::ServiceUnavailable().finish();
+    }
 
-    let ticket = Ticket::create(form.into_inner(), user_id, ticket_id).await;
+    dispatcher.notify_judges(ticket_id);
 
-    insert_ticket(ticket, &client).await
+    HttpResponse::Created().json(CreatedTicket { id: ticket_id })
 }
 
+/// Function is used to handle "/ticket" route, which is available to every
+/// logged in user.
 pub fn ticket_handler(cfg: &mut web::ServiceConfig) {
-    cfg.route("/", web::post().to(create_ticket));
+    cfg.service(
+        web::scope("")
+            .wrap(RoleGuard::new(Role::Student))
+            .route("", web::get().to(view::list_tickets))
+            .route("/", web::post().to(create_ticket))
+            .route("/{ticket_id}", web::get().to(view::get_ticket))
+            .route("/{ticket_id}/events", web::get().to(stream::ticket_events)),
+    );
 }
@@ -0,0 +1,4 @@
+SELECT content, lang, exercise_id
+FROM ticket_data.tickets
+WHERE id = $1
+LIMIT 1;
\ No newline at end of file
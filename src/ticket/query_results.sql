@@ -0,0 +1,4 @@
+SELECT id, verdict, compiler_output, timeout_secs, score, max_score
+FROM ticket_data.results
+WHERE ticket_id = $1
+LIMIT 1;
\ No newline at end of file
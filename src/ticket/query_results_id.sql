@@ -0,0 +1,4 @@
+SELECT id
+FROM ticket_data.results
+WHERE id = $1
+LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,4 @@
+SELECT name, verdict, score, max_score
+FROM ticket_data.subtask_results
+WHERE results_id = $1
+ORDER BY position;
\ No newline at end of file
@@ -0,0 +1,4 @@
+SELECT test_name, verdict, runtime_ms, peak_memory_kb, exit_code, score
+FROM ticket_data.test_results
+WHERE results_id = $1
+ORDER BY position;
\ No newline at end of file
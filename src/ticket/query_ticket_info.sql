@@ -0,0 +1,7 @@
+SELECT id, owner_id, lang, exercise_id, ticket_status, exit_code,
+    EXTRACT(EPOCH FROM created_at)::BIGINT,
+    EXTRACT(EPOCH FROM started_at)::BIGINT,
+    EXTRACT(EPOCH FROM finished_at)::BIGINT,
+    tests_hash
+FROM ticket_data.tickets
+WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,10 @@
+SELECT tickets.id, tickets.owner_id, tickets.lang, tickets.exercise_id, tickets.ticket_status,
+    EXTRACT(EPOCH FROM tickets.created_at)::BIGINT, results.verdict
+FROM ticket_data.tickets AS tickets
+LEFT JOIN ticket_data.results AS results ON results.ticket_id = tickets.id
+WHERE ($1::BIGINT IS NULL OR tickets.owner_id = $1)
+    AND ($2::BIGINT IS NULL OR tickets.exercise_id = $2)
+    AND ($3::VARCHAR IS NULL OR tickets.lang = $3)
+    AND ($4::ticket_data.ticket_status IS NULL OR tickets.ticket_status = $4)
+ORDER BY tickets.created_at DESC, tickets.id DESC
+LIMIT $5 OFFSET $6;
\ No newline at end of file
@@ -0,0 +1,711 @@
+//! Results of judging stored in `ticket_data.results` (one row per ticket),
+//! `ticket_data.test_results` (one row per test case) and
+//! `ticket_data.subtask_results` (one row per group of the manifest).
+//!
+//! Testing container writes its report into `/output/report.json` in the format
+//! described by [TestReport], and output of the compiler into
+//! `/output/compile_stderr.txt`:
+//!
+//! ```json
+//! {
+//!     "compiled": true,
+//!     "tests": [
+//!         {"name": "1", "verdict": "OK", "runtime_ms": 12, "peak_memory_kb": 1024, "exit_code": 0},
+//!         {"name": "2", "verdict": "TLE", "runtime_ms": 2000, "peak_memory_kb": 980, "exit_code": null},
+//!         {"name": "3", "verdict": "WA", "runtime_ms": 15, "peak_memory_kb": 1024, "exit_code": 0, "score": 0.5}
+//!     ]
+//! }
+//! ```
+//!
+//! Optional `score` of the test from 0 to 1 is used by groups with "min" scoring.
+use std::{fmt::Display, str::FromStr};
+
+use deadpool_postgres::{Object, Pool};
+use serde::{Deserialize, Serialize};
+
+use super::{TicketError, TicketId};
+
+pub type ResultsId = i64;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Verdict {
+    #[serde(rename = "OK")]
+    Ok,
+    #[serde(rename = "WA")]
+    WrongAnswer,
+    #[serde(rename = "TLE")]
+    TimeLimitExceeded,
+    #[serde(rename = "MLE")]
+    MemoryLimitExceeded,
+    #[serde(rename = "RE")]
+    RuntimeError,
+    #[serde(rename = "CE")]
+    CompilationError,
+    #[serde(rename = "IE")]
+    InternalError,
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_description = match self {
+            Verdict::Ok => "OK",
+            Verdict::WrongAnswer => "WA",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::MemoryLimitExceeded => "MLE",
+            Verdict::RuntimeError => "RE",
+            Verdict::CompilationError => "CE",
+            Verdict::InternalError => "IE",
+        };
+
+        write!(f, "{string_description}")
+    }
+}
+
+impl FromStr for Verdict {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OK" => Ok(Verdict::Ok),
+            "WA" => Ok(Verdict::WrongAnswer),
+            "TLE" => Ok(Verdict::TimeLimitExceeded),
+            "MLE" => Ok(Verdict::MemoryLimitExceeded),
+            "RE" => Ok(Verdict::RuntimeError),
+            "CE" => Ok(Verdict::CompilationError),
+            "IE" => Ok(Verdict::InternalError),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Outcome of a single test case.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub verdict: Verdict,
+    /// Wall time of the program in milliseconds.
+    pub runtime_ms: Option<i64>,
+    /// Peak memory usage of the program in kilobytes.
+    pub peak_memory_kb: Option<i64>,
+    pub exit_code: Option<i32>,
+    /// Partial score of the test from 0 to 1.
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+impl TestResult {
+    /// Score of the test from 0 to 1. Passed test scores 1 unless it reports
+    /// less, wrong answer scores only what it reports and other verdicts score 0.
+    pub fn score_fraction(&self) -> f64 {
+        let score = match self.verdict {
+            Verdict::Ok => self.score.unwrap_or(1.0),
+            Verdict::WrongAnswer => self.score.unwrap_or(0.0),
+            _ => 0.0,
+        };
+
+        if score.is_nan() {
+            0.0
+        } else {
+            score.clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Outcome of a group of tests (subtask) from the manifest.
+#[derive(Serialize, Clone, Debug)]
+pub struct SubtaskResult {
+    pub name: String,
+    /// First verdict of its tests different from OK.
+    pub verdict: Verdict,
+    pub score: f64,
+    pub max_score: i64,
+}
+
+/// Rounds score to two decimal places, so partial points stay readable.
+pub fn round_score(score: f64) -> f64 {
+    (score * 100.0).round() / 100.0
+}
+
+/// Report written by testing container into `/output/report.json`.
+#[derive(Deserialize, Debug)]
+pub struct TestReport {
+    pub compiled: bool,
+    #[serde(default)]
+    pub tests: Vec<TestResult>,
+}
+
+/// Results of judging a ticket.
+#[derive(Serialize, Clone, Debug)]
+pub struct TicketResults {
+    /// Verdict of the whole submission - first verdict different from OK.
+    pub verdict: Verdict,
+    pub tests: Vec<TestResult>,
+    /// What compiler wrote to stderr, so user can see why compilation failed.
+    pub compiler_output: Option<String>,
+    /// Wall time limit in seconds, set only if container was killed after exceeding it.
+    pub timeout_secs: Option<i64>,
+    /// Sum of scores of subtasks, `None` if the ticket could not be judged.
+    pub score: Option<f64>,
+    /// Points for passing all subtasks.
+    pub max_score: Option<i64>,
+    pub subtasks: Vec<SubtaskResult>,
+}
+
+impl TicketResults {
+    pub fn from_report(report: TestReport) -> TicketResults {
+        if !report.compiled {
+            return TicketResults {
+                verdict: Verdict::CompilationError,
+                tests: report.tests,
+                compiler_output: None,
+                timeout_secs: None,
+                score: None,
+                max_score: None,
+                subtasks: Vec::new(),
+            };
+        }
+
+        let verdict = report
+            .tests
+            .iter()
+            .map(|test| test.verdict)
+            .find(|verdict| *verdict != Verdict::Ok)
+            .unwrap_or(Verdict::Ok);
+
+        TicketResults {
+            verdict,
+            tests: report.tests,
+            compiler_output: None,
+            timeout_secs: None,
+            score: None,
+            max_score: None,
+            subtasks: Vec::new(),
+        }
+    }
+
+    /// Sets scores of subtasks, total score of the ticket is their sum.
+    pub fn set_subtasks(&mut self, subtasks: Vec<SubtaskResult>) {
+        self.score = Some(round_score(
+            subtasks.iter().map(|subtask| subtask.score).sum(),
+        ));
+        self.max_score = Some(subtasks.iter().map(|subtask| subtask.max_score).sum());
+        self.subtasks = subtasks;
+    }
+
+    /// Results of a ticket which was killed after exceeding wall time limit of 'timeout_secs'.
+    pub fn timed_out(timeout_secs: u64) -> TicketResults {
+        TicketResults {
+            verdict: Verdict::TimeLimitExceeded,
+            tests: Vec::new(),
+            compiler_output: None,
+            timeout_secs: Some(timeout_secs as i64),
+            score: None,
+            max_score: None,
+            subtasks: Vec::new(),
+        }
+    }
+
+    /// Results of a ticket which could not be judged because of a problem on the server side.
+    pub fn internal_error() -> TicketResults {
+        TicketResults {
+            verdict: Verdict::InternalError,
+            tests: Vec::new(),
+            compiler_output: None,
+            timeout_secs: None,
+            score: None,
+            max_score: None,
+            subtasks: Vec::new(),
+        }
+    }
+}
+
+async fn generate_results_id(client: &Object) -> Result<ResultsId, TicketError> {
+    use rand::prelude::*;
+    let check_stmt = include_str!("query_results_id.sql");
+
+    loop {
+        // Generator cannot be held across await, as judges run on multiple threads.
+        let possible_id: ResultsId = thread_rng().gen();
+
+        match client.query_opt(check_stmt, &[&possible_id]).await {
+            Err(error) => {
+                error!("Error occured while generating results id. {:?}", error);
+                return Err(TicketError::DatabaseError);
+            }
+            Ok(result) => {
+                if result.is_none() {
+                    return Ok(possible_id);
+                }
+            }
+        }
+    }
+}
+
+/// Function stores results of the ticket and links them with the ticket row.
+/// Results from previous judging of the same ticket are replaced.
+pub async fn store_results(
+    ticket_id: TicketId,
+    results: &TicketResults,
+    db: &Pool,
+) -> Result<ResultsId, TicketError> {
+    let delete_results_stmt = include_str!("delete_results.sql");
+    let insert_results_stmt = include_str!("insert_results.sql");
+    let insert_test_stmt = include_str!("insert_test_result.sql");
+    let insert_subtask_stmt = include_str!("insert_subtask_result.sql");
+    let update_ticket_stmt = include_str!("update_results_id.sql");
+
+    let mut client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let results_id = generate_results_id(&client).await?;
+
+    let transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            error!("Unable to start transaction. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    if let Err(error) = transaction
+        .execute(delete_results_stmt, &[&ticket_id])
+        .await
+    {
+        error!(
+            "Error occured while removing previous results. ERROR = {:?}",
+            error
+        );
+        return Err(TicketError::DatabaseError);
+    }
+
+    if let Err(error) = transaction
+        .execute(
+            insert_results_stmt,
+            &[
+                &results_id,
+                &ticket_id,
+                &results.verdict.to_string(),
+                &results.compiler_output,
+                &results.timeout_secs,
+                &results.score,
+                &results.max_score,
+            ],
+        )
+        .await
+    {
+        // Ticket was deleted while it was judged.
+        if error.code() == Some(&tokio_postgres::error::SqlState::FOREIGN_KEY_VIOLATION) {
+            return Err(TicketError::WrongTicketId);
+        }
+
+        error!("Error occured while inserting results. ERROR = {:?}", error);
+        return Err(TicketError::DatabaseError);
+    }
+
+    for (position, test) in results.tests.iter().enumerate() {
+        let position = position as i32;
+
+        if let Err(error) = transaction
+            .execute(
+                insert_test_stmt,
+                &[
+                    &results_id,
+                    &position,
+                    &test.name,
+                    &test.verdict.to_string(),
+                    &test.runtime_ms,
+                    &test.peak_memory_kb,
+                    &test.exit_code,
+                    &test.score,
+                ],
+            )
+            .await
+        {
+            error!(
+                "Error occured while inserting test result. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    }
+
+    for (position, subtask) in results.subtasks.iter().enumerate() {
+        let position = position as i32;
+
+        if let Err(error) = transaction
+            .execute(
+                insert_subtask_stmt,
+                &[
+                    &results_id,
+                    &position,
+                    &subtask.name,
+                    &subtask.verdict.to_string(),
+                    &subtask.score,
+                    &subtask.max_score,
+                ],
+            )
+            .await
+        {
+            error!(
+                "Error occured while inserting subtask result. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    }
+
+    match transaction
+        .execute(update_ticket_stmt, &[&ticket_id, &results_id])
+        .await
+    {
+        Ok(0) => return Err(TicketError::WrongTicketId),
+        Ok(_) => {}
+        Err(error) => {
+            error!(
+                "Error occured while linking results with ticket. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    }
+
+    if let Err(error) = transaction.commit().await {
+        error!(
+            "Error occured while committing results. ERROR = {:?}",
+            error
+        );
+        return Err(TicketError::DatabaseError);
+    }
+
+    Ok(results_id)
+}
+
+fn parse_verdict(verdict: &str) -> Result<Verdict, TicketError> {
+    Verdict::from_str(verdict).map_err(|_| {
+        error!("Unknown verdict stored in database. Verdict = {}", verdict);
+        TicketError::DatabaseError
+    })
+}
+
+/// Function reads results of the ticket. Returns `None` if ticket was not judged yet.
+pub async fn get_results(
+    ticket_id: TicketId,
+    db: &Pool,
+) -> Result<Option<TicketResults>, TicketError> {
+    let select_results_stmt = include_str!("query_results.sql");
+    let select_tests_stmt = include_str!("query_test_results.sql");
+    let select_subtasks_stmt = include_str!("query_subtask_results.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let row = match client.query_opt(select_results_stmt, &[&ticket_id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return Ok(None),
+        Err(error) => {
+            error!("Error occured while querying results. ERROR = {:?}", error);
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let results_id: ResultsId = row.get(0);
+    let verdict = parse_verdict(row.get(1))?;
+    let compiler_output: Option<String> = row.get(2);
+    let timeout_secs: Option<i64> = row.get(3);
+    let score: Option<f64> = row.get(4);
+    let max_score: Option<i64> = row.get(5);
+
+    let rows = match client.query(select_tests_stmt, &[&results_id]).await {
+        Ok(rows) => rows,
+        Err(error) => {
+            error!(
+                "Error occured while querying test results. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let mut tests = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        tests.push(TestResult {
+            name: row.get(0),
+            verdict: parse_verdict(row.get(1))?,
+            runtime_ms: row.get(2),
+            peak_memory_kb: row.get(3),
+            exit_code: row.get(4),
+            score: row.get(5),
+        });
+    }
+
+    let rows = match client.query(select_subtasks_stmt, &[&results_id]).await {
+        Ok(rows) => rows,
+        Err(error) => {
+            error!(
+                "Error occured while querying subtask results. ERROR = {:?}",
+                error
+            );
+            return Err(TicketError::DatabaseError);
+        }
+    };
+
+    let mut subtasks = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        subtasks.push(SubtaskResult {
+            name: row.get(0),
+            verdict: parse_verdict(row.get(1))?,
+            score: row.get(2),
+            max_score: row.get(3),
+        });
+    }
+
+    Ok(Some(TicketResults {
+        verdict,
+        tests,
+        compiler_output,
+        timeout_secs,
+        score,
+        max_score,
+        subtasks,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_database;
+
+    const VERDICTS: [Verdict; 7] = [
+        Verdict::Ok,
+        Verdict::WrongAnswer,
+        Verdict::TimeLimitExceeded,
+        Verdict::MemoryLimitExceeded,
+        Verdict::RuntimeError,
+        Verdict::CompilationError,
+        Verdict::InternalError,
+    ];
+
+    fn result(verdict: Verdict, score: Option<f64>) -> TestResult {
+        TestResult {
+            name: String::from("test"),
+            verdict,
+            runtime_ms: None,
+            peak_memory_kb: None,
+            exit_code: None,
+            score,
+        }
+    }
+
+    fn subtask(score: f64, max_score: i64) -> SubtaskResult {
+        SubtaskResult {
+            name: String::from("subtask"),
+            verdict: Verdict::Ok,
+            score,
+            max_score,
+        }
+    }
+
+    #[test]
+    fn passed_test_scores_one_unless_it_reports_less() {
+        assert_eq!(result(Verdict::Ok, None).score_fraction(), 1.0);
+        assert_eq!(result(Verdict::Ok, Some(0.5)).score_fraction(), 0.5);
+    }
+
+    #[test]
+    fn wrong_answer_scores_what_it_reports() {
+        assert_eq!(result(Verdict::WrongAnswer, None).score_fraction(), 0.0);
+        assert_eq!(
+            result(Verdict::WrongAnswer, Some(0.25)).score_fraction(),
+            0.25
+        );
+    }
+
+    #[test]
+    fn other_verdicts_score_nothing() {
+        assert_eq!(
+            result(Verdict::TimeLimitExceeded, Some(1.0)).score_fraction(),
+            0.0
+        );
+        assert_eq!(result(Verdict::RuntimeError, None).score_fraction(), 0.0);
+    }
+
+    #[test]
+    fn reported_score_is_clamped() {
+        assert_eq!(result(Verdict::Ok, Some(2.0)).score_fraction(), 1.0);
+        assert_eq!(
+            result(Verdict::WrongAnswer, Some(-1.0)).score_fraction(),
+            0.0
+        );
+        assert_eq!(
+            result(Verdict::WrongAnswer, Some(f64::NAN)).score_fraction(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn ticket_score_is_sum_of_subtasks() {
+        let mut results = TicketResults::internal_error();
+
+        results.set_subtasks(vec![subtask(3.33, 10), subtask(3.33, 10), subtask(3.34, 5)]);
+
+        assert_eq!(results.score, Some(10.0));
+        assert_eq!(results.max_score, Some(25));
+        assert_eq!(results.subtasks.len(), 3);
+    }
+
+    #[test]
+    fn verdict_of_report_is_first_failed_test() {
+        let report = TestReport {
+            compiled: true,
+            tests: vec![
+                result(Verdict::Ok, None),
+                result(Verdict::TimeLimitExceeded, None),
+                result(Verdict::WrongAnswer, None),
+            ],
+        };
+
+        assert_eq!(
+            TicketResults::from_report(report).verdict,
+            Verdict::TimeLimitExceeded
+        );
+
+        let report = TestReport {
+            compiled: false,
+            tests: Vec::new(),
+        };
+
+        assert_eq!(
+            TicketResults::from_report(report).verdict,
+            Verdict::CompilationError
+        );
+    }
+
+    #[test]
+    fn verdicts_have_the_same_short_names_everywhere() {
+        for verdict in VERDICTS {
+            let name = verdict.to_string();
+
+            assert_eq!(name.parse(), Ok(verdict));
+            assert_eq!(
+                serde_json::to_string(&verdict).unwrap(),
+                format!("\"{name}\"")
+            );
+        }
+        assert_eq!("Ok".parse::<Verdict>(), Err(()));
+    }
+
+    #[test]
+    fn documented_report_is_parsed() {
+        let report: TestReport = serde_json::from_str(
+            r#"{
+                "compiled": true,
+                "tests": [
+                    {"name": "1", "verdict": "OK", "runtime_ms": 12, "peak_memory_kb": 1024, "exit_code": 0},
+                    {"name": "2", "verdict": "TLE", "runtime_ms": 2000, "peak_memory_kb": 980, "exit_code": null},
+                    {"name": "3", "verdict": "WA", "runtime_ms": 15, "peak_memory_kb": 1024, "exit_code": 0, "score": 0.5}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(report.compiled);
+        assert_eq!(report.tests[0].score, None);
+        assert_eq!(report.tests[1].exit_code, None);
+        assert_eq!(report.tests[1].verdict, Verdict::TimeLimitExceeded);
+        assert_eq!(report.tests[2].score, Some(0.5));
+    }
+
+    #[test]
+    fn report_of_failed_compilation_needs_no_tests() {
+        let report: TestReport = serde_json::from_str(r#"{"compiled": false}"#).unwrap();
+
+        assert!(report.tests.is_empty());
+        assert!(serde_json::from_str::<TestReport>(r#"{"tests": []}"#).is_err());
+    }
+
+    #[test]
+    fn report_with_all_tests_passed_is_ok() {
+        let report = TestReport {
+            compiled: true,
+            tests: vec![result(Verdict::Ok, None), result(Verdict::Ok, Some(1.0))],
+        };
+
+        let results = TicketResults::from_report(report);
+
+        assert_eq!(results.verdict, Verdict::Ok);
+        assert_eq!(results.tests.len(), 2);
+        assert_eq!(results.score, None);
+    }
+
+    #[actix_web::test]
+    async fn stored_results_replace_previous_ones() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let ticket_id = 800_000_000 + TicketId::from(rand::random::<u16>());
+        db.get()
+            .await
+            .unwrap()
+            .execute(
+                "INSERT INTO ticket_data.tickets (id, owner_id, lang, content, exercise_id)
+                VALUES ($1, 1, 'C', '', 1) ON CONFLICT DO NOTHING",
+                &[&ticket_id],
+            )
+            .await
+            .unwrap();
+
+        let mut results = TicketResults::from_report(TestReport {
+            compiled: true,
+            tests: vec![
+                result(Verdict::Ok, None),
+                result(Verdict::WrongAnswer, Some(0.5)),
+            ],
+        });
+        results.compiler_output = Some(String::from("warning"));
+        results.set_subtasks(vec![subtask(2.5, 5)]);
+
+        store_results(ticket_id, &TicketResults::internal_error(), &db)
+            .await
+            .unwrap();
+        store_results(ticket_id, &results, &db).await.unwrap();
+        let stored = get_results(ticket_id, &db).await.unwrap().unwrap();
+
+        assert_eq!(stored.verdict, Verdict::WrongAnswer);
+        assert_eq!(stored.compiler_output.as_deref(), Some("warning"));
+        assert_eq!((stored.score, stored.max_score), (Some(2.5), Some(5)));
+        assert_eq!(
+            stored
+                .tests
+                .iter()
+                .map(|test| (test.verdict, test.score))
+                .collect::<Vec<_>>(),
+            [(Verdict::Ok, None), (Verdict::WrongAnswer, Some(0.5))]
+        );
+        assert_eq!(stored.subtasks[0].score, 2.5);
+    }
+
+    #[actix_web::test]
+    async fn results_of_unknown_ticket_are_not_stored() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+
+        assert!(matches!(
+            store_results(-1, &TicketResults::internal_error(), &db).await,
+            Err(TicketError::WrongTicketId)
+        ));
+        assert!(get_results(-1, &db).await.unwrap().is_none());
+    }
+}
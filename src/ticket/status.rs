@@ -0,0 +1,195 @@
+use std::error::Error;
+use std::{fmt::Display, str::FromStr};
+
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// Name of the Postgres enum type of `ticket_data.tickets.ticket_status`.
+const POSTGRES_TYPE_NAME: &str = "ticket_status";
+
+/// Stage of the ticket's life, stored as `ticket_data.ticket_status` enum.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TicketStatus {
+    /// Ticket is stored, but it is not in the judging queue.
+    Pending,
+    /// Ticket waits in the judging queue.
+    Queued,
+    /// Judge took the ticket and compiles the submission.
+    Compiling,
+    /// Submission is being run against tests.
+    Running,
+    /// Results are stored.
+    Judged,
+    /// Submission did not compile, compiler output is stored with results.
+    CompileError,
+    /// Ticket cannot be judged because of a problem on the server side.
+    SystemError,
+    /// Ticket was withdrawn before it was judged.
+    Cancelled,
+}
+
+impl Display for TicketStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_description = match self {
+            TicketStatus::Pending => "Pending",
+            TicketStatus::Queued => "Queued",
+            TicketStatus::Compiling => "Compiling",
+            TicketStatus::Running => "Running",
+            TicketStatus::Judged => "Judged",
+            TicketStatus::CompileError => "CompileError",
+            TicketStatus::SystemError => "SystemError",
+            TicketStatus::Cancelled => "Cancelled",
+        };
+
+        write!(f, "{string_description}")
+    }
+}
+
+impl FromStr for TicketStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Pending" => Ok(TicketStatus::Pending),
+            "Queued" => Ok(TicketStatus::Queued),
+            "Compiling" => Ok(TicketStatus::Compiling),
+            "Running" => Ok(TicketStatus::Running),
+            "Judged" => Ok(TicketStatus::Judged),
+            "CompileError" => Ok(TicketStatus::CompileError),
+            "SystemError" => Ok(TicketStatus::SystemError),
+            "Cancelled" => Ok(TicketStatus::Cancelled),
+            _ => Err(()),
+        }
+    }
+}
+
+// Postgres sends and receives enum values as their labels.
+impl ToSql for TicketStatus {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.extend_from_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == POSTGRES_TYPE_NAME
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for TicketStatus {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let label = std::str::from_utf8(raw)?;
+
+        TicketStatus::from_str(label).map_err(|_| format!("Unknown ticket status: {label}").into())
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == POSTGRES_TYPE_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Kind;
+
+    use super::*;
+    use crate::migrations::test_database;
+
+    const STATUSES: [TicketStatus; 8] = [
+        TicketStatus::Pending,
+        TicketStatus::Queued,
+        TicketStatus::Compiling,
+        TicketStatus::Running,
+        TicketStatus::Judged,
+        TicketStatus::CompileError,
+        TicketStatus::SystemError,
+        TicketStatus::Cancelled,
+    ];
+
+    fn status_type(name: &str) -> Type {
+        let labels = STATUSES.iter().map(ToString::to_string).collect();
+
+        Type::new(
+            String::from(name),
+            0,
+            Kind::Enum(labels),
+            String::from("ticket_data"),
+        )
+    }
+
+    #[test]
+    fn status_is_parsed_from_its_label() {
+        for status in STATUSES {
+            assert_eq!(status.to_string().parse(), Ok(status));
+        }
+        assert_eq!("judged".parse::<TicketStatus>(), Err(()));
+    }
+
+    #[test]
+    fn status_round_trips_through_sql() {
+        let ty = status_type(POSTGRES_TYPE_NAME);
+
+        for status in STATUSES {
+            let mut raw = BytesMut::new();
+            status.to_sql_checked(&ty, &mut raw).unwrap();
+
+            assert_eq!(&raw[..], status.to_string().as_bytes());
+            assert_eq!(TicketStatus::from_sql(&ty, &raw).unwrap(), status);
+        }
+    }
+
+    #[test]
+    fn only_ticket_status_type_is_accepted() {
+        assert!(<TicketStatus as ToSql>::accepts(&status_type(
+            POSTGRES_TYPE_NAME
+        )));
+        assert!(!<TicketStatus as ToSql>::accepts(&Type::VARCHAR));
+        assert!(!<TicketStatus as FromSql>::accepts(&status_type(
+            "job_state"
+        )));
+        assert!(TicketStatus::Judged
+            .to_sql_checked(&Type::TEXT, &mut BytesMut::new())
+            .is_err());
+    }
+
+    #[test]
+    fn unknown_label_is_error() {
+        let ty = status_type(POSTGRES_TYPE_NAME);
+
+        assert!(TicketStatus::from_sql(&ty, b"Finished").is_err());
+        assert!(TicketStatus::from_sql(&ty, &[0xff]).is_err());
+    }
+
+    #[actix_web::test]
+    async fn every_status_is_label_of_database_enum() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let client = db.get().await.unwrap();
+
+        for status in STATUSES {
+            let row = client
+                .query_one("SELECT $1::ticket_data.ticket_status", &[&status])
+                .await
+                .unwrap();
+
+            assert_eq!(row.get::<_, TicketStatus>(0), status);
+        }
+
+        let labels: i64 = client
+            .query_one(
+                "SELECT COUNT(*) FROM unnest(enum_range(NULL::ticket_data.ticket_status))",
+                &[],
+            )
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(labels, STATUSES.len() as i64);
+    }
+}
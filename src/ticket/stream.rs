@@ -0,0 +1,323 @@
+//! Live status of a ticket sent as Server-Sent Events. Every event is JSON
+//! [TicketEvent] in `data` field; stream ends after the final event, so clients
+//! do not have to poll for results.
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use futures::stream;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use super::results;
+use super::{TicketId, TicketStatus};
+use crate::account::{AuthenticatedUser, UserId};
+use crate::judge::events::{TicketEvent, TicketProgress};
+use crate::judge::JudgeDispatcher;
+
+/// Comment sent when nothing happened for a while, so proxies do not close
+/// the connection.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+struct EventStream {
+    receiver: Receiver<TicketEvent>,
+    ticket_id: TicketId,
+    db: Pool,
+    /// Event built from the stored status, sent before the live ones.
+    initial: Option<TicketProgress>,
+    finished: bool,
+}
+
+impl EventStream {
+    /// Waits for the next chunk of the response, `None` ends the stream.
+    async fn next_chunk(&mut self) -> Option<web::Bytes> {
+        if self.finished {
+            return None;
+        }
+
+        if let Some(progress) = self.initial.take() {
+            return Some(self.event_chunk(progress));
+        }
+
+        loop {
+            match tokio::time::timeout(KEEP_ALIVE_INTERVAL, self.receiver.recv()).await {
+                Ok(Ok(event)) if event.ticket_id == self.ticket_id => {
+                    return Some(self.event_chunk(event.progress));
+                }
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    warn!(
+                        "Ticket event subscriber fell behind. TicketId = {}, Skipped = {}",
+                        self.ticket_id, skipped
+                    );
+
+                    // Final event could be among the skipped ones.
+                    match self.stored_final_progress().await {
+                        Ok(Some(progress)) => return Some(self.event_chunk(progress)),
+                        Ok(None) => continue,
+                        // Client can reconnect and read the status again.
+                        Err(()) => return None,
+                    }
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => return Some(web::Bytes::from_static(b": keep-alive\n\n")),
+            }
+        }
+    }
+
+    /// Final event built from the stored status, `None` if ticket is not finished.
+    async fn stored_final_progress(&self) -> Result<Option<TicketProgress>, ()> {
+        let status = match query_status(self.ticket_id, &self.db).await? {
+            Some(status) => status,
+            None => {
+                warn!(
+                    "Ticket was deleted while its events were streamed. TicketId = {}",
+                    self.ticket_id
+                );
+                return Err(());
+            }
+        };
+
+        match stored_progress(self.ticket_id, status, &self.db).await? {
+            Some(progress) if progress.is_final() => Ok(Some(progress)),
+            _ => Ok(None),
+        }
+    }
+
+    fn event_chunk(&mut self, progress: TicketProgress) -> web::Bytes {
+        self.finished = progress.is_final();
+
+        let event = TicketEvent {
+            ticket_id: self.ticket_id,
+            progress,
+        };
+
+        match serde_json::to_string(&event) {
+            Ok(data) => web::Bytes::from(format!("data: {data}\n\n")),
+            Err(error) => {
+                error!(
+                    "Error occured while serializing ticket event. ERROR = {:?}",
+                    error
+                );
+                web::Bytes::from_static(b": invalid event\n\n")
+            }
+        }
+    }
+}
+
+/// Reads stored status of the ticket, `None` if the ticket does not exist.
+async fn query_status(ticket_id: TicketId, db: &Pool) -> Result<Option<TicketStatus>, ()> {
+    let select_stmt = include_str!("query_ticket_info.sql");
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Unable to get database connection. ERROR = {:?}", error);
+            return Err(());
+        }
+    };
+
+    match client.query_opt(select_stmt, &[&ticket_id]).await {
+        Ok(row) => Ok(row.map(|row| row.get(4))),
+        Err(error) => {
+            error!("Error occured while querying ticket. ERROR = {:?}", error);
+            Err(())
+        }
+    }
+}
+
+/// Translates status stored in the database into the event sent first. Running
+/// ticket has no such event, number of the current test is known only from
+/// the next live one.
+async fn stored_progress(
+    ticket_id: TicketId,
+    status: TicketStatus,
+    db: &Pool,
+) -> Result<Option<TicketProgress>, ()> {
+    let progress = match status {
+        TicketStatus::Pending | TicketStatus::Queued => TicketProgress::Queued,
+        TicketStatus::Compiling => TicketProgress::Compiling,
+        TicketStatus::Running => return Ok(None),
+        TicketStatus::Judged | TicketStatus::CompileError => {
+            match results::get_results(ticket_id, db).await {
+                Ok(Some(results)) => TicketProgress::Finished {
+                    verdict: results.verdict,
+                },
+                Ok(None) => {
+                    error!("Judged ticket has no results. TicketId = {}", ticket_id);
+                    return Err(());
+                }
+                Err(_) => return Err(()),
+            }
+        }
+        TicketStatus::SystemError => TicketProgress::SystemError,
+        TicketStatus::Cancelled => TicketProgress::Cancelled,
+    };
+
+    Ok(Some(progress))
+}
+
+/// Streams status changes of the ticket as `text/event-stream`. The first event
+/// is the current status; stream ends after the ticket is judged or fails.
+/// Possible responses:
+///     HTTP 200 => Stream of events.
+///     HTTP 404 => Ticket does not exist or belongs to another user.
+///     HTTP 503 => Server problem, try again later.
+pub async fn ticket_events(
+    user: AuthenticatedUser,
+    ticket_id: web::Path<TicketId>,
+    db: web::Data<Pool>,
+    dispatcher: web::Data<JudgeDispatcher>,
+) -> HttpResponse {
+    let select_stmt = include_str!("query_ticket_info.sql");
+    let ticket_id = ticket_id.into_inner();
+
+    // Subscription comes before reading the status, so no transition is missed.
+    let receiver = dispatcher.subscribe();
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let row = match client.query_opt(select_stmt, &[&ticket_id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(error) => {
+            error!("Error occured while querying ticket. ERROR = {:?}", error);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let owner_id: UserId = row.get(1);
+
+    if !user.can_see_tickets_of(owner_id) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let status: TicketStatus = row.get(4);
+    drop(client);
+
+    let initial = match stored_progress(ticket_id, status, &db).await {
+        Ok(progress) => progress,
+        Err(()) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let events = EventStream {
+        receiver,
+        ticket_id,
+        db: db.get_ref().clone(),
+        initial,
+        finished: false,
+    };
+
+    let body = stream::unfold(events, |mut events| async move {
+        let chunk = events.next_chunk().await?;
+        Some((Ok::<_, actix_web::Error>(chunk), events))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::events::TicketEvents;
+    use crate::ticket::results::Verdict;
+
+    /// Pool of a database which refuses connections.
+    fn unavailable_db() -> Pool {
+        let mut config = deadpool_postgres::Config::new();
+        config.host = Some(String::from("127.0.0.1"));
+        config.port = Some(1);
+        config.dbname = Some(String::from("alsit_test"));
+
+        config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap()
+    }
+
+    fn event_stream(events: &TicketEvents, initial: Option<TicketProgress>) -> EventStream {
+        EventStream {
+            receiver: events.subscribe(),
+            ticket_id: 7,
+            db: unavailable_db(),
+            initial,
+            finished: false,
+        }
+    }
+
+    fn data(progress: TicketProgress) -> web::Bytes {
+        let event = TicketEvent {
+            ticket_id: 7,
+            progress,
+        };
+
+        web::Bytes::from(format!(
+            "data: {}\n\n",
+            serde_json::to_string(&event).unwrap()
+        ))
+    }
+
+    #[tokio::test]
+    async fn stream_ends_after_final_event() {
+        let events = TicketEvents::new();
+        let mut stream = event_stream(&events, Some(TicketProgress::Queued));
+
+        events.publish(8, TicketProgress::Compiling);
+        events.publish(7, TicketProgress::Compiling);
+        events.publish(
+            7,
+            TicketProgress::Finished {
+                verdict: Verdict::Ok,
+            },
+        );
+
+        assert_eq!(
+            stream.next_chunk().await,
+            Some(data(TicketProgress::Queued))
+        );
+        assert_eq!(
+            stream.next_chunk().await,
+            Some(data(TicketProgress::Compiling))
+        );
+        assert_eq!(
+            stream.next_chunk().await,
+            Some(data(TicketProgress::Finished {
+                verdict: Verdict::Ok
+            }))
+        );
+        assert_eq!(stream.next_chunk().await, None);
+    }
+
+    #[tokio::test]
+    async fn finished_initial_event_ends_stream() {
+        let events = TicketEvents::new();
+        let mut stream = event_stream(&events, Some(TicketProgress::SystemError));
+
+        assert_eq!(
+            stream.next_chunk().await,
+            Some(data(TicketProgress::SystemError))
+        );
+        assert_eq!(stream.next_chunk().await, None);
+    }
+
+    #[tokio::test]
+    async fn lagged_stream_ends_when_status_cannot_be_read() {
+        let events = TicketEvents::new();
+        let mut stream = event_stream(&events, None);
+
+        // Final event is among the ones the subscriber missed.
+        for _ in 0..2000 {
+            events.publish(7, TicketProgress::Running { test: 1, total: 2 });
+        }
+        events.publish(7, TicketProgress::Cancelled);
+
+        assert_eq!(stream.next_chunk().await, None);
+    }
+}
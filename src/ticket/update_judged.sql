@@ -0,0 +1,3 @@
+UPDATE ticket_data.tickets
+SET ticket_status = $2, exit_code = $3, finished_at = now()
+WHERE id = $1;
\ No newline at end of file
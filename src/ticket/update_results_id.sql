@@ -0,0 +1,3 @@
+UPDATE ticket_data.tickets
+SET results_id = $2
+WHERE id = $1;
\ No newline at end of file
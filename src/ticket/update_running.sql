@@ -0,0 +1,3 @@
+UPDATE ticket_data.tickets
+SET ticket_status = 'Running'
+WHERE id = $1 AND ticket_status = 'Compiling';
\ No newline at end of file
@@ -0,0 +1,3 @@
+UPDATE ticket_data.tickets
+SET ticket_status = 'Compiling', started_at = now()
+WHERE id = $1;
\ No newline at end of file
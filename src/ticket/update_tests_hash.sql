@@ -0,0 +1,3 @@
+UPDATE ticket_data.tickets
+SET tests_hash = $2
+WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,411 @@
+//! Endpoints showing tickets to their owners. Teachers and admins see tickets of
+//! every user. Timestamps are in seconds since Unix epoch.
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+
+use super::results::{self, TicketResults, Verdict};
+use super::{ExerciseId, Language, TicketId, TicketStatus};
+use crate::account::{AuthenticatedUser, UserId};
+use crate::crypto::encode_hex;
+
+/// Number of tickets returned by list endpoint when limit is not given.
+const DEFAULT_PAGE_SIZE: i64 = 20;
+/// Maximal number of tickets returned by list endpoint at once.
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Serialize)]
+struct TicketDetails {
+    id: TicketId,
+    owner_id: UserId,
+    language: Language,
+    exercise_id: ExerciseId,
+    status: TicketStatus,
+    exit_code: Option<i64>,
+    created_at: i64,
+    /// When judge took the ticket, `None` while it is queued.
+    started_at: Option<i64>,
+    /// When status became final, `None` until then.
+    finished_at: Option<i64>,
+    /// Hex encoded SHA-256 of the test package used for judging.
+    tests_hash: Option<String>,
+    results: Option<TicketResults>,
+}
+
+#[derive(Serialize)]
+struct TicketSummary {
+    id: TicketId,
+    owner_id: UserId,
+    language: Language,
+    exercise_id: ExerciseId,
+    status: TicketStatus,
+    created_at: i64,
+    verdict: Option<Verdict>,
+}
+
+#[derive(Serialize)]
+struct TicketPage {
+    tickets: Vec<TicketSummary>,
+    limit: i64,
+    offset: i64,
+    /// Whether there are more tickets after this page.
+    has_more: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TicketFilter {
+    /// Owner of listed tickets, only teachers and admins may list tickets of others.
+    owner_id: Option<UserId>,
+    exercise_id: Option<ExerciseId>,
+    language: Option<Language>,
+    status: Option<TicketStatus>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+fn parse_language(lang: &str, ticket_id: TicketId) -> Option<Language> {
+    match Language::from_str(lang) {
+        Ok(lang) => Some(lang),
+        Err(_) => {
+            error!(
+                "Ticket has unknown language stored. TicketId = {}, Language = {}",
+                ticket_id, lang
+            );
+            None
+        }
+    }
+}
+
+/// Returns status, language, exercise, timestamps and results of the ticket.
+/// Possible responses:
+///     HTTP 200 => Ticket in JSON body.
+///     HTTP 404 => Ticket does not exist or belongs to another user.
+///     HTTP 503 => Server problem, try again later.
+pub async fn get_ticket(
+    user: AuthenticatedUser,
+    ticket_id: web::Path<TicketId>,
+    db: web::Data<Pool>,
+) -> HttpResponse {
+    let select_stmt = include_str!("query_ticket_info.sql");
+    let ticket_id = ticket_id.into_inner();
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let row = match client.query_opt(select_stmt, &[&ticket_id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(error) => {
+            error!("Error occured while querying ticket. ERROR = {:?}", error);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let owner_id: UserId = row.get(1);
+
+    // Existence of tickets of other users is not revealed.
+    if !user.can_see_tickets_of(owner_id) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let language = match parse_language(row.get(2), ticket_id) {
+        Some(language) => language,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let results = match results::get_results(ticket_id, &db).await {
+        Ok(results) => results,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    HttpResponse::Ok().json(TicketDetails {
+        id: row.get(0),
+        owner_id,
+        language,
+        exercise_id: row.get(3),
+        status: row.get(4),
+        exit_code: row.get(5),
+        created_at: row.get(6),
+        started_at: row.get(7),
+        finished_at: row.get(8),
+        tests_hash: row
+            .get::<_, Option<Vec<u8>>>(9)
+            .map(|hash| encode_hex(&hash)),
+        results,
+    })
+}
+
+/// Lists tickets of the caller, newest first, filtered by exercise, language and
+/// status given in query string. Possible responses:
+///     HTTP 200 => Page of tickets in JSON body.
+///     HTTP 403 => Student asked for tickets of another user.
+///     HTTP 422 => Limit or offset is out of range.
+///     HTTP 503 => Server problem, try again later.
+pub async fn list_tickets(
+    user: AuthenticatedUser,
+    filter: web::Query<TicketFilter>,
+    db: web::Data<Pool>,
+) -> HttpResponse {
+    let select_stmt = include_str!("query_tickets.sql");
+
+    let owner_id = match filter.owner_id {
+        Some(owner_id) if !user.can_see_tickets_of(owner_id) => {
+            return HttpResponse::Forbidden().finish();
+        }
+        Some(owner_id) => Some(owner_id),
+        None => Some(user.user_id),
+    };
+
+    let limit = filter.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = filter.offset.unwrap_or(0);
+
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) || offset < 0 {
+        return HttpResponse::UnprocessableEntity().body(format!(
+            "Limit has to be between 1 and {MAX_PAGE_SIZE} and offset must not be negative."
+        ));
+    }
+
+    let client = match db.get().await {
+        Ok(client) => client,
+        Err(_) => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let language = filter.language.as_ref().map(Language::to_string);
+
+    // One ticket more is fetched to find out whether there is next page.
+    let rows = match client
+        .query(
+            select_stmt,
+            &[
+                &owner_id,
+                &filter.exercise_id,
+                &language,
+                &filter.status,
+                &(limit + 1),
+                &offset,
+            ],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(error) => {
+            error!("Error occured while querying tickets. ERROR = {:?}", error);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let has_more = rows.len() as i64 > limit;
+    let mut tickets = Vec::with_capacity(rows.len());
+
+    for row in rows.iter().take(limit as usize) {
+        let ticket_id: TicketId = row.get(0);
+
+        let language = match parse_language(row.get(2), ticket_id) {
+            Some(language) => language,
+            None => return HttpResponse::ServiceUnavailable().finish(),
+        };
+
+        let verdict: Option<String> = row.get(6);
+
+        tickets.push(TicketSummary {
+            id: ticket_id,
+            owner_id: row.get(1),
+            language,
+            exercise_id: row.get(3),
+            status: row.get(4),
+            created_at: row.get(5),
+            verdict: verdict.and_then(|verdict| Verdict::from_str(&verdict).ok()),
+        });
+    }
+
+    HttpResponse::Ok().json(TicketPage {
+        tickets,
+        limit,
+        offset,
+        has_more,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::{Service as _, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{App, HttpMessage};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::account::Role;
+    use crate::migrations::test_database;
+
+    /// Sends GET request to 'uri' as 'user', handlers get 'db' as the pool.
+    async fn get(db: Pool, user: AuthenticatedUser, uri: &str) -> ServiceResponse {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db))
+                .route("/ticket", web::get().to(list_tickets))
+                .route("/ticket/{ticket_id}", web::get().to(get_ticket))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(user);
+                    srv.call(req)
+                }),
+        )
+        .await;
+
+        test::call_service(&app, TestRequest::get().uri(uri).to_request()).await
+    }
+
+    fn user(user_id: UserId, role: Role) -> AuthenticatedUser {
+        AuthenticatedUser { user_id, role }
+    }
+
+    /// Pool which is never connected to, for requests rejected before querying.
+    fn unavailable_db() -> Pool {
+        let mut config = deadpool_postgres::Config::new();
+        config.host = Some(String::from("127.0.0.1"));
+        config.port = Some(1);
+        config.dbname = Some(String::from("alsit_test"));
+
+        config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap()
+    }
+
+    /// Inserts tickets of new owner, the last one is the newest. Returns the owner
+    /// and ids of the tickets.
+    async fn insert_tickets(
+        db: &Pool,
+        tickets: &[(&str, TicketStatus)],
+    ) -> (UserId, Vec<TicketId>) {
+        let owner_id = UserId::from(rand::random::<u32>()) + 1;
+        let client = db.get().await.unwrap();
+        let mut ids = Vec::new();
+
+        for (index, (lang, status)) in tickets.iter().enumerate() {
+            // Above ids used by tests of other modules.
+            let ticket_id = 1_000_000_000_000 + owner_id * 100 + index as TicketId;
+            client
+                .execute(
+                    "INSERT INTO ticket_data.tickets (id, owner_id, lang, content, exercise_id, ticket_status, created_at)
+                    VALUES ($1, $2, $3, '', 1, $4, now() + make_interval(secs => $5))",
+                    &[&ticket_id, &owner_id, lang, status, &(index as f64)],
+                )
+                .await
+                .unwrap();
+            ids.push(ticket_id);
+        }
+
+        (owner_id, ids)
+    }
+
+    #[actix_web::test]
+    async fn students_cannot_list_tickets_of_others() {
+        let response = get(
+            unavailable_db(),
+            user(1, Role::Student),
+            "/ticket?owner_id=2",
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn page_out_of_range_is_rejected() {
+        for query in ["limit=0", "limit=101", "offset=-1"] {
+            let response = get(
+                unavailable_db(),
+                user(1, Role::Student),
+                &format!("/ticket?{query}"),
+            )
+            .await;
+
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[actix_web::test]
+    async fn ticket_is_shown_to_owner_and_teachers_only() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let (owner_id, tickets) = insert_tickets(&db, &[("Rust", TicketStatus::Queued)]).await;
+        let uri = format!("/ticket/{}", tickets[0]);
+
+        let response = get(db.clone(), user(owner_id, Role::Student), &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ticket: Value = test::read_body_json(response).await;
+
+        assert_eq!(ticket["owner_id"], owner_id);
+        assert_eq!(ticket["language"], "Rust");
+        assert_eq!(ticket["status"], "Queued");
+        assert_eq!(ticket["started_at"], Value::Null);
+        assert_eq!(ticket["results"], Value::Null);
+
+        let other = get(db.clone(), user(owner_id + 1, Role::Student), &uri).await;
+        let teacher = get(db.clone(), user(owner_id + 1, Role::Teacher), &uri).await;
+        let missing = get(db, user(owner_id, Role::Student), "/ticket/-1").await;
+
+        assert_eq!(other.status(), StatusCode::NOT_FOUND);
+        assert_eq!(teacher.status(), StatusCode::OK);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn tickets_are_listed_newest_first_in_pages() {
+        let Some(db) = test_database().await else {
+            return;
+        };
+        let (owner_id, tickets) = insert_tickets(
+            &db,
+            &[
+                ("C", TicketStatus::Judged),
+                ("Cpp", TicketStatus::Queued),
+                ("C", TicketStatus::Queued),
+            ],
+        )
+        .await;
+        let student = user(owner_id, Role::Student);
+
+        let ids = |page: &Value| -> Vec<i64> {
+            page["tickets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|ticket| ticket["id"].as_i64().unwrap())
+                .collect()
+        };
+
+        let first: Value =
+            test::read_body_json(get(db.clone(), student, "/ticket?limit=2").await).await;
+        let second: Value =
+            test::read_body_json(get(db.clone(), student, "/ticket?limit=2&offset=2").await).await;
+        let filtered: Value = test::read_body_json(
+            get(db.clone(), student, "/ticket?language=C&status=Queued").await,
+        )
+        .await;
+        let teacher: Value = test::read_body_json(
+            get(
+                db,
+                user(owner_id + 1, Role::Teacher),
+                &format!("/ticket?owner_id={owner_id}"),
+            )
+            .await,
+        )
+        .await;
+
+        assert_eq!(ids(&first), [tickets[2], tickets[1]]);
+        assert_eq!(first["has_more"], true);
+        assert_eq!(ids(&second), [tickets[0]]);
+        assert_eq!(second["has_more"], false);
+        assert_eq!(ids(&filtered), [tickets[2]]);
+        assert_eq!(ids(&teacher).len(), 3);
+    }
+}